@@ -1,204 +1,1253 @@
-use bitcoin::consensus::Encodable;
-use bitcoin::hashes::{sha256d, Hash};
-use bitcoin::opcodes::all::{OP_PUSHBYTES_36, OP_RETURN};
-use bitcoin::{Address, Network, OutPoint, ScriptBuf, Txid, WScriptHash};
-use clap::Parser;
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::{deserialize, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, Denomination, Network, OutPoint, Psbt, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
-use covenants_gadgets::test::SimulationInstruction;
+use covenants_gadgets::test::{simulation_test, SimulationInstruction};
 use covenants_gadgets::{get_script_pub_key, get_tx, CovenantInput, CovenantProgram, DUST_AMOUNT};
-use fibonacci_example_non_table::fiat_shamir::compute_fiat_shamir_hints;
-use fibonacci_example_non_table::fold::compute_fold_hints;
-use fibonacci_example_non_table::prepare::compute_prepare_hints;
-use fibonacci_example_non_table::quotients::compute_quotients_hints;
+use fibonacci_example_non_table::broadcast::{broadcast_tx, BroadcastOptions};
+use fibonacci_example_non_table::chain::{caboose_script_pub_key, validate_chain};
+use fibonacci_example_non_table::error::ChainError;
+use fibonacci_example_non_table::policy::{check_standardness, DEFAULT_MIN_RELAY_FEE_RATE};
+use fibonacci_example_non_table::proof::FibonacciProofFile;
+use fibonacci_example_non_table::rpc::RpcClient;
+use fibonacci_example_non_table::serialization::{SerializedVerifierHints, VerifierHintsFile};
+use fibonacci_example_non_table::split::follow::{FibonacciSplitFollower, FollowerStatus};
+use fibonacci_example_non_table::split::limits::{check_stack_usage, stack_usage_of_steps};
 use fibonacci_example_non_table::split::{
-    FibonacciSplitInput, FibonacciSplitProgram, FibonacciSplitState,
+    DefaultFibonacciSplitParameters, FibonacciSplitInput, FibonacciSplitParameters,
+    FibonacciSplitProgram, FibonacciSplitState,
+};
+use fibonacci_example_non_table::verifier::verify;
+use fibonacci_example_non_table::{
+    channel_for_claim, fibonacci_claim, verify_with_hints, FibonacciClaimMode, VerifierHints,
 };
-use fibonacci_example_non_table::FIB_LOG_SIZE;
+use std::collections::HashMap;
 use std::io::Write;
-use stwo_prover::core::channel::Sha256Channel;
-use stwo_prover::core::fields::m31::{BaseField, M31};
-use stwo_prover::core::fields::IntoSlice;
-use stwo_prover::core::pcs::PcsConfig;
-use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
-use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleChannel;
+use std::time::Duration;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::prover::StarkProof;
+use stwo_prover::core::vcs::sha256_merkle::{Sha256MerkleChannel, Sha256MerkleHasher};
 use stwo_prover::examples::fibonacci::Fibonacci;
 use stwo_prover::trace_generation::commit_and_prove;
 
+type Parameters = DefaultFibonacciSplitParameters;
+type Program = FibonacciSplitProgram<Parameters>;
+
+/// The virtual size of the funding transaction, with an input from the wallet and the outputs of
+/// the program and the caboose, rounded up.
+const FUNDING_TX_VSIZE: u64 = 200;
+
+/// The extra virtual size to fund, as the witnesses of different proofs differ by a few bytes.
+const FEE_MARGIN_VSIZE: u64 = 200;
+
+/// The balance of the program when estimating the fees, which do not depend on it.
+const ESTIMATION_BALANCE: u64 = 100000000;
+
+/// The amount to fund the demo with is rounded up to a multiple of this, i.e., 0.0001 BTC.
+const FUNDING_AMOUNT_UNIT: u64 = 10000;
+
+/// The network that the demo runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum DemoNetwork {
+    Regtest,
+    Testnet,
+    Signet,
+    Mainnet,
+}
+
+impl DemoNetwork {
+    /// The network of the addresses.
+    fn network(&self) -> Network {
+        match self {
+            DemoNetwork::Regtest => Network::Regtest,
+            DemoNetwork::Testnet => Network::Testnet,
+            DemoNetwork::Signet => Network::Signet,
+            DemoNetwork::Mainnet => Network::Bitcoin,
+        }
+    }
+
+    /// The name of the network, used in the names of the output files.
+    fn name(&self) -> &'static str {
+        match self {
+            DemoNetwork::Regtest => "regtest",
+            DemoNetwork::Testnet => "testnet",
+            DemoNetwork::Signet => "signet",
+            DemoNetwork::Mainnet => "mainnet",
+        }
+    }
+
+    /// The bitcoin-cli command for the network.
+    fn cli(&self) -> &'static str {
+        match self {
+            DemoNetwork::Regtest => "./bitcoin-cli -chain=regtest",
+            DemoNetwork::Testnet => "./bitcoin-cli -chain=test",
+            DemoNetwork::Signet => "./bitcoin-cli -chain=signet",
+            DemoNetwork::Mainnet => "./bitcoin-cli -chain=main",
+        }
+    }
+
+    /// The default RPC port of Bitcoin Core for the network.
+    fn rpc_port(&self) -> u16 {
+        match self {
+            DemoNetwork::Regtest => 18443,
+            DemoNetwork::Testnet => 18332,
+            DemoNetwork::Signet => 38332,
+            DemoNetwork::Mainnet => 8332,
+        }
+    }
+
+    /// The name of the file that stores the funding PSBT.
+    fn psbt_file_name(&self) -> String {
+        format!("funding-{}.psbt", self.name())
+    }
+
+    /// The name of the file that stores the signed funding transaction.
+    fn funding_tx_file_name(&self) -> String {
+        format!("funding-{}.txt", self.name())
+    }
+
+    /// The name of the file that stores the transaction of the given step, starting from 1.
+    fn tx_file_name(&self, step: usize) -> String {
+        format!("tx-{}-{}.txt", self.name(), step)
+    }
+}
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Funding Txid
-    #[arg(short, long)]
-    funding_txid: Option<String>,
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "The program of the demo has no operator: its reset key is the unspendable key of \
+                  BIP-341, so it can never be reset, and an unfinished verification can only be \
+                  resumed."
+)]
+struct Cli {
+    /// Network of the addresses and the transactions
+    #[arg(short, long, global = true, value_enum, default_value_t = DemoNetwork::Signet)]
+    network: DemoNetwork,
 
-    /// Txid
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Debug)]
+struct ProofArgs {
+    /// Path to a proof file, which is generated if not provided
     #[arg(short, long)]
-    initial_program_txid: Option<String>,
+    proof: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct HintsArgs {
+    #[command(flatten)]
+    proof: ProofArgs,
+
+    /// Path to a hints file written by the hints command, which is used instead of computing the
+    /// hints from a proof
+    #[arg(long, conflicts_with = "proof")]
+    hints: Option<String>,
+}
+
+impl HintsArgs {
+    /// Read the hints from the hints file if provided, or compute them from the proof otherwise.
+    fn load(self) -> (Option<M31>, SerializedVerifierHints) {
+        match self.hints {
+            Some(hints_path) => read_hints(&hints_path),
+            None => load_hints(self.proof.proof),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct FeeArgs {
+    /// Fee rate in sat/vB
+    #[arg(long, default_value_t = 7)]
+    fee_rate: u64,
+}
+
+#[derive(Args, Debug)]
+struct TipArgs {
+    /// Path to a file with the last transaction of the program in the chain in hex, from which
+    /// the state is decoded instead of given by the other options
+    #[arg(long, conflicts_with_all = ["txid", "spent_outpoint", "pc", "randomizer", "balance"])]
+    last_tx: Option<String>,
+
+    /// Txid of the last transaction of the program in the chain
+    #[arg(short, long, required_unless_present = "last_tx")]
+    txid: Option<Txid>,
+
+    /// The outpoint spent by the first input of that transaction, as txid:vout
+    #[arg(short, long, required_unless_present = "last_tx")]
+    spent_outpoint: Option<OutPoint>,
+
+    /// The program counter of the state after that transaction
+    #[arg(long, required_unless_present = "last_tx")]
+    pc: Option<usize>,
+
+    /// The randomizer of the caboose of that transaction
+    #[arg(short, long, required_unless_present = "last_tx")]
+    randomizer: Option<u32>,
+
+    /// The balance of the program after that transaction, in sats
+    #[arg(short, long, required_unless_present = "last_tx")]
+    balance: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+struct RpcArgs {
+    /// Address of the RPC of the node, as host:port, which defaults to the local node
+    #[arg(long)]
+    rpc: Option<String>,
+
+    /// User of the RPC of the node
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+
+    /// Password of the RPC of the node
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+}
+
+impl RpcArgs {
+    /// Create a client of the node.
+    fn client(&self, network: DemoNetwork) -> RpcClient {
+        let address = self
+            .rpc
+            .clone()
+            .unwrap_or_else(|| format!("127.0.0.1:{}", network.rpc_port()));
+        let auth = self.rpc_user.as_deref().zip(self.rpc_password.as_deref());
+        RpcClient::new(address, auth)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the addresses of the program and the caboose, and how to fund them
+    Address {
+        #[command(flatten)]
+        proof: ProofArgs,
+
+        #[command(flatten)]
+        fee: FeeArgs,
+    },
+    /// Generate a proof and write it to a file
+    Prove {
+        /// Path to the proof file
+        #[arg(short, long, default_value = "proof.json")]
+        output: String,
+    },
+    /// Compute the hints of a proof and write them to a file
+    Hints {
+        #[command(flatten)]
+        proof: ProofArgs,
+
+        /// Path to the hints file
+        #[arg(short, long, default_value = "hints.json")]
+        output: String,
+
+        /// Write the hints in the compact binary format instead of JSON
+        #[arg(long)]
+        binary: bool,
+    },
+    /// Create a PSBT that funds the program and the caboose from a UTXO, for the wallet to sign
+    FundingPsbt {
+        /// The UTXO prepared for the demo, as txid:vout
+        #[arg(short, long)]
+        utxo: OutPoint,
+
+        /// The value of the UTXO in BTC, as reported by gettxout
+        #[arg(long, value_parser = parse_btc)]
+        utxo_value: Amount,
+
+        /// The script pubkey of the UTXO in hex, as reported by gettxout, which must be a segwit
+        /// output
+        #[arg(long, value_parser = parse_script)]
+        utxo_script: ScriptBuf,
+
+        #[command(flatten)]
+        proof: ProofArgs,
+
+        #[command(flatten)]
+        fee: FeeArgs,
+    },
+    /// Build the transactions of all the steps and write them to files
+    BuildTxs {
+        /// Path to a file with the signed funding transaction in hex, whose first output is the
+        /// initial balance of the program
+        #[arg(short, long)]
+        funding_tx: String,
+
+        #[command(flatten)]
+        hints: HintsArgs,
+
+        #[command(flatten)]
+        fee: FeeArgs,
+    },
+    /// Build the remaining transactions, continuing the chain from a transaction of the program,
+    /// whose state is recomputed from the proof or the hints file
+    Resume {
+        #[command(flatten)]
+        tip: TipArgs,
+
+        #[command(flatten)]
+        hints: HintsArgs,
+
+        #[command(flatten)]
+        fee: FeeArgs,
+    },
+    /// Decode a transaction of the program and print the states that it moves between
+    Inspect {
+        /// Path to a file with the transaction in hex
+        tx: String,
+    },
+    /// Execute the transactions of the chain against the outputs that they spend, as a node would
+    Validate {
+        /// Path to a file with the funding transaction in hex
+        #[arg(short, long)]
+        funding_tx: String,
+
+        /// Paths to files with the transactions of the steps in hex, in order, which are the
+        /// files written by build-txs if not provided
+        txs: Vec<String>,
+    },
+    /// Send the transactions of the chain in order through the RPC of a node, each after the
+    /// previous one is accepted
+    Broadcast {
+        /// Paths to files with the transactions of the steps in hex, in order, which are the
+        /// files written by build-txs if not provided
+        txs: Vec<String>,
+
+        /// Confirmations to wait for before sending the next transaction, where 0 waits for the
+        /// mempool acceptance only
+        #[arg(short, long, default_value_t = 0)]
+        confirmations: u64,
+
+        /// Seconds between two polls of the node, and before a retry
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+
+        /// Retries of a transaction when the node is unreachable or warming up
+        #[arg(long, default_value_t = 5)]
+        max_retries: usize,
+
+        /// Seconds to wait for the confirmations of a transaction before giving up
+        #[arg(long)]
+        timeout: Option<u64>,
+
+        #[command(flatten)]
+        rpc: RpcArgs,
+    },
+    /// Follow the program on the chain through the RPC of a node, and report its progress
+    Follow {
+        /// The program output of the funding transaction, as txid:vout
+        #[arg(short, long)]
+        utxo: OutPoint,
+
+        /// Seconds between two polls of the node
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+
+        /// Seconds without a new transaction after which the program is reported as stalled
+        #[arg(long, default_value_t = 600)]
+        stall_timeout: u64,
+
+        #[command(flatten)]
+        rpc: RpcArgs,
+    },
+    /// Run all the steps of the program locally, checking that every script succeeds
+    Simulate {
+        #[command(flatten)]
+        hints: HintsArgs,
+
+        #[command(flatten)]
+        fee: FeeArgs,
+    },
 }
 
 fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
 
-    let amount = (2800u64 + 474845 + 325136 + 591542 * 8 + 10000) / 7 + 330 * 10;
-    let amount_display = (((amount as f64) / 1000.0 / 1000.0 / 100.0) * 10000.0).ceil() / 10000.0;
-    let amount = (amount_display * 100.0 * 1000.0 * 1000.0) as u64;
-    let rest = amount - 330 - 400;
+    match cli.command {
+        Command::Address { proof, fee } => print_address(cli.network, proof.proof, fee.fee_rate),
+        Command::Prove { output } => write_proof(&output),
+        Command::Hints {
+            proof,
+            output,
+            binary,
+        } => write_hints(proof.proof, &output, binary),
+        Command::FundingPsbt {
+            utxo: outpoint,
+            utxo_value,
+            utxo_script,
+            proof,
+            fee,
+        } => {
+            let utxo = TxOut {
+                value: utxo_value,
+                script_pubkey: utxo_script,
+            };
+            write_funding_psbt(cli.network, outpoint, utxo, proof.proof, fee.fee_rate)
+        }
+        Command::BuildTxs {
+            funding_tx,
+            hints,
+            fee,
+        } => write_initial_txs(cli.network, &funding_tx, hints, fee.fee_rate),
+        Command::Resume { tip, hints, fee } => {
+            write_resumed_txs(cli.network, tip, hints, fee.fee_rate)
+        }
+        Command::Inspect { tx } => inspect_tx(cli.network, &tx),
+        Command::Validate { funding_tx, txs } => validate(cli.network, &funding_tx, txs),
+        Command::Broadcast {
+            txs,
+            confirmations,
+            interval,
+            max_retries,
+            timeout,
+            rpc,
+        } => {
+            let options = BroadcastOptions {
+                confirmations,
+                poll_interval: Duration::from_secs(interval),
+                max_retries,
+                timeout: timeout.map(Duration::from_secs),
+            };
+            broadcast(cli.network, txs, &options, rpc)
+        }
+        Command::Follow {
+            utxo,
+            interval,
+            stall_timeout,
+            rpc,
+        } => follow(cli.network, utxo, interval, stall_timeout, rpc),
+        Command::Simulate { hints, fee } => simulate(hints, fee.fee_rate),
+    }
+}
 
-    if args.funding_txid.is_none() || args.initial_program_txid.is_none() {
-        let script_pub_key = get_script_pub_key::<FibonacciSplitProgram>();
+/// Compute the amount to fund the demo with, rounded up to 0.0001 BTC, and the balance of the
+/// program after the funding transaction.
+fn funding_amounts(
+    fee_rate: u64,
+    public_claim: Option<M31>,
+    hints: &SerializedVerifierHints,
+) -> (u64, u64) {
+    // the fees do not depend on the txids and the balances, so they are computed from a chain
+    // of transactions with placeholders
+    let (_, fees) = build_txs(
+        &ChainTip::initial(Txid::all_zeros(), OutPoint::null(), ESTIMATION_BALANCE),
+        Program::num_steps(),
+        fee_rate,
+        public_claim,
+        hints,
+    );
+    let num_steps = Program::num_steps() as u64;
+    let funding_fee = FUNDING_TX_VSIZE * fee_rate;
 
-        let program_address =
-            Address::from_script(script_pub_key.as_script(), Network::Signet).unwrap();
+    let amount = fees.iter().sum::<u64>()
+        + DUST_AMOUNT * (num_steps + 1)
+        + funding_fee
+        + FEE_MARGIN_VSIZE * fee_rate;
+    let amount = amount.div_ceil(FUNDING_AMOUNT_UNIT) * FUNDING_AMOUNT_UNIT;
+    let rest = amount - DUST_AMOUNT - funding_fee;
 
-        let init_state = FibonacciSplitProgram::new();
-        let hash = FibonacciSplitProgram::get_hash(&init_state);
+    (amount, rest)
+}
 
-        let mut bytes = vec![OP_RETURN.to_u8(), OP_PUSHBYTES_36.to_u8()];
-        bytes.extend_from_slice(&hash);
-        bytes.extend_from_slice(&12u32.to_le_bytes());
+/// Format an amount in sats as BTC, with all the eight decimals.
+fn format_btc(sats: u64) -> String {
+    format!("{}.{:08}", sats / 100000000, sats % 100000000)
+}
 
-        let caboose_address = Address::from_script(
-            ScriptBuf::new_p2wsh(&WScriptHash::hash(&bytes)).as_script(),
-            Network::Signet,
-        )
+fn print_address(network: DemoNetwork, proof_path: Option<String>, fee_rate: u64) {
+    let (public_claim, hints) = load_hints(proof_path);
+    let (amount, rest) = funding_amounts(fee_rate, public_claim, &hints);
+
+    let script_pub_key = get_script_pub_key::<Program>();
+
+    let program_address =
+        Address::from_script(script_pub_key.as_script(), network.network()).unwrap();
+
+    let caboose_address = Address::from_script(
+        caboose_script_pub_key::<Program>(&Program::new(), 12).as_script(),
+        network.network(),
+    )
+    .unwrap();
+
+    let amount_display = format_btc(amount);
+
+    println!("================= INSTRUCTIONS =================");
+    println!(
+        "The program starts with {} BTC and the caboose with {} BTC.",
+        format_btc(rest),
+        format_btc(DUST_AMOUNT)
+    );
+    println!("Program address: {}", program_address);
+    println!("Caboose address: {}", caboose_address);
+    println!();
+    println!("To start with, prepare {} BTC into a UTXO transaction which would be used to fund the transaction fee for the entire demo.",
+             amount_display
+    );
+    println!(
+        "> {} sendtoaddress {} {}",
+        network.cli(),
+        "\"[a segwit address in the local wallet]\""
+            .on_bright_green()
+            .black(),
+        amount_display
+    );
+    println!();
+    println!("Look up the value and the script pubkey of the UTXO");
+    println!(
+        "> {} gettxout {} {}",
+        network.cli(),
+        "[txid]".on_bright_green().black(),
+        "[vout]".on_bright_green().black()
+    );
+    println!();
+    println!("According to that transaction, create a PSBT that sends the UTXO to the program and the state caboose with the initial state");
+    println!(
+        "> cargo run --bin demo -- --network {} funding-psbt -u {} --utxo-value {} --utxo-script {}",
+        network.name(),
+        "[txid]:[vout]".on_bright_green().black(),
+        "[value]".on_bright_green().black(),
+        "[scriptPubKey.hex]".on_bright_green().black()
+    );
+    println!("================================================");
+}
+
+fn write_proof(output: &str) {
+    let claim = match Parameters::CLAIM_MODE {
+        FibonacciClaimMode::Constant(claim) => claim,
+        FibonacciClaimMode::PublicInput => fibonacci_claim(Parameters::LOG_SIZE),
+    };
+
+    let proof = generate_proof(claim);
+    FibonacciProofFile::new(Parameters::LOG_SIZE, claim, Parameters::CONFIG, &proof)
+        .write(output)
         .unwrap();
 
-        let amount_display =
-            (((amount as f64) / 1000.0 / 1000.0 / 100.0) * 10000.0).ceil() / 10000.0;
-        let rest_display = (rest as f64) / 1000.0 / 1000.0 / 100.0;
+    println!(
+        "The proof of the claim {} is written to {}.",
+        claim.0, output
+    );
+}
 
-        println!("================= INSTRUCTIONS =================");
-        println!("To start with, prepare {} BTC into a UTXO transaction which would be used to fund the transaction fee for the entire demo.",
-                 amount_display
+fn write_hints(proof_path: Option<String>, output: &str, binary: bool) {
+    let (claim, hints) = compute_hints(proof_path);
+    VerifierHintsFile::new(Parameters::LOG_SIZE, claim, Parameters::CONFIG, hints)
+        .write(output, binary)
+        .unwrap();
+
+    println!("The hints are written to {}.", output);
+}
+
+fn write_funding_psbt(
+    network: DemoNetwork,
+    outpoint: OutPoint,
+    utxo: TxOut,
+    proof_path: Option<String>,
+    fee_rate: u64,
+) {
+    let (public_claim, hints) = load_hints(proof_path);
+    let (amount, rest) = funding_amounts(fee_rate, public_claim, &hints);
+
+    // any difference would go to the fees, or would not cover them
+    if utxo.value.to_sat() != amount {
+        eprintln!(
+            "The UTXO holds {} BTC, but the demo must be funded with exactly {} BTC.",
+            format_btc(utxo.value.to_sat()),
+            format_btc(amount)
         );
-        println!(
-            "> ./bitcoin-cli --datadir=signet sendtoaddress {} {}",
-            "\"[an address in the local wallet]\""
-                .on_bright_green()
-                .black(),
-            amount_display
+        std::process::exit(1);
+    }
+    // the transactions of the program are built from the signed funding transaction, and a
+    // third party could change the txid of a non-segwit input after it is sent
+    if !utxo.script_pubkey.is_witness_program() {
+        eprintln!("The UTXO must be a segwit output, so that the funding txid cannot change.");
+        std::process::exit(1);
+    }
+
+    let psbt = funding_psbt(outpoint, utxo, rest);
+
+    std::fs::write(network.psbt_file_name(), psbt.to_string()).unwrap();
+
+    println!("================= INSTRUCTIONS =================");
+    println!(
+        "The PSBT has been stored in {}. It spends {} BTC from {} and leaves {} sats in fees.",
+        network.psbt_file_name(),
+        format_btc(amount),
+        outpoint,
+        amount - rest - DUST_AMOUNT
+    );
+    println!();
+    println!("Sign it with the wallet that owns the UTXO");
+    println!(
+        "> {} walletprocesspsbt $(< {})",
+        network.cli(),
+        network.psbt_file_name()
+    );
+    println!();
+    println!(
+        "Extract the signed transaction, store its hex in {}, and send it",
+        network.funding_tx_file_name()
+    );
+    println!(
+        "> {} finalizepsbt {}",
+        network.cli(),
+        "[signed psbt]".on_bright_green().black()
+    );
+    println!(
+        "> {} sendrawtransaction $(< {})",
+        network.cli(),
+        network.funding_tx_file_name()
+    );
+    println!();
+    println!("Build the transactions of the program, which continue from the funding transaction");
+    println!(
+        "> cargo run --bin demo -- --network {} build-txs -f {}",
+        network.name(),
+        network.funding_tx_file_name()
+    );
+    println!("================================================");
+}
+
+fn write_initial_txs(
+    network: DemoNetwork,
+    funding_tx_path: &str,
+    hints_args: HintsArgs,
+    fee_rate: u64,
+) {
+    let (public_claim, hints) = hints_args.load();
+
+    // the chain starts from what the funding transaction actually pays, which may differ from
+    // the amounts computed when the PSBT is created, e.g., at another fee rate
+    let funding_tx = read_tx(funding_tx_path);
+    let Some(program_output) = funding_tx
+        .output
+        .first()
+        .filter(|txout| txout.script_pubkey == get_script_pub_key::<Program>())
+    else {
+        eprintln!(
+            "{} does not pay the program in its first output.",
+            funding_tx_path
         );
-        println!();
-        println!("According to that transaction, send BTC from that UTXO to the program and the state caboose with the initial state");
-        println!("> ./bitcoin-cli --datadir=signet createrawtransaction \"[{{\\\"txid\\\":\\\"{}\\\", \\\"vout\\\": {}}}]\" \"[{{\\\"{}\\\":{:.8}}}, {{\\\"{}\\\":0.0000033}}]\"",
-            "[txid]".on_bright_green().black(),
-            "[vout]".on_bright_green().black(), program_address, rest_display,
-            caboose_address
+        std::process::exit(1);
+    };
+    let tip = ChainTip::initial(
+        funding_tx.compute_txid(),
+        funding_tx.input[0].previous_output,
+        program_output.value.to_sat(),
+    );
+    if funding_tx.output.get(1) != tip.prevouts().get(&OutPoint::new(tip.txid, 1)) {
+        eprintln!(
+            "{} does not pay the caboose with the initial state in its second output.",
+            funding_tx_path
         );
-        println!();
-        println!("Then, sign the transaction");
-        println!(
-            "> ./bitcoin-cli --datadir=signet signrawtransactionwithwallet {}",
-            "[tx hex]".on_bright_green().black()
+        std::process::exit(1);
+    }
+
+    // the fees do not depend on the balance, so whether it covers them is known upfront
+    let (_, fees) = build_txs(
+        &ChainTip::initial(Txid::all_zeros(), OutPoint::null(), ESTIMATION_BALANCE),
+        Program::num_steps(),
+        fee_rate,
+        public_claim,
+        &hints,
+    );
+    let required = fees.iter().sum::<u64>() + DUST_AMOUNT * Program::num_steps() as u64;
+    if tip.balance < required {
+        eprintln!(
+            "The program is funded with {} sats, but the transactions need {} sats at {} sat/vB.",
+            tip.balance, required, fee_rate
         );
-        println!();
-        println!("Send the signed transaction");
+        std::process::exit(1);
+    }
+
+    write_txs(network, &tip, fee_rate, public_claim, &hints);
+}
+
+fn write_resumed_txs(network: DemoNetwork, tip: TipArgs, hints_args: HintsArgs, fee_rate: u64) {
+    let (public_claim, hints) = hints_args.load();
+
+    let tip = match tip.last_tx {
+        Some(path) => decode_tip(&read_tx(&path), public_claim, &hints),
+        None => ChainTip {
+            txid: tip.txid.unwrap(),
+            spent_outpoint: tip.spent_outpoint.unwrap(),
+            state: state_at(tip.pc.unwrap(), public_claim, &hints),
+            randomizer: tip.randomizer.unwrap(),
+            balance: tip.balance.unwrap(),
+        },
+    };
+
+    // the caboose is the only place where the state is on chain, so it must match
+    let caboose_address = Address::from_script(
+        caboose_script_pub_key::<Program>(&tip.state, tip.randomizer).as_script(),
+        network.network(),
+    )
+    .unwrap();
+    println!(
+        "Resuming from {}, whose caboose should be paid to {}.",
+        tip.txid, caboose_address
+    );
+
+    write_txs(network, &tip, fee_rate, public_claim, &hints);
+}
+
+/// Build the transactions from the tip of the chain until the program is back to its initial
+/// state, and write them to files named after the program counters of their steps.
+fn write_txs(
+    network: DemoNetwork,
+    tip: &ChainTip,
+    fee_rate: u64,
+    public_claim: Option<M31>,
+    hints: &SerializedVerifierHints,
+) {
+    let first_step = tip.state.pc;
+    let (txs, fees) = build_txs(
+        tip,
+        Program::num_steps() - first_step,
+        fee_rate,
+        public_claim,
+        hints,
+    );
+
+    // check that the transactions would be relayed, where the first one spends the outputs of
+    // the tip, i.e., the program and the caboose
+    let mut prevouts = tip.prevouts();
+    for (i, tx) in txs.iter().enumerate() {
+        for violation in check_standardness(tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE) {
+            println!(
+                "{} {} would not be relayed: {}",
+                "warning:".yellow(),
+                network.tx_file_name(first_step + i + 1),
+                violation
+            );
+        }
+
+        let txid = tx.compute_txid();
+        for (vout, txout) in tx.output.iter().enumerate() {
+            prevouts.insert(
+                OutPoint {
+                    txid,
+                    vout: vout as u32,
+                },
+                txout.clone(),
+            );
+        }
+    }
+
+    for (i, tx) in txs.iter().enumerate() {
+        let mut bytes = vec![];
+        tx.consensus_encode(&mut bytes).unwrap();
+
+        let mut fs = std::fs::File::create(network.tx_file_name(first_step + i + 1)).unwrap();
+        fs.write_all(hex::encode(bytes).as_bytes()).unwrap();
+    }
+
+    println!("================= INSTRUCTIONS =================");
+    println!(
+        "{} transactions have been generated and stored in the current directory.",
+        txs.len()
+    );
+    println!(
+        "They pay {} sats in fees in total, at {} sat/vB.",
+        fees.iter().sum::<u64>(),
+        fee_rate
+    );
+    println!("Send them in order");
+    for i in 0..txs.len() {
         println!(
-            "> ./bitcoin-cli --datadir=signet sendrawtransaction {}",
-            "[signed tx hex]".on_bright_green().black()
+            "> {} sendrawtransaction $(< {})",
+            network.cli(),
+            network.tx_file_name(first_step + i + 1)
         );
-        println!();
-        println!("Call this tool again with the funding txid and initial program id");
-        println!("> cargo run -f ");
-        println!("================================================");
+    }
+    println!();
+    println!("Or let the demo send them through the RPC of the node");
+    println!(
+        "> cargo run --bin demo -- --network {} broadcast {}",
+        network.name(),
+        (0..txs.len())
+            .map(|i| network.tx_file_name(first_step + i + 1))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+}
+
+fn inspect_tx(network: DemoNetwork, path: &str) {
+    let tx = read_tx(path);
+
+    println!("txid: {}", tx.compute_txid());
+    println!("vsize: {} vB", tx.vsize());
+    for (i, txin) in tx.input.iter().enumerate() {
+        println!("input {}: {}", i, txin.previous_output);
+    }
+    for (i, txout) in tx.output.iter().enumerate() {
+        match Address::from_script(txout.script_pubkey.as_script(), network.network()) {
+            Ok(address) => println!("output {}: {} to {}", i, txout.value, address),
+            Err(_) => println!("output {}: {} to {}", i, txout.value, txout.script_pubkey),
+        }
+    }
+
+    let decoded = match Program::decode_tx(&tx) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            eprintln!("The transaction is not a step of the program: {}.", err);
+            std::process::exit(1);
+        }
+    };
+    let step = if decoded.program_index == Program::reset_index() {
+        "reset".to_string()
     } else {
-        let fib = Fibonacci::new(FIB_LOG_SIZE, M31::reduce(443693538));
-        let config = PcsConfig::default();
-
-        let trace = fib.get_trace();
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let proof =
-            commit_and_prove::<_, Sha256MerkleChannel>(&fib.air, channel, vec![trace], config)
-                .unwrap();
-
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let (fiat_shamir_output, fiat_shamir_hints) =
-            compute_fiat_shamir_hints(proof.clone(), channel, &fib.air).unwrap();
-
-        let (prepare_output, prepare_hints) =
-            compute_prepare_hints(&fiat_shamir_output, &proof).unwrap();
-
-        let (quotients_output, per_query_quotients_hints) =
-            compute_quotients_hints(&fiat_shamir_output, &prepare_output);
-
-        let per_query_fold_hints = compute_fold_hints(
-            &proof.commitment_scheme_proof.fri_proof,
-            &fiat_shamir_output,
-            &prepare_output,
-            &quotients_output,
+        format!(
+            "step {} of {}",
+            decoded.program_index + 1,
+            Program::num_steps()
+        )
+    };
+
+    println!();
+    println!("script {}: {}", decoded.program_index, step);
+    println!(
+        "old state: pc {}, stack hash {}, randomizer {}, balance {} sats",
+        decoded.old_state.pc,
+        hex::encode(&decoded.old_state.stack_hash),
+        decoded.covenant.old_randomizer,
+        decoded.covenant.old_balance
+    );
+    println!(
+        "new state: pc {}, stack hash {}, randomizer {}, balance {} sats",
+        decoded.new_state.pc,
+        hex::encode(&decoded.new_state.stack_hash),
+        decoded.covenant.new_randomizer,
+        decoded.covenant.new_balance
+    );
+    match &decoded.input {
+        FibonacciSplitInput::FiatShamir(claim, hints) => {
+            if let Some(claim) = claim {
+                println!("claim: {}", claim.0);
+            }
+            println!("input: {} elements of hints", hints.0.len());
+        }
+        FibonacciSplitInput::Prepare(stack, hints) => println!(
+            "input: {} elements of the carried stack and {} elements of hints",
+            stack.len(),
+            hints.0.len()
+        ),
+        FibonacciSplitInput::PerQuery(stack, quotients_hints, fold_hints) => println!(
+            "input: {} elements of the carried stack and {} elements of hints for {} queries",
+            stack.len(),
+            quotients_hints
+                .iter()
+                .chain(fold_hints)
+                .map(|hints| hints.0.len())
+                .sum::<usize>(),
+            quotients_hints.len()
+        ),
+        FibonacciSplitInput::Reset(_) => println!("input: the signature of the operator"),
+    }
+
+    // the new state is committed to by the caboose
+    if tx.output.get(1).map(|txout| &txout.script_pubkey)
+        != Some(&caboose_script_pub_key::<Program>(
+            &decoded.new_state,
+            decoded.covenant.new_randomizer,
+        ))
+    {
+        println!(
+            "{} the caboose does not commit to the new state",
+            "warning:".yellow()
         );
+    }
+}
 
-        let mut initial_program_txid = [0u8; 32];
-        initial_program_txid
-            .copy_from_slice(&hex::decode(args.initial_program_txid.unwrap()).unwrap());
-        initial_program_txid.reverse();
+fn validate(network: DemoNetwork, funding_tx_path: &str, tx_paths: Vec<String>) {
+    let tx_paths = if tx_paths.is_empty() {
+        (1..=Program::num_steps())
+            .map(|i| network.tx_file_name(i))
+            .collect()
+    } else {
+        tx_paths
+    };
 
-        let mut funding_txid = [0u8; 32];
-        funding_txid.copy_from_slice(&hex::decode(args.funding_txid.unwrap()).unwrap());
-        funding_txid.reverse();
+    let funding_tx = read_tx(funding_tx_path);
+    let txs = tx_paths
+        .iter()
+        .map(|path| read_tx(path))
+        .collect::<Vec<_>>();
 
-        let mut old_state = FibonacciSplitProgram::new();
-        let mut old_randomizer = 12u32;
-        let mut old_balance = rest;
-        let mut old_txid =
-            Txid::from_raw_hash(*sha256d::Hash::from_bytes_ref(&initial_program_txid));
+    match validate_chain::<Program>(&funding_tx, &txs, &HashMap::new()) {
+        Ok(()) => println!("All {} transactions are valid.", txs.len()),
+        Err(err) => {
+            let path = match err {
+                ChainError::MissingProgramOutput => funding_tx_path,
+                ChainError::NotSpendingProgram { tx, .. }
+                | ChainError::UnknownPrevout { tx, .. }
+                | ChainError::NotTapscriptSpend { tx, .. }
+                | ChainError::InvalidTaprootCommitment { tx, .. }
+                | ChainError::ScriptFailure { tx, .. } => &tx_paths[tx],
+            };
+            eprintln!("{} is invalid: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
 
-        let mut old_tx_outpoint1 = OutPoint {
-            txid: Txid::from_raw_hash(*sha256d::Hash::from_bytes_ref(&funding_txid)),
-            vout: 0,
-        };
+fn broadcast(
+    network: DemoNetwork,
+    tx_paths: Vec<String>,
+    options: &BroadcastOptions,
+    rpc: RpcArgs,
+) {
+    let tx_paths = if tx_paths.is_empty() {
+        (1..=Program::num_steps())
+            .map(|i| network.tx_file_name(i))
+            .collect()
+    } else {
+        tx_paths
+    };
+    let rpc = rpc.client(network);
 
-        let get_instruction = |old_state: &FibonacciSplitState| {
-            if old_state.pc == 0 {
-                Some(SimulationInstruction::<FibonacciSplitProgram> {
-                    program_index: 0,
-                    fee: 67835,
-                    program_input: FibonacciSplitInput::FiatShamir(Box::new(
-                        fiat_shamir_hints.clone(),
-                    )),
-                })
-            } else if old_state.pc == 1 {
-                Some(SimulationInstruction::<FibonacciSplitProgram> {
-                    program_index: 1,
-                    fee: 46448,
-                    program_input: FibonacciSplitInput::Prepare(
-                        old_state.stack.clone(),
-                        prepare_hints.clone(),
-                    ),
-                })
-            } else if old_state.pc >= 2 && old_state.pc <= 9 {
-                let i = old_state.pc - 2;
-                Some(SimulationInstruction {
-                    program_index: old_state.pc,
-                    fee: 84506,
-                    program_input: FibonacciSplitInput::PerQuery(
-                        old_state.stack.clone(),
-                        per_query_quotients_hints[i].clone(),
-                        per_query_fold_hints[i].clone(),
-                    ),
-                })
-            } else {
-                unimplemented!()
+    for path in tx_paths.iter() {
+        let tx = read_tx(path);
+        match broadcast_tx(&rpc, &tx, options) {
+            Ok(txid) => println!("{} is accepted as {}", path, txid),
+            Err(err) => {
+                eprintln!("{} is not broadcast: {}", path, err);
+                eprintln!("The transactions after it are not sent.");
+                std::process::exit(1);
             }
+        }
+    }
+    println!(
+        "{}",
+        format!("All {} transactions are broadcast.", tx_paths.len()).green()
+    );
+}
+
+fn follow(network: DemoNetwork, utxo: OutPoint, interval: u64, stall_timeout: u64, rpc: RpcArgs) {
+    let rpc = rpc.client(network);
+    let address = Address::from_script(
+        get_script_pub_key::<Program>().as_script(),
+        network.network(),
+    )
+    .unwrap();
+    let mut follower = FibonacciSplitFollower::<Parameters>::new(
+        address,
+        utxo,
+        Duration::from_secs(stall_timeout),
+    );
+
+    let mut last_progress = None;
+    loop {
+        // the node may be briefly unreachable, so a failed poll is retried at the next interval
+        match follower.poll(&rpc) {
+            Ok(progress) => {
+                if last_progress.as_ref() != Some(&progress) {
+                    println!("{}", progress);
+                }
+                if progress.status == FollowerStatus::Completed {
+                    println!("{}", "The proof is verified on the chain.".green());
+                    return;
+                }
+                last_progress = Some(progress);
+            }
+            Err(err) => eprintln!("failed to poll the node: {}", err),
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+fn simulate(hints_args: HintsArgs, fee_rate: u64) {
+    let (public_claim, hints) = hints_args.load();
+
+    // the fees are the same as in the transactions
+    let (_, fees) = build_txs(
+        &ChainTip::initial(Txid::all_zeros(), OutPoint::null(), ESTIMATION_BALANCE),
+        Program::num_steps(),
+        fee_rate,
+        public_claim,
+        &hints,
+    );
+
+    let mut step = 0;
+    let mut generator = |old_state: &FibonacciSplitState| {
+        let instruction = SimulationInstruction {
+            program_index: old_state.pc,
+            fee: fees[step] as usize,
+            program_input: Program::input_for_state(old_state, public_claim, &hints),
         };
+        step += 1;
+        Some(instruction)
+    };
+    simulation_test::<Program>(Program::num_steps(), &mut generator);
 
-        let mut txs = Vec::new();
+    let usages = stack_usage_of_steps::<Program>(Program::num_steps(), |state| {
+        (
+            state.pc,
+            Program::input_for_state(state, public_claim, &hints),
+        )
+    })
+    .unwrap();
+    match check_stack_usage(&usages) {
+        Ok(report) => println!("{}", report),
+        Err(err) => {
+            eprintln!("The stack exceeds the limits in {}.", err);
+            std::process::exit(1);
+        }
+    }
 
-        for _ in 0..10 {
-            let next = get_instruction(&old_state).unwrap();
+    println!(
+        "All {} steps succeed, paying {} sats in fees in total, at {} sat/vB.",
+        Program::num_steps(),
+        fees.iter().sum::<u64>(),
+        fee_rate
+    );
+}
 
-            let mut new_balance = old_balance;
-            new_balance -= next.fee as u64; // as for transaction fee
-            new_balance -= DUST_AMOUNT;
+/// Read the proof from the file, or generate it if not provided, check it natively, and compute
+/// the hints, together with the claim if it is a public input.
+fn load_hints(proof_path: Option<String>) -> (Option<M31>, SerializedVerifierHints) {
+    let (claim, hints) = compute_hints(proof_path);
+    (public_claim(claim), SerializedVerifierHints::from(&hints))
+}
 
+/// The claim as it is part of the witness, i.e., only in the public-input mode.
+fn public_claim(claim: M31) -> Option<M31> {
+    match Parameters::CLAIM_MODE {
+        FibonacciClaimMode::Constant(_) => None,
+        FibonacciClaimMode::PublicInput => Some(claim),
+    }
+}
+
+/// Read the proof from the file, or generate it if not provided, check it natively, and compute
+/// the hints, together with the claim.
+fn compute_hints(proof_path: Option<String>) -> (M31, VerifierHints) {
+    let proof_file = proof_path.map(|path| FibonacciProofFile::read(path).unwrap());
+
+    let claim = match Parameters::CLAIM_MODE {
+        FibonacciClaimMode::Constant(claim) => claim,
+        FibonacciClaimMode::PublicInput => match &proof_file {
+            Some(file) => file.claim().unwrap(),
+            None => fibonacci_claim(Parameters::LOG_SIZE),
+        },
+    };
+
+    let fib = Fibonacci::new(Parameters::LOG_SIZE, claim);
+
+    let proof = if let Some(file) = proof_file {
+        assert_eq!(file.log_size, Parameters::LOG_SIZE);
+        assert_eq!(file.claim().unwrap(), claim);
+        assert_eq!(file.config, Parameters::CONFIG);
+        file.proof().unwrap()
+    } else {
+        generate_proof(claim)
+    };
+
+    // check the proof natively before generating any transaction
+    let verdict = verify(&proof, &fib.air, Parameters::CONFIG);
+    if !verdict.is_accept() {
+        eprintln!("The proof is {}.", verdict);
+        std::process::exit(1);
+    }
+
+    match verify_with_hints(proof, &fib.air, Parameters::CONFIG) {
+        Ok(hints) => (claim, hints),
+        Err(err) => {
+            eprintln!("The hints cannot be computed from the proof: {}.", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read the hints from a file written by the hints command, together with the claim if it is a
+/// public input, which then comes from the file.
+fn read_hints(hints_path: &str) -> (Option<M31>, SerializedVerifierHints) {
+    let file = VerifierHintsFile::read(hints_path).unwrap();
+
+    let claim = match Parameters::CLAIM_MODE {
+        FibonacciClaimMode::Constant(claim) => claim,
+        FibonacciClaimMode::PublicInput => file.claim().unwrap(),
+    };
+
+    // the hints are not checked against a proof, but they must be for the program
+    if let Err(err) = file.check(Parameters::LOG_SIZE, claim, Parameters::CONFIG) {
+        eprintln!(
+            "The hints in {} do not fit the program: {}.",
+            hints_path, err
+        );
+        std::process::exit(1);
+    }
+
+    (
+        public_claim(claim),
+        SerializedVerifierHints::from(&file.hints),
+    )
+}
+
+/// Read a transaction in hex from a file.
+fn read_tx(path: &str) -> Transaction {
+    let tx_hex = std::fs::read_to_string(path).unwrap();
+    deserialize(&hex::decode(tx_hex.trim()).unwrap()).unwrap()
+}
+
+/// Generate a proof of the claim with the parameters of the program.
+fn generate_proof(claim: M31) -> StarkProof<Sha256MerkleHasher> {
+    let fib = Fibonacci::new(Parameters::LOG_SIZE, claim);
+    let trace = fib.get_trace();
+    let channel = &mut channel_for_claim(fib.air.component.claim);
+    commit_and_prove::<_, Sha256MerkleChannel>(
+        &fib.air,
+        channel,
+        vec![trace],
+        Parameters::CONFIG.pcs_config(),
+    )
+    .unwrap()
+}
+
+/// The last transaction of the program in the chain, which the next transactions spend.
+#[derive(Clone, Debug)]
+struct ChainTip {
+    /// The txid of the transaction.
+    txid: Txid,
+    /// The outpoint spent by the first input of the transaction.
+    spent_outpoint: OutPoint,
+    /// The state of the program after the transaction.
+    state: FibonacciSplitState,
+    /// The randomizer of the caboose of the transaction.
+    randomizer: u32,
+    /// The balance of the program after the transaction.
+    balance: u64,
+}
+
+impl ChainTip {
+    /// The initial program transaction, which spends the funding outpoint.
+    fn initial(
+        initial_program_txid: Txid,
+        funding_outpoint: OutPoint,
+        initial_balance: u64,
+    ) -> Self {
+        Self {
+            txid: initial_program_txid,
+            spent_outpoint: funding_outpoint,
+            state: Program::new(),
+            randomizer: 12,
+            balance: initial_balance,
+        }
+    }
+
+    /// The outputs of the transaction, i.e., the program and the caboose.
+    fn prevouts(&self) -> HashMap<OutPoint, TxOut> {
+        let mut prevouts = HashMap::new();
+        prevouts.insert(
+            OutPoint {
+                txid: self.txid,
+                vout: 0,
+            },
+            TxOut {
+                value: Amount::from_sat(self.balance),
+                script_pubkey: get_script_pub_key::<Program>(),
+            },
+        );
+        prevouts.insert(
+            OutPoint {
+                txid: self.txid,
+                vout: 1,
+            },
+            TxOut {
+                value: Amount::from_sat(DUST_AMOUNT),
+                script_pubkey: caboose_script_pub_key::<Program>(&self.state, self.randomizer),
+            },
+        );
+        prevouts
+    }
+}
+
+/// The state of the program with the given program counter, with the stack reconstructed by
+/// running the steps before it with the hints.
+fn state_at(
+    pc: usize,
+    public_claim: Option<M31>,
+    hints: &SerializedVerifierHints,
+) -> FibonacciSplitState {
+    assert!(
+        pc < Program::num_steps(),
+        "the program counter must be below {}",
+        Program::num_steps()
+    );
+
+    let mut state = Program::new();
+    while state.pc < pc {
+        let input = Program::input_for_state(&state, public_claim, hints);
+        state = Program::run(state.pc, &state, &input).unwrap();
+    }
+    state
+}
+
+/// Decode the tip of the chain from the last transaction of the program, whose new state must be
+/// the state reached with the hints.
+fn decode_tip(
+    tx: &Transaction,
+    public_claim: Option<M31>,
+    hints: &SerializedVerifierHints,
+) -> ChainTip {
+    let decoded = match Program::decode_tx(tx) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            eprintln!("The transaction is not a step of the program: {}.", err);
+            std::process::exit(1);
+        }
+    };
+
+    let state = state_at(decoded.new_state.pc, public_claim, hints);
+    if state.stack_hash != decoded.new_state.stack_hash {
+        eprintln!(
+            "The state after {} is not reached with the hints, which must be of the same proof.",
+            tx.compute_txid()
+        );
+        std::process::exit(1);
+    }
+
+    ChainTip {
+        txid: tx.compute_txid(),
+        spent_outpoint: tx.input[0].previous_output,
+        state,
+        randomizer: decoded.covenant.new_randomizer,
+        balance: decoded.covenant.new_balance,
+    }
+}
+
+/// Build the transactions of the given number of steps, continuing from the tip of the chain,
+/// where the fee of each step is the virtual size of its transaction at the given fee rate.
+fn build_txs(
+    tip: &ChainTip,
+    num_steps: usize,
+    fee_rate: u64,
+    public_claim: Option<M31>,
+    hints: &SerializedVerifierHints,
+) -> (Vec<Transaction>, Vec<u64>) {
+    let mut old_state = tip.state.clone();
+    let mut old_randomizer = tip.randomizer;
+    let mut old_balance = tip.balance;
+    let mut old_txid = tip.txid;
+    let mut old_tx_outpoint1 = tip.spent_outpoint;
+
+    let mut txs = vec![];
+    let mut fees = vec![];
+
+    for _ in 0..num_steps {
+        let program_index = old_state.pc;
+        let input = Program::input_for_state(&old_state, public_claim, hints);
+        let new_state = Program::run(program_index, &old_state, &input).unwrap();
+
+        let build_tx = |fee: u64| {
             let info = CovenantInput {
                 old_randomizer,
                 old_balance,
@@ -206,40 +1255,64 @@ fn main() {
                 input_outpoint1: old_tx_outpoint1,
                 input_outpoint2: None,
                 optional_deposit_input: None,
-                new_balance,
+                new_balance: old_balance - fee - DUST_AMOUNT,
             };
+            get_tx::<Program>(&info, program_index, &old_state, &new_state, &input)
+        };
 
-            let new_state =
-                FibonacciSplitProgram::run(next.program_index, &old_state, &next.program_input)
-                    .unwrap();
-
-            let (tx_template, randomizer) = get_tx::<FibonacciSplitProgram>(
-                &info,
-                next.program_index,
-                &old_state,
-                &new_state,
-                &next.program_input,
-            );
+        // the size of the transaction does not depend on the fee
+        let (tx_template, _) = build_tx(0);
+        let fee = tx_template.tx.vsize() as u64 * fee_rate;
+        let (tx_template, randomizer) = build_tx(fee);
 
-            txs.push(tx_template.tx.clone());
+        old_state = new_state;
+        old_randomizer = randomizer;
+        old_balance -= fee + DUST_AMOUNT;
+        old_txid = tx_template.tx.compute_txid();
+        old_tx_outpoint1 = tx_template.tx.input[0].previous_output;
 
-            old_state = new_state;
-            old_randomizer = randomizer;
-            old_balance = new_balance;
-            old_txid = tx_template.tx.compute_txid();
+        txs.push(tx_template.tx);
+        fees.push(fee);
+    }
 
-            old_tx_outpoint1 = tx_template.tx.input[0].previous_output;
-        }
+    (txs, fees)
+}
 
-        for (i, tx) in txs.iter().enumerate() {
-            let mut bytes = vec![];
-            tx.consensus_encode(&mut bytes).unwrap();
+/// The unsigned funding transaction, which spends the UTXO into the program with the initial
+/// balance and the caboose with the initial state.
+fn funding_psbt(outpoint: OutPoint, utxo: TxOut, initial_balance: u64) -> Psbt {
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(initial_balance),
+                script_pubkey: get_script_pub_key::<Program>(),
+            },
+            TxOut {
+                value: Amount::from_sat(DUST_AMOUNT),
+                script_pubkey: caboose_script_pub_key::<Program>(&Program::new(), 12),
+            },
+        ],
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+    // the wallet signs a segwit input against the amount and the script that it spends
+    psbt.inputs[0].witness_utxo = Some(utxo);
+    psbt
+}
 
-            let mut fs = std::fs::File::create(format!("./tx-{}.txt", i + 1)).unwrap();
-            fs.write_all(hex::encode(bytes).as_bytes()).unwrap();
-        }
+/// Parse an amount in BTC, e.g., 0.0123.
+fn parse_btc(value: &str) -> Result<Amount, String> {
+    Amount::from_str_in(value, Denomination::Bitcoin).map_err(|err| err.to_string())
+}
 
-        println!("================= INSTRUCTIONS =================");
-        println!("All 10 transactions have been generated and stored in the current directory.");
-    }
+/// Parse a script in hex.
+fn parse_script(script: &str) -> Result<ScriptBuf, String> {
+    ScriptBuf::from_hex(script).map_err(|err| err.to_string())
 }
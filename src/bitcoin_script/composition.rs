@@ -1,9 +1,11 @@
+use crate::bitcoin_script::FibonacciClaimMode;
 use bitcoin_circle_stark::constraints::ConstraintsGadget;
 use bitcoin_circle_stark::treepp::*;
 use num_traits::One;
 use rust_bitcoin_m31::{
-    qm31_add, qm31_copy, qm31_dup, qm31_equalverify, qm31_from_bottom, qm31_fromaltstack, qm31_mul,
-    qm31_mul_m31, qm31_over, qm31_rot, qm31_square, qm31_sub, qm31_swap, qm31_toaltstack,
+    m31_add, m31_mul, qm31_add, qm31_copy, qm31_dup, qm31_equalverify, qm31_from_bottom,
+    qm31_fromaltstack, qm31_mul, qm31_mul_m31, qm31_over, qm31_rot, qm31_square, qm31_sub,
+    qm31_swap, qm31_toaltstack,
 };
 use stwo_prover::core::circle::{CirclePoint, Coset};
 use stwo_prover::core::fields::m31::M31;
@@ -90,31 +92,33 @@ impl FibonacciCompositionGadget {
     /// - f(z)
     /// - z.x
     /// - z.y
+    /// - (claim - 1) / p.y (M31), where p is the last point of the trace domain
     ///
     /// Output:
     /// - num/denom
     ///
-    fn boundary_constraint_eval_quotient_by_mask(log_size: u32, claim: M31) -> Script {
+    fn boundary_constraint_eval_quotient_by_mask(log_size: u32) -> Script {
         let constraint_zero_domain = Coset::subgroup(log_size);
         let p = constraint_zero_domain.at(constraint_zero_domain.size() - 1);
         script! {
-            qm31_dup
-            qm31_toaltstack
-            qm31_swap
-            qm31_toaltstack // stack: f(z), z.y; altstack: z.y, z.x
+            OP_TOALTSTACK
+            qm31_rot
+            qm31_over // stack: z.x, z.y, f(z), z.y; altstack: (claim - 1) / p.y
 
-            { (claim - M31::one()) * p.y.inverse() }
+            OP_FROMALTSTACK
             qm31_mul_m31
 
             { QM31::one() }
             qm31_add // linear = QM31::one() + z.y * (self.claim - M31::one()) * p.y.inverse();
 
             qm31_sub // num = f(z) - linear
+            qm31_toaltstack // stack: z.x, z.y; altstack: num
 
-            qm31_fromaltstack // bring back z.x from altstack
-            qm31_fromaltstack // bring back z.y from altstack
             { ConstraintsGadget::pair_vanishing_with_constant_m31_points(p, CirclePoint::zero())} // denom
 
+            qm31_fromaltstack // bring back num from altstack
+            qm31_swap
+
             qm31_from_bottom // pull num/denom from hint
 
             qm31_dup
@@ -141,16 +145,40 @@ impl FibonacciCompositionGadget {
     /// - f(G^2 z)
     /// - z.x
     /// - z.y
+    /// - claim (M31, only in the public-input mode)
     ///
     /// Output:
     /// - alpha * step_constraint(f(z),f(Gz),f(G^2 z),z) + boundary_constraint(f(z),z,claim)
     ///
-    pub(crate) fn eval_composition_polynomial_at_point(log_size: u32, claim: M31) -> Script {
+    pub(crate) fn eval_composition_polynomial_at_point(
+        log_size: u32,
+        claim: FibonacciClaimMode,
+    ) -> Script {
+        let constraint_zero_domain = Coset::subgroup(log_size);
+        let p = constraint_zero_domain.at(constraint_zero_domain.size() - 1);
+
+        // compute (claim - 1) / p.y for the boundary constraint
+        let boundary_coeff = match claim {
+            FibonacciClaimMode::Constant(claim) => script! {
+                { (claim - M31::one()) * p.y.inverse() }
+            },
+            FibonacciClaimMode::PublicInput => script! {
+                { -M31::one() }
+                m31_add
+                { p.y.inverse() }
+                m31_mul
+            },
+        };
+
         script! {
+            { boundary_coeff }
+            OP_TOALTSTACK
+
             { qm31_copy(4) }
             { qm31_copy(2) }
             { qm31_copy(2) }
-            { Self::boundary_constraint_eval_quotient_by_mask(log_size,claim) }
+            OP_FROMALTSTACK
+            { Self::boundary_constraint_eval_quotient_by_mask(log_size) }
             qm31_toaltstack
 
             { Self::step_constraint_eval_quotient_by_mask(log_size) }
@@ -165,6 +193,7 @@ impl FibonacciCompositionGadget {
 #[cfg(test)]
 mod test {
     use crate::bitcoin_script::composition::FibonacciCompositionGadget;
+    use crate::bitcoin_script::FibonacciClaimMode;
     use bitcoin_circle_stark::air::CompositionHint;
     use bitcoin_circle_stark::tests_utils::report::report_bitcoin_script_size;
     use bitcoin_circle_stark::treepp::*;
@@ -206,7 +235,10 @@ mod test {
         let mut prng = ChaCha20Rng::seed_from_u64(0);
 
         let composition_polynomial_script =
-            FibonacciCompositionGadget::eval_composition_polynomial_at_point(log_size, claim);
+            FibonacciCompositionGadget::eval_composition_polynomial_at_point(
+                log_size,
+                FibonacciClaimMode::Constant(claim),
+            );
         report_bitcoin_script_size(
             "Fibonacci",
             format!(
@@ -217,6 +249,12 @@ mod test {
             composition_polynomial_script.len(),
         );
 
+        let public_claim_composition_polynomial_script =
+            FibonacciCompositionGadget::eval_composition_polynomial_at_point(
+                log_size,
+                FibonacciClaimMode::PublicInput,
+            );
+
         for _ in 0..20 {
             let random_coeff = get_rand_qm31(&mut prng);
 
@@ -249,7 +287,7 @@ mod test {
 
             let res = evaluation_accumulator.finalize();
 
-            let composition_hint = CompositionHint {
+            let composition_hint = || CompositionHint {
                 constraint_eval_quotients_by_mask: vec![
                     fib.air.component.boundary_constraint_eval_quotient_by_mask(
                         z,
@@ -263,7 +301,7 @@ mod test {
             };
 
             let script = script! {
-                { composition_hint } // hint
+                { composition_hint() } // hint
                 { random_coeff }
                 { mask_values[0][0] }
                 { mask_values[0][1] }
@@ -277,6 +315,23 @@ mod test {
             };
             let exec_result = execute_script(script);
             assert!(exec_result.success);
+
+            let script = script! {
+                { composition_hint() } // hint
+                { random_coeff }
+                { mask_values[0][0] }
+                { mask_values[0][1] }
+                { mask_values[0][2] }
+                { z.x }
+                { z.y }
+                { claim }
+                { public_claim_composition_polynomial_script.clone() }
+                { res }
+                qm31_equalverify
+                OP_TRUE
+            };
+            let exec_result = execute_script(script);
+            assert!(exec_result.success);
         }
     }
 }
@@ -1,5 +1,9 @@
+use crate::bitcoin_script::assert_valid_config;
 use crate::bitcoin_script::composition::FibonacciCompositionGadget;
-use crate::bitcoin_script::FIB_LOG_SIZE;
+use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+use crate::bitcoin_script::FibonacciClaimMode;
+use crate::channel_for_claim;
+use crate::VerifierConfig;
 use bitcoin_circle_stark::air::AirGadget;
 use bitcoin_circle_stark::channel::Sha256ChannelGadget;
 use bitcoin_circle_stark::circle::CirclePointGadget;
@@ -12,10 +16,8 @@ use bitcoin_scriptexec::{profiler_end, profiler_start};
 use rust_bitcoin_m31::{
     qm31_copy, qm31_dup, qm31_equalverify, qm31_from_bottom, qm31_over, qm31_roll,
 };
-use stwo_prover::core::channel::Sha256Channel;
-use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::m31::{M31, P};
 use stwo_prover::core::poly::circle::CanonicCoset;
-use stwo_prover::core::prover::{LOG_BLOWUP_FACTOR, N_QUERIES, PROOF_OF_WORK_BITS};
 
 pub struct FibonacciFiatShamirGadget;
 
@@ -23,6 +25,7 @@ impl FibonacciFiatShamirGadget {
     /// Finish the Fiat-Shamir transform steps until finalizing the queries.
     ///
     /// Hint:
+    /// - claim (only in the public-input mode)
     /// - trace commitment and composition commitment
     /// - first random coeff hint, used for constructing the composition polynomial
     /// - OODS hint, used for extraction
@@ -35,27 +38,134 @@ impl FibonacciFiatShamirGadget {
     ///   hints for extracting the corresponding folding alpha
     /// - last layer value, assuming only one QM31 element
     /// - PoW hint, used for verifying the PoW
-    /// - queries sampling hints, used to sample the `N_QUERIES` queries
+    /// - queries sampling hints, used to sample the `n_queries` queries
     ///
     /// Input: none
     ///
     /// Output:
+    /// - claim (only in the public-input mode)
     /// - trace oods values (3 * 4 = 12)
     /// - composition odds raw values (4 * 4 = 16)
     /// - random_coeff2 (4)
     /// - circle_poly_alpha (4)
-    /// - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+    /// - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
     /// - last layer (4)
-    /// - queries (N_QUERIES)
-    /// - trace queries (2 * N_QUERIES)
-    /// - composition queries (8 * N_QUERIES)
+    /// - queries (n_queries)
+    /// - trace queries (2 * n_queries)
+    /// - composition queries (8 * n_queries)
     /// - masked points (3 * 8 = 24)
     /// - oods point (8)
     ///
-    pub fn run(channel: &Sha256Channel) -> Script {
+    pub fn run(claim: FibonacciClaimMode, log_size: u32, config: VerifierConfig) -> Script {
+        assert_valid_config(config);
+
+        let n_queries = config.n_queries;
+        let log_blowup_factor = config.log_blowup_factor;
+
+        // the stack right after the OODS values are pulled from the hint
+        let mut layout = StackLayout::new()
+            .with(StackRegion::TraceCommitment, 1)
+            .with(StackRegion::RandomCoeff, 4)
+            .with(StackRegion::CompositionCommitment, 1)
+            .with(StackRegion::ChannelDigest, 1)
+            .with(StackRegion::OodsPoint, 8)
+            .with(StackRegion::MaskedPoints, 3 * 8)
+            .with(StackRegion::TraceOodsValues, 3 * 4)
+            .with(StackRegion::CompositionOodsRawValues, 4 * 4)
+            .with_claim(claim);
+
+        // the channel digest is kept in the altstack while the OODS values are mixed in
+        let stash_channel_digest = layout.remove(StackRegion::ChannelDigest);
+        let copy_composition_oods_raw_values =
+            layout.qm31_offset(StackRegion::CompositionOodsRawValues, 0);
+        layout.push(StackRegion::CompositionOodsValue, 4);
+
+        // and so is c2 while the composition polynomial is evaluated
+        let stash_composition_commitment = layout.remove(StackRegion::CompositionCommitment);
+        let roll_random_coeff = layout.qm31_offset(StackRegion::RandomCoeff, 0);
+        layout.roll(StackRegion::RandomCoeff);
+        let copy_trace_oods_values = layout.qm31_offset(StackRegion::TraceOodsValues, 0);
+        layout.push(StackRegion::TraceOodsValuesCopy, 3 * 4);
+        let copy_oods_point = layout.qm31_offset(StackRegion::OodsPoint, 0);
+        layout.push(StackRegion::OodsPointCopy, 8);
+        let composition_layout = layout.clone();
+
+        // the composition polynomial consumes its inputs, and its value is checked against the
+        // composition OODS value, before c2 is back from the altstack and the channel digest
+        // goes on top of the stack
+        let mut layout = layout
+            .without(StackRegion::RandomCoeff)
+            .without(StackRegion::TraceOodsValuesCopy)
+            .without(StackRegion::OodsPointCopy)
+            .without(StackRegion::CompositionOodsValue)
+            .with(StackRegion::CompositionCommitment, 1)
+            .with(StackRegion::RandomCoeff2, 4)
+            .with(StackRegion::CirclePolyAlpha, 4)
+            .with(
+                StackRegion::FriCommitmentsAndAlphas,
+                (1 + 4) * log_size as usize,
+            )
+            .with(StackRegion::LastLayer, 4)
+            .with(StackRegion::Queries, n_queries);
+
+        // the layouts when the `i`-th query of the trace or the composition is being verified
+        let pull_trace_commitment = layout.roll(StackRegion::TraceCommitment);
+        let trace_layout = layout.clone();
+        let trace_query_layout =
+            |i: usize| trace_layout.clone().with(StackRegion::TraceQueries, 2 * i);
+        layout.push(StackRegion::TraceQueries, 2 * n_queries);
+        let drop_trace_commitment = layout.remove(StackRegion::TraceCommitment);
+
+        let pull_composition_commitment = layout.roll(StackRegion::CompositionCommitment);
+        let composition_commitment_layout = layout.clone();
+        let composition_query_layout = |i: usize| {
+            composition_commitment_layout
+                .clone()
+                .with(StackRegion::CompositionQueries, 8 * i)
+        };
+        layout.push(StackRegion::CompositionQueries, 8 * n_queries);
+        let drop_composition_commitment = layout.remove(StackRegion::CompositionCommitment);
+
+        let pull_masked_points = layout.roll(StackRegion::MaskedPoints);
+        let pull_oods_point = layout.roll(StackRegion::OodsPoint);
+
+        assert_eq!(
+            layout,
+            StackLayout::fiat_shamir_output(log_size, config).with_claim(claim)
+        );
+
+        let (initial_channel, pick_claim) = match claim {
+            FibonacciClaimMode::Constant(claim) => (
+                script! {
+                    { channel_for_claim(claim).digest }
+                },
+                script! {},
+            ),
+            FibonacciClaimMode::PublicInput => (
+                script! {
+                    // pull the claim and check that it is a canonical M31 element
+                    OP_HINT OP_1ADD OP_1SUB
+                    OP_DUP 0 OP_GREATERTHANOREQUAL OP_VERIFY
+                    OP_DUP { P as usize } OP_LESSTHAN OP_VERIFY
+
+                    // pad the claim into its 4-byte little-endian representation and hash it,
+                    // which matches `channel_for_claim`
+                    OP_DUP
+                    for _ in 0..4 {
+                        OP_SIZE 4 OP_LESSTHAN OP_IF { vec![0u8; 1] } OP_CAT OP_ENDIF
+                    }
+                    OP_SHA256
+                },
+                script! {
+                    // copy the claim, which is right below c1
+                    { composition_layout.bottom(StackRegion::Claim) } OP_PICK
+                },
+            ),
+        };
+
         script! {
-            // push the initial channel
-            { channel.digest }
+            // push the initial channel, seeded with the claim
+            { initial_channel }
 
             // pull the first commitment and mix it with the channel
             OP_HINT
@@ -75,11 +185,10 @@ impl FibonacciFiatShamirGadget {
             // draw the OODS point
             { OODSGadget::get_random_point() }
 
-            // stack: c1, random_coeff (4), c2, channel_digest, oods point (8)
             { CirclePointGadget::dup() }
 
             // mask the points
-            { AirGadget::shifted_mask_points(&vec![vec![0, 1, 2]], &[CanonicCoset::new(FIB_LOG_SIZE)]) }
+            { AirGadget::shifted_mask_points(&vec![vec![0, 1, 2]], &[CanonicCoset::new(log_size)]) }
 
             // pull trace oods values from the hint
             for _ in 0..3 {
@@ -91,15 +200,8 @@ impl FibonacciFiatShamirGadget {
                 qm31_from_bottom
             }
 
-            // stack:
-            //    c1, random_coeff (4), c2, channel_digest, oods point (8),
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-
             // update the digest with all the trace oods values and composition odds raw values
-
-            60 OP_ROLL OP_TOALTSTACK
+            { stash_channel_digest } OP_ROLL OP_TOALTSTACK
             { qm31_copy(6) } OP_FROMALTSTACK { Sha256ChannelGadget::mix_felt() } OP_TOALTSTACK
             { qm31_copy(5) } OP_FROMALTSTACK { Sha256ChannelGadget::mix_felt() } OP_TOALTSTACK
             { qm31_copy(4) } OP_FROMALTSTACK { Sha256ChannelGadget::mix_felt() } OP_TOALTSTACK
@@ -109,58 +211,25 @@ impl FibonacciFiatShamirGadget {
             qm31_dup OP_FROMALTSTACK { Sha256ChannelGadget::mix_felt() } OP_TOALTSTACK
 
             // compute the composition eval
-            //
-            // stack:
-            //    c1, random_coeff (4), c2, oods point (8),
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //
-            // altstack:
-            //    channel_digest
-
-            { qm31_copy(3) }
-            { qm31_copy(3) }
-            { qm31_copy(3) }
-            { qm31_copy(3) }
+            for _ in 0..4 {
+                { qm31_copy(copy_composition_oods_raw_values) }
+            }
             { AirGadget::eval_from_partial_evals() }
 
             // prepare the input to `eval_composition_polynomial_at_point`
-            //
-            // stack:
-            //    c1, random_coeff (4), c2, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    composition odds value (4)
-            //
-            // altstack:
-            //    channel_digest
-
-            64 OP_ROLL OP_TOALTSTACK
-            { qm31_roll(16) }
-            { qm31_copy(8) }
-            { qm31_copy(8) }
-            { qm31_copy(8) }
-            { qm31_copy(19) }
-            { qm31_copy(19) }
-
-            // stack:
-            //    c1, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    composition odds value (4)
-            //
-            //    random_coeff (4)
-            //    trace oods values (3 * 4 = 12)
-            //    oods point (8)
-            //
-            // altstack:
-            //    channel_digest, c2
+            { stash_composition_commitment } OP_ROLL OP_TOALTSTACK
+            { qm31_roll(roll_random_coeff) }
+            for _ in 0..3 {
+                { qm31_copy(copy_trace_oods_values) }
+            }
+            for _ in 0..2 {
+                { qm31_copy(copy_oods_point) }
+            }
+
+            { pick_claim }
 
             { profiler_start("eval composition polynomial") }
-            { FibonacciCompositionGadget::eval_composition_polynomial_at_point(FIB_LOG_SIZE, M31::from_u32_unchecked(443693538)) }
+            { FibonacciCompositionGadget::eval_composition_polynomial_at_point(log_size, claim) }
             { profiler_end("eval composition polynomial") }
 
             qm31_equalverify
@@ -173,151 +242,65 @@ impl FibonacciFiatShamirGadget {
             4 OP_ROLL
 
             // compute all the intermediate alphas
-            //
-            // stack:
-            //    c1, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    channel_digest
-
-            for _ in 0..FIB_LOG_SIZE {
+            for _ in 0..log_size {
                 OP_HINT OP_DUP OP_ROT { Sha256ChannelGadget::mix_digest() }
                 { Sha256ChannelGadget::draw_felt_with_hint() }
                 4 OP_ROLL
             }
 
-            // stack:
-            //    c1, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    channel_digest
-
             // incorporate the last layer
             qm31_from_bottom
             qm31_dup
             8 OP_ROLL
             { Sha256ChannelGadget::mix_felt() }
 
-            // stack:
-            //    c1, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    channel_digest
-
             // check proof of work
-            { PowGadget::verify_pow(PROOF_OF_WORK_BITS) }
+            { PowGadget::verify_pow(config.pow_bits) }
 
-            // derive N_QUERIES queries
-            { Sha256ChannelGadget::draw_numbers_with_hint(N_QUERIES, (FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) as usize) }
+            // derive n_queries queries
+            { Sha256ChannelGadget::draw_numbers_with_hint(n_queries, (log_size + log_blowup_factor + 1) as usize) }
 
             // drop channel digest
             OP_DROP
 
-            // stack:
-            //    c1, oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-
             // pull c1, which is the commitment of the trace Merkle tree
-            { N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 1 + 16 + 12 + 24 + 8 } OP_ROLL
-
-            // stack:
-            //    oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    c1
+            { pull_trace_commitment } OP_ROLL
 
             // handle each query for trace
-            for i in 0..N_QUERIES {
-                { 2 * i } OP_PICK // copy c1
-                { 2 * i + 1 + 1 + N_QUERIES - i - 1 } OP_PICK // copy query
+            for i in 0..n_queries {
+                { trace_query_layout(i).bottom(StackRegion::TraceCommitment) } OP_PICK // copy c1
+                { trace_query_layout(i).with(StackRegion::CommitmentCopy, 1).offset(StackRegion::Queries, i) } OP_PICK // copy query
                 { profiler_start("merkle tree for trace") }
-                { MerkleTreeTwinGadget::query_and_verify(1, (FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) as usize) }
+                { MerkleTreeTwinGadget::query_and_verify(1, (log_size + log_blowup_factor + 1) as usize) }
                 { profiler_end("merkle tree for trace") }
             }
 
             // drop c1
-            { 2 * N_QUERIES } OP_ROLL OP_DROP
-
-            // stack:
-            //    oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    c2
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
+            { drop_trace_commitment } OP_ROLL OP_DROP
 
             // pull c2, which is the commitment of the composition Merkle tree
-            { 2 * N_QUERIES + N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 } OP_ROLL
-
+            { pull_composition_commitment } OP_ROLL
 
             // handle each query for composition
-            for i in 0..N_QUERIES {
-                { 8 * i } OP_PICK // copy c2
-                { 1 + 8 * i + 1 + 2 * N_QUERIES + N_QUERIES - i - 1 } OP_PICK // copy query
+            for i in 0..n_queries {
+                { composition_query_layout(i).bottom(StackRegion::CompositionCommitment) } OP_PICK // copy c2
+                { composition_query_layout(i).with(StackRegion::CommitmentCopy, 1).offset(StackRegion::Queries, i) } OP_PICK // copy query
                 { profiler_start("merkle tree for composition") }
-                { MerkleTreeTwinGadget::query_and_verify(4, (FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) as usize) }
+                { MerkleTreeTwinGadget::query_and_verify(4, (log_size + log_blowup_factor + 1) as usize) }
                 { profiler_end("merkle tree for composition") }
             }
 
             // drop c2
-            { 8 * N_QUERIES } OP_ROLL OP_DROP
-
-            // stack:
-            //    oods point (8)
-            //    masked points (3 * 8 = 24)
-            //    trace oods values (3 * 4 = 12)
-            //    composition odds raw values (4 * 4 = 16)
-            //    random_coeff2 (4)
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            { drop_composition_commitment } OP_ROLL OP_DROP
 
             // pull the masked points
             for _ in 0..24 {
-                { (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + 12 + 24 - 1 } OP_ROLL
+                { pull_masked_points } OP_ROLL
             }
 
             // pull the OODS point
             for _ in 0..8 {
-                { 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + 12 + 8 - 1 } OP_ROLL
+                { pull_oods_point } OP_ROLL
             }
         }
     }
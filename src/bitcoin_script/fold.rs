@@ -1,4 +1,6 @@
-use crate::bitcoin_script::FIB_LOG_SIZE;
+use crate::bitcoin_script::assert_valid_config;
+use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+use crate::VerifierConfig;
 use bitcoin_circle_stark::fri::FFTGadget;
 use bitcoin_circle_stark::merkle_tree::MerkleTreePathGadget;
 use bitcoin_circle_stark::treepp::*;
@@ -10,91 +12,61 @@ use bitcoin_scriptexec::{profiler_end, profiler_start};
 use rust_bitcoin_m31::{
     qm31_add, qm31_copy, qm31_equalverify, qm31_mul, qm31_over, qm31_rot, qm31_swap,
 };
-use stwo_prover::core::prover::{LOG_BLOWUP_FACTOR, N_QUERIES};
 
 pub struct FibonacciPerQueryFoldGadget;
 
 impl FibonacciPerQueryFoldGadget {
-    pub fn run(query_idx: usize) -> Script {
-        script! {
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (6 * 4 = 24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    answer_1 (qm31)
-            //    answer_2 (qm31)
+    pub fn run(query_idx: usize, log_size: u32, config: VerifierConfig) -> Script {
+        assert_valid_config(config);
+
+        let log_blowup_factor = config.log_blowup_factor;
+        let num_twiddles = (log_size + log_blowup_factor) as usize;
+
+        // the layout of the stack when the given number of twiddle factors are left
+        let prepare_layout = StackLayout::prepare_output(log_size, config);
+        let query_layout = |num_twiddles: usize| {
+            prepare_layout
+                .clone()
+                .with(StackRegion::TwiddleFactors, num_twiddles)
+        };
+
+        // the answers are on top of the twiddle factors, and the twin values of each layer are
+        // folded into a single value with the alpha of the layer
+        let answers_layout = query_layout(num_twiddles).with(StackRegion::Answers, 2 * 4);
+        let twin_values_layout = |num_twiddles: usize| {
+            query_layout(num_twiddles).with(StackRegion::LayerTwinValues, 2 * 4)
+        };
+        let folded_value_layout =
+            |num_twiddles: usize| query_layout(num_twiddles).with(StackRegion::FoldedValue, 4);
 
+        script! {
             // pull the query
-            { 4 + 4 + 15 + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8) * N_QUERIES + N_QUERIES - query_idx - 1 } OP_PICK
-            { limb_to_be_bits_toaltstack_except_lowest_1bit(FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) }
+            { answers_layout.offset(StackRegion::Queries, query_idx) } OP_PICK
+            { limb_to_be_bits_toaltstack_except_lowest_1bit(log_size + log_blowup_factor + 1) }
 
             // pull y (last twiddle factor)
-            8 OP_ROLL
-
-            // local stack (4 elements):
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (14)
-            //    answer_1 (qm31)
-            //    answer_2 (qm31)
-            //    y (1)
+            { answers_layout.offset(StackRegion::TwiddleFactors, num_twiddles - 1) } OP_ROLL
 
             // perform the inverse FFT using p.y
             { FFTGadget::ibutterfly() }
 
             // obtain circle_poly_alpha
             for _ in 0..4 {
-                { 4 + 4 + 14 + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 - 1 } OP_PICK
+                { twin_values_layout(num_twiddles - 1).bottom(StackRegion::CirclePolyAlpha) } OP_PICK
             }
 
-            // local stack (4 elements):
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (14)
-            //    f1 (qm31)
-            //    f2 (qm31)
-            //    circle_poly_alpha (qm31)
-
             qm31_mul qm31_add
 
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (6 * 4 = 24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (14)
-            //    res (qm31)
-
-            for j in 0..FIB_LOG_SIZE as usize {
+            for j in 0..log_size as usize {
                 // copy the Merkle tree hash
-                { 4 + (14 - j) + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize - (1 + 4) * j - 1 } OP_PICK
+                { folded_value_layout(num_twiddles - 1 - j).offset(StackRegion::FriCommitmentsAndAlphas, (1 + 4) * j) } OP_PICK
 
                 // copy the query
-                { 1 + 4 + (14 - j) + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8) * N_QUERIES + N_QUERIES - query_idx - 1 } OP_PICK
+                { folded_value_layout(num_twiddles - 1 - j).with(StackRegion::CommitmentCopy, 1).offset(StackRegion::Queries, query_idx) } OP_PICK
 
                 // push the root hash to the altstack, first
                 OP_SWAP OP_TOALTSTACK
-                { limb_to_be_bits_toaltstack_except_lowest_2bits(FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) }
+                { limb_to_be_bits_toaltstack_except_lowest_2bits(log_size + log_blowup_factor + 1) }
                 for _ in 0..j {
                     OP_FROMALTSTACK OP_DROP
                 }
@@ -124,7 +96,7 @@ impl FibonacciPerQueryFoldGadget {
                 OP_CAT hash
 
                 { profiler_start("merkle tree verification for folding") }
-                { MerkleTreePathGadget::verify((FIB_LOG_SIZE + LOG_BLOWUP_FACTOR - 1) as usize - j) }
+                { MerkleTreePathGadget::verify((log_size + log_blowup_factor - 1) as usize - j) }
                 { profiler_end("merkle tree verification for folding") }
 
                 qm31_rot
@@ -137,12 +109,12 @@ impl FibonacciPerQueryFoldGadget {
                 qm31_equalverify
 
                 { profiler_start("fft and multiply by alpha in folding") }
-                8 OP_ROLL
+                { twin_values_layout(num_twiddles - 1 - j).offset(StackRegion::TwiddleFactors, num_twiddles - 1 - j - 1) } OP_ROLL
                 { FFTGadget::ibutterfly() }
 
                 // obtain the corresponding alpha
                 for _ in 0..4 {
-                    { 4 + 4 + (14 - 1 - j) + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize - (1 + 4) * j - 2 } OP_PICK
+                    { twin_values_layout(num_twiddles - 1 - 1 - j).offset(StackRegion::FriCommitmentsAndAlphas, (1 + 4) * j + 1) } OP_PICK
                 }
 
                 qm31_mul qm31_add
@@ -151,14 +123,14 @@ impl FibonacciPerQueryFoldGadget {
 
             // pull the last layer
             for _ in 0..4 {
-                { 4 + (14 - FIB_LOG_SIZE as usize) + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 - 1 } OP_PICK
+                { folded_value_layout(num_twiddles - 1 - log_size as usize).bottom(StackRegion::LastLayer) } OP_PICK
             }
             qm31_equalverify
 
-            for _ in 0..(LOG_BLOWUP_FACTOR - 1) {
+            for _ in 0..(log_blowup_factor - 1) {
                 OP_DROP
             } // drop the twiddle factors
-            for _ in 0..LOG_BLOWUP_FACTOR {
+            for _ in 0..log_blowup_factor {
                 OP_FROMALTSTACK OP_DROP
             } // drop the unused position bits
         }
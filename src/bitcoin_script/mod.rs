@@ -2,10 +2,11 @@ use crate::bitcoin_script::fiat_shamir::FibonacciFiatShamirGadget;
 use crate::bitcoin_script::fold::FibonacciPerQueryFoldGadget;
 use crate::bitcoin_script::prepare::FibonacciPrepareGadget;
 use crate::bitcoin_script::quotients::FibonacciPerQueryQuotientGadget;
+use crate::bitcoin_script::stack_layout::StackLayout;
+use crate::VerifierConfig;
 use bitcoin_circle_stark::treepp::*;
 use bitcoin_circle_stark::utils::clean_stack;
-use stwo_prover::core::channel::Sha256Channel;
-use stwo_prover::core::prover::N_QUERIES;
+use stwo_prover::core::fields::m31::M31;
 
 mod composition;
 
@@ -17,29 +18,71 @@ pub(crate) mod prepare;
 
 pub(crate) mod fold;
 
-/// The Fibonacci log size in this test.
+pub(crate) mod stack_layout;
+
+/// The Fibonacci log size used by the demo.
 pub const FIB_LOG_SIZE: u32 = 5;
 
+/// The Fibonacci claim used by the demo, i.e., the last element of the trace of size `2^FIB_LOG_SIZE`.
+pub const FIB_CLAIM: M31 = M31::from_u32_unchecked(443693538);
+
+/// How the verifier obtains the Fibonacci claim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FibonacciClaimMode {
+    /// The claim is a constant compiled into the script.
+    Constant(M31),
+    /// The claim is a public input read from the hint, which stays at the bottom of the stack
+    /// so that it is committed together with the rest of the stack.
+    PublicInput,
+}
+
+impl FibonacciClaimMode {
+    /// The number of stack elements that the claim occupies at the bottom of the stack.
+    pub fn num_stack_elements(&self) -> usize {
+        match self {
+            FibonacciClaimMode::Constant(_) => 0,
+            FibonacciClaimMode::PublicInput => 1,
+        }
+    }
+}
+
+/// Check the configuration before building a script for it, as the script would be wrong for an
+/// invalid one rather than failing.
+pub(crate) fn assert_valid_config(config: VerifierConfig) {
+    if let Err(err) = config.check() {
+        panic!("cannot build the verifier: {}", err);
+    }
+}
+
 /// A verifier for the Fibonacci proof.
 pub struct FibonacciVerifierGadget;
 
 impl FibonacciVerifierGadget {
-    /// Run the verifier in the Bitcoin script.
-    pub fn run_verifier(channel: &Sha256Channel) -> Script {
+    /// Run the verifier in the Bitcoin script, for the claim of a Fibonacci trace of size `2^log_size`.
+    ///
+    /// In the public-input mode, the claim is expected to be the first element of the hint.
+    pub fn run_verifier(
+        claim: FibonacciClaimMode,
+        log_size: u32,
+        config: VerifierConfig,
+    ) -> Script {
+        let n_queries = config.n_queries;
+
         script! {
             // Run the Fiat-Shamir gadget
-            { FibonacciFiatShamirGadget::run(channel) }
+            { FibonacciFiatShamirGadget::run(claim, log_size, config) }
 
             // Run prepare gadget
-            { FibonacciPrepareGadget::run() }
+            { FibonacciPrepareGadget::run(log_size, config) }
 
             // stack:
+            //    claim (only in the public-input mode)
             //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
             //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            //    queries (n_queries)
+            //    trace queries (2 * n_queries)
+            //    composition queries (8 * n_queries)
             //    masked points (3 * 8 = 24)
             //    oods point (8)
             //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
@@ -48,18 +91,19 @@ impl FibonacciVerifierGadget {
             //    prepared oods point (4)
             //    coeff^6, coeff^5, ..., coeff (24)
 
-            for i in 0..N_QUERIES {
-                { FibonacciPerQueryQuotientGadget::run(i) }
-                { FibonacciPerQueryFoldGadget::run(i) }
+            for i in 0..n_queries {
+                { FibonacciPerQueryQuotientGadget::run(i, log_size, config) }
+                { FibonacciPerQueryFoldGadget::run(i, log_size, config) }
             }
 
             // stack:
+            //    claim (only in the public-input mode)
             //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
             //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            //    queries (n_queries)
+            //    trace queries (2 * n_queries)
+            //    composition queries (8 * n_queries)
             //    masked points (3 * 8 = 24)
             //    oods point (8)
             //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
@@ -69,7 +113,7 @@ impl FibonacciVerifierGadget {
             //    coeff^6, coeff^5, ..., coeff (24)
 
             // clean up the stack
-            { clean_stack(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
+            { clean_stack(StackLayout::prepare_output(log_size, config).with_claim(claim).len()) }
         }
     }
 }
@@ -77,70 +121,256 @@ impl FibonacciVerifierGadget {
 #[cfg(test)]
 mod test {
     use crate::bitcoin_script::FIB_LOG_SIZE;
-    use crate::{verify_with_hints, FibonacciVerifierGadget};
+    use crate::error::{ConfigError, HintError};
+    use crate::proof::test::proof_for;
+    use crate::tamper::{hints_for_tampering, tampered_hints, TamperedStep};
+    use crate::{
+        channel_for_claim, fibonacci_claim, verify_with_hints, FibonacciClaimMode,
+        FibonacciVerifierGadget, VerifierConfig, VerifierHints, MAX_LOG_BLOWUP_FACTOR,
+    };
     use bitcoin_circle_stark::tests_utils::report::report_bitcoin_script_size;
     use bitcoin_circle_stark::treepp::*;
     use bitcoin_scriptexec::execute_script_with_witness_unlimited_stack;
-    use stwo_prover::core::channel::Sha256Channel;
-    use stwo_prover::core::fields::m31::{BaseField, M31};
-    use stwo_prover::core::fields::IntoSlice;
-    use stwo_prover::core::pcs::PcsConfig;
-    use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::prover::PROOF_OF_WORK_BITS;
     use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleChannel;
     use stwo_prover::examples::fibonacci::Fibonacci;
-    use stwo_prover::trace_generation::{commit_and_prove, commit_and_verify};
+    use stwo_prover::trace_generation::commit_and_verify;
 
-    #[test]
-    fn test_verifier() {
-        let fib = Fibonacci::new(FIB_LOG_SIZE, M31::reduce(443693538));
-        let config = PcsConfig::default();
-
-        let trace = fib.get_trace();
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let proof =
-            commit_and_prove::<_, Sha256MerkleChannel>(&fib.air, channel, vec![trace], config)
-                .unwrap();
+    fn test_verifier_with_claim(
+        log_size: u32,
+        script_claim: FibonacciClaimMode,
+        public_claim: Option<M31>,
+        verifier_config: VerifierConfig,
+    ) -> bool {
+        let fib = Fibonacci::new(log_size, fibonacci_claim(log_size));
+        let config = verifier_config.pcs_config();
+        let proof = proof_for(log_size, fibonacci_claim(log_size), verifier_config);
 
         {
-            let channel = &mut Sha256Channel::default();
-            channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-                .air
-                .component
-                .claim])));
+            let channel = &mut channel_for_claim(fib.air.component.claim);
             commit_and_verify::<Sha256MerkleChannel>(proof.clone(), &fib.air, channel, config)
                 .unwrap();
         }
 
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let channel_clone = channel.clone();
+        let hint = verify_with_hints(proof, &fib.air, verifier_config).unwrap();
 
-        let hint = verify_with_hints(proof, &fib.air, channel).unwrap();
-
-        let witness = script! {
-            { hint }
+        let witness = match public_claim {
+            Some(claim) => script! {
+                { claim }
+                { hint }
+            },
+            None => script! {
+                { hint }
+            },
         };
 
         let script = script! {
-            { FibonacciVerifierGadget::run_verifier(&channel_clone) }
+            { FibonacciVerifierGadget::run_verifier(script_claim, log_size, verifier_config) }
             OP_TRUE
         };
 
-        report_bitcoin_script_size("Fibonacci", "verifier", script.len());
+        report_bitcoin_script_size(
+            "Fibonacci",
+            format!(
+                "verifier(log_size={}, n_queries={}, pow_bits={})",
+                log_size, verifier_config.n_queries, verifier_config.pow_bits
+            )
+            .as_str(),
+            script.len(),
+        );
 
         let exec_result = execute_script_with_witness_unlimited_stack(
             script,
             convert_to_witness(witness).unwrap(),
         );
-        assert!(exec_result.success);
         #[cfg(feature = "profiler")]
         exec_result.profiler.print_stats();
+        exec_result.success
+    }
+
+    #[test]
+    fn test_verifier() {
+        let claim = fibonacci_claim(FIB_LOG_SIZE);
+        assert!(test_verifier_with_claim(
+            FIB_LOG_SIZE,
+            FibonacciClaimMode::Constant(claim),
+            None,
+            VerifierConfig::default()
+        ));
+    }
+
+    #[test]
+    fn test_verifier_different_log_sizes() {
+        for log_size in [6, 7, 10] {
+            let claim = fibonacci_claim(log_size);
+            assert!(test_verifier_with_claim(
+                log_size,
+                FibonacciClaimMode::Constant(claim),
+                None,
+                VerifierConfig::default()
+            ));
+        }
+    }
+
+    #[test]
+    #[ignore = "proving a trace of 2^16 rows and running its verifier take minutes"]
+    fn test_verifier_log_size_16() {
+        let claim = fibonacci_claim(16);
+        assert!(test_verifier_with_claim(
+            16,
+            FibonacciClaimMode::Constant(claim),
+            None,
+            VerifierConfig::default()
+        ));
+    }
+
+    #[test]
+    fn test_verifier_wrong_claim() {
+        let wrong_claim = fibonacci_claim(FIB_LOG_SIZE) + M31::from_u32_unchecked(1);
+        assert!(!test_verifier_with_claim(
+            FIB_LOG_SIZE,
+            FibonacciClaimMode::Constant(wrong_claim),
+            None,
+            VerifierConfig::default()
+        ));
+    }
+
+    #[test]
+    fn test_verifier_public_claim() {
+        for log_size in [FIB_LOG_SIZE, 6] {
+            let claim = fibonacci_claim(log_size);
+            assert!(test_verifier_with_claim(
+                log_size,
+                FibonacciClaimMode::PublicInput,
+                Some(claim),
+                VerifierConfig::default()
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verifier_public_claim_wrong_claim() {
+        let wrong_claim = fibonacci_claim(FIB_LOG_SIZE) + M31::from_u32_unchecked(1);
+        assert!(!test_verifier_with_claim(
+            FIB_LOG_SIZE,
+            FibonacciClaimMode::PublicInput,
+            Some(wrong_claim),
+            VerifierConfig::default()
+        ));
+    }
+
+    #[test]
+    fn test_verifier_different_configs() {
+        let claim = fibonacci_claim(FIB_LOG_SIZE);
+        for verifier_config in [
+            VerifierConfig {
+                n_queries: 4,
+                pow_bits: PROOF_OF_WORK_BITS + 2,
+                ..VerifierConfig::default()
+            },
+            VerifierConfig {
+                n_queries: 12,
+                ..VerifierConfig::default()
+            },
+            VerifierConfig {
+                log_blowup_factor: 2,
+                ..VerifierConfig::default()
+            },
+        ] {
+            assert!(test_verifier_with_claim(
+                FIB_LOG_SIZE,
+                FibonacciClaimMode::Constant(claim),
+                None,
+                verifier_config
+            ));
+        }
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, fibonacci_claim(FIB_LOG_SIZE));
+        let proof = proof_for(
+            FIB_LOG_SIZE,
+            fibonacci_claim(FIB_LOG_SIZE),
+            VerifierConfig::default(),
+        );
+
+        for (verifier_config, expected) in [
+            (
+                VerifierConfig {
+                    n_queries: 0,
+                    ..VerifierConfig::default()
+                },
+                ConfigError::NoQueries,
+            ),
+            (
+                VerifierConfig {
+                    log_blowup_factor: 0,
+                    ..VerifierConfig::default()
+                },
+                ConfigError::LogBlowupFactorOutOfRange {
+                    log_blowup_factor: 0,
+                },
+            ),
+            (
+                VerifierConfig {
+                    log_blowup_factor: MAX_LOG_BLOWUP_FACTOR + 1,
+                    ..VerifierConfig::default()
+                },
+                ConfigError::LogBlowupFactorOutOfRange {
+                    log_blowup_factor: MAX_LOG_BLOWUP_FACTOR + 1,
+                },
+            ),
+        ] {
+            assert_eq!(verifier_config.check(), Err(expected.clone()));
+            assert!(matches!(
+                verify_with_hints(proof.clone(), &fib.air, verifier_config),
+                Err(HintError::InvalidConfig(err)) if err == expected
+            ));
+            assert!(std::panic::catch_unwind(|| {
+                FibonacciVerifierGadget::run_verifier(
+                    FibonacciClaimMode::Constant(fibonacci_claim(FIB_LOG_SIZE)),
+                    FIB_LOG_SIZE,
+                    verifier_config,
+                )
+            })
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_verifier_tampered_hints() {
+        let verifier_config = VerifierConfig::default();
+        let (hints, other) = hints_for_tampering(FIB_LOG_SIZE, verifier_config);
+
+        let script = script! {
+            { FibonacciVerifierGadget::run_verifier(FibonacciClaimMode::Constant(fibonacci_claim(FIB_LOG_SIZE)), FIB_LOG_SIZE, verifier_config) }
+            OP_TRUE
+        };
+
+        let run = |hints: &VerifierHints| {
+            let witness = convert_to_witness(script! {
+                { hints.clone() }
+            })
+            .unwrap();
+            execute_script_with_witness_unlimited_stack(script.clone(), witness).success
+        };
+
+        assert!(run(&hints));
+        // the hints other than the per-query ones are tampered only once
+        for query in [0, verifier_config.n_queries - 1] {
+            for tampered in tampered_hints(&hints, &other, query)
+                .into_iter()
+                .filter(|tampered| query == 0 || tampered.step == TamperedStep::PerQuery(query))
+            {
+                assert!(
+                    !run(&tampered.hints),
+                    "the verifier accepts a tampered {} ({:?})",
+                    tampered.name,
+                    tampered.step
+                );
+            }
+        }
     }
 }
@@ -1,29 +1,53 @@
+use crate::bitcoin_script::assert_valid_config;
+use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+use crate::VerifierConfig;
 use bitcoin_circle_stark::constraints::ConstraintsGadget;
 use bitcoin_circle_stark::treepp::*;
 use bitcoin_scriptexec::{profiler_end, profiler_start};
 use rust_bitcoin_m31::{
     qm31_dup, qm31_fromaltstack, qm31_mul, qm31_over, qm31_square, qm31_toaltstack,
 };
-use stwo_prover::core::prover::N_QUERIES;
-
-const FIB_LOG_SIZE: u32 = 5;
 
 /// Prepare Gadget
 pub struct FibonacciPrepareGadget;
 
 impl FibonacciPrepareGadget {
-    pub fn run() -> Script {
+    pub fn run(log_size: u32, config: VerifierConfig) -> Script {
+        assert_valid_config(config);
+
+        let after_fiat_shamir = StackLayout::fiat_shamir_output(log_size, config);
+        let after_trace = after_fiat_shamir
+            .clone()
+            .without(StackRegion::TraceOodsValues)
+            .with(StackRegion::TraceColumnLineCoeffs, 3 * 2 * 2);
+        let after_composition = after_trace
+            .clone()
+            .without(StackRegion::CompositionOodsRawValues)
+            .with(StackRegion::CompositionColumnLineCoeffs, 4 * 2 * 2);
+        let after_pair_vanishing = after_composition
+            .clone()
+            .with(StackRegion::PreparedMaskedPoints, 3 * 4)
+            .with(StackRegion::PreparedOodsPoint, 4);
+
+        // the masked points and the oods point, in the order that they are prepared
+        let points = [
+            after_composition.offset(StackRegion::MaskedPoints, 0),
+            after_composition.offset(StackRegion::MaskedPoints, 8),
+            after_composition.offset(StackRegion::MaskedPoints, 16),
+            after_composition.bottom(StackRegion::OodsPoint),
+        ];
+
         script! {
             // stack:
             //    trace oods values (3 * 4 = 12)
             //    composition odds raw values (4 * 4 = 16)
             //    random_coeff2 (4)
             //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
             //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            //    queries (n_queries)
+            //    trace queries (2 * n_queries)
+            //    composition queries (8 * n_queries)
             //    masked points (3 * 8 = 24)
             //    oods point (8)
 
@@ -31,12 +55,14 @@ impl FibonacciPrepareGadget {
             // - input: p.y, f1(p)
             // - output: a1, b1
 
+            // the (a, b) of the previous columns (4 * i) are on top of the layout, and the trace oods
+            // values of the previous columns have been consumed from the bottom of the region
             for i in 0..3 {
                 for _ in 0..4 {
-                    { i * 4 + 8 + (16 - 8 * i) + 4 - 1 } OP_PICK
+                    { after_fiat_shamir.offset(StackRegion::MaskedPoints, 8 * i + 4) + 4 * i } OP_PICK
                 }
                 for _ in 0..4 {
-                    { i * 4 + 4 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + (8 - 4 * i) + 4 - 1 } OP_ROLL
+                    { after_fiat_shamir.offset(StackRegion::TraceOodsValues, 4 * i) + 4 * i + 4 } OP_ROLL
                 }
                 { profiler_start("column line coeffs for trace") }
                 { ConstraintsGadget::column_line_coeffs_with_hint(1) }
@@ -47,11 +73,11 @@ impl FibonacciPrepareGadget {
             //    composition odds raw values (4 * 4 = 16)
             //    random_coeff2 (4)
             //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
             //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            //    queries (n_queries)
+            //    trace queries (2 * n_queries)
+            //    composition queries (8 * n_queries)
             //    masked points (3 * 8 = 24)
             //    oods point (8)
             //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
@@ -61,10 +87,10 @@ impl FibonacciPrepareGadget {
             // - output: a1, b1, a2, b2, a3, b3, a4, b4
 
             for _ in 0..4 {
-                { 12 + 4 - 1 } OP_PICK
+                { after_trace.offset(StackRegion::OodsPoint, 4) } OP_PICK
             }
             for _ in 0..16 {
-                { 4 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 - 1 } OP_ROLL
+                { after_trace.bottom(StackRegion::CompositionOodsRawValues) + 4 } OP_ROLL
             }
 
             { profiler_start("column line coeffs for composition") }
@@ -74,11 +100,11 @@ impl FibonacciPrepareGadget {
             // stack:
             //    random_coeff2 (4)
             //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
             //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
+            //    queries (n_queries)
+            //    trace queries (2 * n_queries)
+            //    composition queries (8 * n_queries)
             //    masked points (3 * 8 = 24)
             //    oods point (8)
             //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
@@ -87,7 +113,7 @@ impl FibonacciPrepareGadget {
             // prepare masked points and oods point for pair vanishing
             for i in 0..4 {
                 for _ in 0..8 {
-                    { 16 + 12 + 4 * i + (8 + 24) - 8 * i - 1 } OP_PICK
+                    { points[i] + 4 * i } OP_PICK
                 }
                 { profiler_start("prepare pair vanishing") }
                 { ConstraintsGadget::prepare_pair_vanishing_with_hint() }
@@ -96,7 +122,7 @@ impl FibonacciPrepareGadget {
 
             // move random_coeffs2 closer
             for _ in 0..4 {
-                { 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 - 1 } OP_ROLL
+                { after_pair_vanishing.bottom(StackRegion::RandomCoeff2) } OP_ROLL
             }
 
             { profiler_start("compute coeff power sequence") }
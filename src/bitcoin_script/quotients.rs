@@ -1,4 +1,6 @@
-use crate::bitcoin_script::FIB_LOG_SIZE;
+use crate::bitcoin_script::assert_valid_config;
+use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+use crate::VerifierConfig;
 use bitcoin_circle_stark::constraints::ConstraintsGadget;
 use bitcoin_circle_stark::precomputed_merkle_tree::{
     get_precomputed_merkle_tree_roots, PrecomputedMerkleTreeGadget, PRECOMPUTED_MERKLE_TREE_ROOTS,
@@ -9,107 +11,109 @@ use rust_bitcoin_m31::{
     cm31_add, cm31_fromaltstack, cm31_mul, cm31_roll, cm31_toaltstack, qm31_add, qm31_fromaltstack,
     qm31_mul_cm31, qm31_rot, qm31_swap, qm31_toaltstack,
 };
-use stwo_prover::core::prover::{LOG_BLOWUP_FACTOR, N_QUERIES};
 
 pub struct FibonacciPerQueryQuotientGadget;
 
 impl FibonacciPerQueryQuotientGadget {
-    pub fn run(query_idx: usize) -> Script {
+    pub fn run(query_idx: usize, log_size: u32, config: VerifierConfig) -> Script {
+        assert_valid_config(config);
+
+        let log_blowup_factor = config.log_blowup_factor;
         let precomputed_merkle_tree_roots =
             PRECOMPUTED_MERKLE_TREE_ROOTS.get_or_init(get_precomputed_merkle_tree_roots);
+        let num_twiddles = (log_size + log_blowup_factor) as usize;
+
+        let prepare_layout = StackLayout::prepare_output(log_size, config);
+        let query_layout = prepare_layout
+            .clone()
+            .with(StackRegion::TwiddleFactors, num_twiddles)
+            .with(StackRegion::QueryPoint, 2);
+
+        // the layout when the `i`-th denominator inverse is being computed
+        let denominator_layout = |i: usize| {
+            query_layout
+                .clone()
+                .with(StackRegion::DenominatorInverses, 4 * i)
+        };
+
+        // the prepared masked points and the prepared oods point, in the order that they are used
+        let prepared_point = |i: usize| {
+            if i < 3 {
+                denominator_layout(i).offset(StackRegion::PreparedMaskedPoints, 4 * i)
+            } else {
+                denominator_layout(i).bottom(StackRegion::PreparedOodsPoint)
+            }
+        };
+
+        // the layout when the `num_inputs`-th input of `apply_twin` is being copied for the `i`-th
+        // column, from the trace and then from the composition
+        let twin_values_layout = query_layout
+            .clone()
+            .with(StackRegion::DenominatorInverses, 4 * 4)
+            .with(StackRegion::TraceTwinValues, 2)
+            .with(StackRegion::CompositionTwinValues, 8);
+        let apply_twin_layout = |i: usize, num_inputs: usize| {
+            twin_values_layout
+                .clone()
+                .with(StackRegion::Nominators, 4 * i)
+                .with(StackRegion::ApplyTwinInputs, num_inputs)
+        };
+
+        // the layout after all the nominators are computed
+        let mut nominators_layout = twin_values_layout
+            .clone()
+            .with(StackRegion::Nominators, 7 * 4);
+        let drop_twin_values = nominators_layout.offset(StackRegion::CompositionTwinValues, 8 - 1);
+        nominators_layout.remove(StackRegion::TraceTwinValues);
+        nominators_layout.remove(StackRegion::CompositionTwinValues);
+        let drop_query_point = nominators_layout.offset(StackRegion::QueryPoint, 1);
+
+        // the depth of coeff^k, below the given number of quotient terms
+        let coeff_power = |k: usize, num_terms: usize| {
+            prepare_layout
+                .clone()
+                .with(StackRegion::TwiddleFactors, num_twiddles)
+                .with(StackRegion::QuotientTerms, num_terms)
+                .offset(StackRegion::CoeffPowers, 4 * (6 - k))
+        };
 
         script! {
             // resolve the point and obtain its twiddle factors
-            { 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8) * N_QUERIES - query_idx + (N_QUERIES - 1) } OP_PICK
+            { prepare_layout.offset(StackRegion::Queries, query_idx) } OP_PICK
 
             { profiler_start("query precomputed merkle tree") }
-            { PrecomputedMerkleTreeGadget::query_and_verify(*precomputed_merkle_tree_roots.get(&(FIB_LOG_SIZE + LOG_BLOWUP_FACTOR)).unwrap(), (FIB_LOG_SIZE + LOG_BLOWUP_FACTOR + 1) as usize) }
+            { PrecomputedMerkleTreeGadget::query_and_verify(*precomputed_merkle_tree_roots.get(&(log_size + log_blowup_factor)).unwrap(), (log_size + log_blowup_factor + 1) as usize) }
             { profiler_end("query precomputed merkle tree") }
 
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    x, y (2)
-
             // compute the denominator inverses
             for i in 0..4 {
                 for _ in 0..4 {
-                    { 4 * i + 2 + 15 + (24 + 4 + 12) - 4 * i - 1 } OP_PICK // the prepared masked point
+                    { prepared_point(i) } OP_PICK // the prepared masked point
                 }
-                { 4 + 4 * i + 1 } OP_PICK { 4 + 4 * i + 1 } OP_PICK // x, y
+                for _ in 0..2 {
+                    { denominator_layout(i).with(StackRegion::PreparedPointCopy, 4).bottom(StackRegion::QueryPoint) } OP_PICK
+                } // x, y
 
                 { profiler_start("compute denominator inverse") }
                 { ConstraintsGadget::denominator_inverse_from_prepared() }
                 { profiler_end("compute denominator inverse") }
             }
 
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    x, y (2)
-            //    denominator inverses (4 * 4 = 16)
-
             // compute the nominator (before alpha)
             for _ in 0..2 {
-                (16 + 2 + 15 + 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8) * N_QUERIES - (2 * query_idx) - 1) OP_PICK // pick the trace queries
+                { denominator_layout(4).offset(StackRegion::TraceQueries, 2 * query_idx) } OP_PICK // pick the trace queries
             }
             for _ in 0..4 * 2 {
-                (2 + 16 + 2 + 15 + 24 + 4 + 12 + 16 + 12 + 8 + 24 + 8 * N_QUERIES - (8 * query_idx) - 1) OP_PICK // pick the composition queries
+                { denominator_layout(4).with(StackRegion::TraceTwinValues, 2).offset(StackRegion::CompositionQueries, 8 * query_idx) } OP_PICK // pick the composition queries
             }
 
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES)
-            //    composition queries (8 * N_QUERIES)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    x, y (2)
-            //    denominator inverses (4 * 4 = 16)
-            //    trace queries (2)
-            //    composition queries (8)
-
             for i in 0..3 {
-                { 4 * i + 8 + 2 + 16 } OP_PICK // copy y
-                { 1 + 4 * i + 8 + 2 - 1 } OP_PICK { 1 + 4 * i + 8 + 2 - 1 } OP_PICK // copy trace queries
+                { apply_twin_layout(i, 0).offset(StackRegion::QueryPoint, 1) } OP_PICK // copy y
+                { apply_twin_layout(i, 1).bottom(StackRegion::TraceTwinValues) } OP_PICK
+                { apply_twin_layout(i, 2).offset(StackRegion::TraceTwinValues, 1) } OP_PICK // copy trace queries
 
                 for _ in 0..4 {
-                    { 3 + 4 * i + 8 + 2 + 16 + 2 + 15 + 24 + 4 + 12 + 16 + (12 - 4 * i) - 1 } OP_PICK // copy (a, b)
+                    { apply_twin_layout(i, 3).offset(StackRegion::TraceColumnLineCoeffs, 4 * i) } OP_PICK // copy (a, b)
                 }
 
                 { profiler_start("apply column line coeffs") }
@@ -118,13 +122,13 @@ impl FibonacciPerQueryQuotientGadget {
             }
 
             for i in 0..4 {
-                { 4 * i + 12 + 8 + 2 + 16 } OP_PICK // copy y
-                { 1 + 4 * i + 12 + (8 - i) - 1 } OP_PICK
-                { 1 + 1 + 4 * i + 12 + (4 - i) - 1 } OP_PICK
+                { apply_twin_layout(3 + i, 0).offset(StackRegion::QueryPoint, 1) } OP_PICK // copy y
+                { apply_twin_layout(3 + i, 1).offset(StackRegion::CompositionTwinValues, i) } OP_PICK
+                { apply_twin_layout(3 + i, 2).offset(StackRegion::CompositionTwinValues, 4 + i) } OP_PICK
                 // copy composition queries
 
                 for _ in 0..4 {
-                    { 3 + 4 * i + 12 + 8 + 2 + 16 + 2 + 15 + 24 + 4 + 12 + (16 - 4 * i) - 1 } OP_PICK
+                    { apply_twin_layout(3 + i, 3).offset(StackRegion::CompositionColumnLineCoeffs, 4 * i) } OP_PICK
                 } // copy (a, b)
 
                 { profiler_start("apply column line coeffs") }
@@ -132,55 +136,15 @@ impl FibonacciPerQueryQuotientGadget {
                 { profiler_end("apply column line coeffs") }
             }
 
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES - 2 * 1)
-            //    composition queries (8 * N_QUERIES - 8 * 1)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    x, y (2)
-            //    denominator inverses (4 * 2 * 2 = 16)
-            //    trace queries (2)
-            //    composition queries (8)
-            //    nominators (7 * 2 * 2 = 28)
-
             // remove the trace queries and composition queries (unused)
             for _ in 0..(2 + 8) {
-                28 OP_ROLL OP_DROP
+                { drop_twin_values } OP_ROLL OP_DROP
             }
 
             // remove x, y (unused)
-            { 28 + 16 } OP_ROLL OP_DROP
-            { 28 + 16 } OP_ROLL OP_DROP
-
-            // stack:
-            //    circle_poly_alpha (4)
-            //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
-            //    last layer (4)
-            //    queries (N_QUERIES)
-            //    trace queries (2 * N_QUERIES - 2 * 1)
-            //    composition queries (8 * N_QUERIES - 8 * 1)
-            //    masked points (3 * 8 = 24)
-            //    oods point (8)
-            //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
-            //    (a, b), (a, b), (a, b), (a, b) for composition (4 * 2 * 2 = 16)
-            //    prepared masked points (3 * 4 = 12)
-            //    prepared oods point (4)
-            //    coeff^6, coeff^5, ..., coeff (6 * 4 = 24)
-            //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
-            //    denominator inverses (4 * 2 * 2 = 16)
-            //    nominators (7 * 2 * 2 = 28)
+            for _ in 0..2 {
+                { drop_query_point } OP_ROLL OP_DROP
+            }
 
 
             { profiler_start("aggregate different components of the quotient") }
@@ -235,19 +199,19 @@ impl FibonacciPerQueryQuotientGadget {
             //      v1 * b1 (cm31), v2 * b2 (cm31), v3 * b3 (cm31)
 
             for _ in 0..4 {
-                { (8 + 2) * 2 + 15 + 12 - 1 } OP_PICK
+                { coeff_power(3, (8 + 2) * 2) } OP_PICK
             } // copy coeff^3
             { cm31_roll(2 + 8 - 1) } // c1
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { (7 + 2) * 2 + 15 + 8 - 1 } OP_PICK
+                { coeff_power(2, (7 + 2) * 2) } OP_PICK
             } // copy coeff^2
             { cm31_roll(2 + 6 - 1) } // c2
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { (6 + 2) * 2 + 15 + 4 - 1 } OP_PICK
+                { coeff_power(1, (6 + 2) * 2) } OP_PICK
             } // copy coeff
             { cm31_roll(2 + 4 - 1) } // c3
             qm31_mul_cm31
@@ -272,19 +236,19 @@ impl FibonacciPerQueryQuotientGadget {
             //      (coeff^3 * c1 + coeff^2 * c2 + coeff * c3 + c4) * u4 (qm31)
 
             for _ in 0..4 {
-                { (4 + 1) * 2 + 15 + 12 - 1 } OP_PICK
+                { coeff_power(3, (4 + 1) * 2) } OP_PICK
             } // copy coeff^3
             { cm31_roll(2 + 4 - 1) } // d1
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { (3 + 1) * 2 + 15 + 8 - 1 } OP_PICK
+                { coeff_power(2, (3 + 1) * 2) } OP_PICK
             } // copy coeff^2
             { cm31_roll(2 + 3 - 1) } // d2
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { (2 + 1) * 2 + 15 + 4 - 1 } OP_PICK
+                { coeff_power(1, (2 + 1) * 2) } OP_PICK
             } // copy coeff
             { cm31_roll(2 + 2 - 1) } // d3
             qm31_mul_cm31
@@ -317,19 +281,19 @@ impl FibonacciPerQueryQuotientGadget {
             //      u3 * a3 (cm31), u2 * a2 (cm31), u1 * a1 (cm31)
 
             for _ in 0..4 {
-                { 20 + 15 + 24 - 1 } OP_PICK
+                { coeff_power(6, 20) } OP_PICK
             } // copy coeff^6
             { cm31_roll(2) } // u1 * a1
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { 18 + 15 + 20 - 1 } OP_PICK
+                { coeff_power(5, 18) } OP_PICK
             } // copy coeff^5
             { cm31_roll(2) } // u2 * a2
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { 16 + 15 + 16 - 1 } OP_PICK
+                { coeff_power(4, 16) } OP_PICK
             } // copy coeff^4
             { cm31_roll(2) } // u3 * a3
             qm31_mul_cm31
@@ -338,19 +302,19 @@ impl FibonacciPerQueryQuotientGadget {
             qm31_toaltstack
 
             for _ in 0..4 {
-                { 14 + 15 + 24 - 1 } OP_PICK
+                { coeff_power(6, 14) } OP_PICK
             } // copy coeff^6
             { cm31_roll(2) } // v1 * b1
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { 12 + 15 + 20 - 1 } OP_PICK
+                { coeff_power(5, 12) } OP_PICK
             } // copy coeff^5
             { cm31_roll(2) } // v2 * b2
             qm31_mul_cm31 qm31_toaltstack
 
             for _ in 0..4 {
-                { 10 + 15 + 16 - 1 } OP_PICK
+                { coeff_power(4, 10) } OP_PICK
             } // copy coeff^4
             { cm31_roll(2) } // v3 * b3
             qm31_mul_cm31
@@ -365,7 +329,7 @@ impl FibonacciPerQueryQuotientGadget {
 
             // local stack (4 elements):
             //    ---------------------------- per query ----------------------------
-            //    twiddle factors (15)
+            //    twiddle factors (log_size + log_blowup_factor)
             //    answer_1 (qm31)
             //    answer_2 (qm31)
         }
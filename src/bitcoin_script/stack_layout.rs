@@ -0,0 +1,377 @@
+use crate::bitcoin_script::FibonacciClaimMode;
+use crate::VerifierConfig;
+use std::fmt::{Display, Formatter};
+
+/// A named region of the stack used by the verifier gadgets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackRegion {
+    /// The claim, only present in the public-input mode.
+    Claim,
+    /// The commitment of the trace Merkle tree.
+    TraceCommitment,
+    /// The commitment of the composition Merkle tree.
+    CompositionCommitment,
+    /// The trace OODS values.
+    TraceOodsValues,
+    /// The composition OODS raw values.
+    CompositionOodsRawValues,
+    /// The composition OODS value, recombined from the raw values.
+    CompositionOodsValue,
+    /// The digest of the channel.
+    ChannelDigest,
+    /// The first random coefficient, used for evaluating the composition polynomial.
+    RandomCoeff,
+    /// The copy of the trace OODS values, used to evaluate the composition polynomial.
+    TraceOodsValuesCopy,
+    /// The copy of the OODS point, used to evaluate the composition polynomial.
+    OodsPointCopy,
+    /// The copy of a commitment, against which a query is verified.
+    CommitmentCopy,
+    /// The second random coefficient, used for aggregating the FRI answers.
+    RandomCoeff2,
+    /// The alpha of the first FRI step.
+    CirclePolyAlpha,
+    /// The commitments and the folding alphas of the FRI layers.
+    FriCommitmentsAndAlphas,
+    /// The last layer of FRI.
+    LastLayer,
+    /// The queries.
+    Queries,
+    /// The queried values of the trace.
+    TraceQueries,
+    /// The queried values of the composition.
+    CompositionQueries,
+    /// The masked points.
+    MaskedPoints,
+    /// The OODS point.
+    OodsPoint,
+    /// The column line coefficients (a, b) for the trace.
+    TraceColumnLineCoeffs,
+    /// The column line coefficients (a, b) for the composition.
+    CompositionColumnLineCoeffs,
+    /// The masked points prepared for pair vanishing.
+    PreparedMaskedPoints,
+    /// The OODS point prepared for pair vanishing.
+    PreparedOodsPoint,
+    /// The powers of random_coeff2, i.e., coeff^6, coeff^5, ..., coeff.
+    CoeffPowers,
+    /// The twiddle factors of the current query.
+    TwiddleFactors,
+    /// The point (x, y) of the current query.
+    QueryPoint,
+    /// The denominator inverses of the current query.
+    DenominatorInverses,
+    /// The copy of a prepared point, used to compute a denominator inverse.
+    PreparedPointCopy,
+    /// The queried values of the trace for the current query and its twin.
+    TraceTwinValues,
+    /// The queried values of the composition for the current query and its twin.
+    CompositionTwinValues,
+    /// The copies of y, the twin values, and the column line coefficients, which are the inputs
+    /// of applying the column line coefficients.
+    ApplyTwinInputs,
+    /// The nominators of the current query.
+    Nominators,
+    /// The terms of the quotients that are being aggregated.
+    QuotientTerms,
+    /// The answers of the current query and its twin.
+    Answers,
+    /// The values of the current query and its twin in a FRI layer.
+    LayerTwinValues,
+    /// The folded value of the current query.
+    FoldedValue,
+}
+
+impl Display for StackRegion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            StackRegion::Claim => "claim",
+            StackRegion::TraceCommitment => "trace commitment",
+            StackRegion::CompositionCommitment => "composition commitment",
+            StackRegion::TraceOodsValues => "trace oods values",
+            StackRegion::CompositionOodsRawValues => "composition oods raw values",
+            StackRegion::CompositionOodsValue => "composition oods value",
+            StackRegion::ChannelDigest => "channel_digest",
+            StackRegion::RandomCoeff => "random_coeff",
+            StackRegion::TraceOodsValuesCopy => "copy of trace oods values",
+            StackRegion::OodsPointCopy => "copy of oods point",
+            StackRegion::CommitmentCopy => "copy of commitment",
+            StackRegion::RandomCoeff2 => "random_coeff2",
+            StackRegion::CirclePolyAlpha => "circle_poly_alpha",
+            StackRegion::FriCommitmentsAndAlphas => "(commitment, alpha), ..., (commitment, alpha)",
+            StackRegion::LastLayer => "last layer",
+            StackRegion::Queries => "queries",
+            StackRegion::TraceQueries => "trace queries",
+            StackRegion::CompositionQueries => "composition queries",
+            StackRegion::MaskedPoints => "masked points",
+            StackRegion::OodsPoint => "oods point",
+            StackRegion::TraceColumnLineCoeffs => "(a, b), (a, b), (a, b) for trace",
+            StackRegion::CompositionColumnLineCoeffs => {
+                "(a, b), (a, b), (a, b), (a, b) for composition"
+            }
+            StackRegion::PreparedMaskedPoints => "prepared masked points",
+            StackRegion::PreparedOodsPoint => "prepared oods point",
+            StackRegion::CoeffPowers => "coeff^6, coeff^5, ..., coeff",
+            StackRegion::TwiddleFactors => "twiddle factors",
+            StackRegion::QueryPoint => "x, y",
+            StackRegion::DenominatorInverses => "denominator inverses",
+            StackRegion::PreparedPointCopy => "copy of prepared point",
+            StackRegion::TraceTwinValues => "trace twin values",
+            StackRegion::CompositionTwinValues => "composition twin values",
+            StackRegion::ApplyTwinInputs => "y, twin values, (a, b)",
+            StackRegion::Nominators => "nominators",
+            StackRegion::QuotientTerms => "quotient terms",
+            StackRegion::Answers => "answer_1, answer_2",
+            StackRegion::LayerTwinValues => "twin values of the layer",
+            StackRegion::FoldedValue => "folded value",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A symbolic layout of the stack, as a list of named regions from the bottom to the top.
+///
+/// The gadgets derive the depths for `OP_PICK` and `OP_ROLL` from the layout, instead of adding
+/// up the sizes of the regions by hand.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackLayout {
+    regions: Vec<(StackRegion, usize)>,
+}
+
+impl StackLayout {
+    /// Create an empty layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The layout of the stack after the Fiat-Shamir gadget, excluding the claim.
+    pub fn fiat_shamir_output(log_size: u32, config: VerifierConfig) -> Self {
+        let n_queries = config.n_queries;
+
+        Self::new()
+            .with(StackRegion::TraceOodsValues, 3 * 4)
+            .with(StackRegion::CompositionOodsRawValues, 4 * 4)
+            .with(StackRegion::RandomCoeff2, 4)
+            .with(StackRegion::CirclePolyAlpha, 4)
+            .with(
+                StackRegion::FriCommitmentsAndAlphas,
+                (1 + 4) * log_size as usize,
+            )
+            .with(StackRegion::LastLayer, 4)
+            .with(StackRegion::Queries, n_queries)
+            .with(StackRegion::TraceQueries, 2 * n_queries)
+            .with(StackRegion::CompositionQueries, 8 * n_queries)
+            .with(StackRegion::MaskedPoints, 3 * 8)
+            .with(StackRegion::OodsPoint, 8)
+    }
+
+    /// The layout of the stack after the prepare gadget, excluding the claim.
+    ///
+    /// This layout is kept by the per-query gadgets.
+    pub fn prepare_output(log_size: u32, config: VerifierConfig) -> Self {
+        Self::fiat_shamir_output(log_size, config)
+            .without(StackRegion::TraceOodsValues)
+            .with(StackRegion::TraceColumnLineCoeffs, 3 * 2 * 2)
+            .without(StackRegion::CompositionOodsRawValues)
+            .with(StackRegion::CompositionColumnLineCoeffs, 4 * 2 * 2)
+            .with(StackRegion::PreparedMaskedPoints, 3 * 4)
+            .with(StackRegion::PreparedOodsPoint, 4)
+            .without(StackRegion::RandomCoeff2)
+            .with(StackRegion::CoeffPowers, 6 * 4)
+    }
+
+    /// Add the claim at the bottom of the layout, which is omitted in the constant mode.
+    pub fn with_claim(mut self, claim: FibonacciClaimMode) -> Self {
+        assert!(self.position(StackRegion::Claim).is_none());
+        let claim_size = claim.num_stack_elements();
+        if claim_size > 0 {
+            self.regions.insert(0, (StackRegion::Claim, claim_size));
+        }
+        self
+    }
+
+    /// Push a region on top of the layout.
+    pub fn push(&mut self, region: StackRegion, size: usize) {
+        assert!(
+            self.position(region).is_none(),
+            "region {} is already in the stack layout",
+            region
+        );
+        self.regions.push((region, size));
+    }
+
+    /// Push a region on top of the layout, in the builder style.
+    pub fn with(mut self, region: StackRegion, size: usize) -> Self {
+        self.push(region, size);
+        self
+    }
+
+    /// Remove a region from the layout, returning the depth of its bottom element before removal.
+    pub fn remove(&mut self, region: StackRegion) -> usize {
+        let depth = self.bottom(region);
+        self.regions.remove(self.index(region));
+        depth
+    }
+
+    /// Remove a region from the layout, in the builder style.
+    pub fn without(mut self, region: StackRegion) -> Self {
+        self.remove(region);
+        self
+    }
+
+    /// Move a region to the top of the layout, returning the depth of its bottom element before
+    /// the move.
+    ///
+    /// Rolling the region to the top takes `size` times of `{ depth } OP_ROLL`.
+    pub fn roll(&mut self, region: StackRegion) -> usize {
+        let depth = self.bottom(region);
+        let entry = self.regions.remove(self.index(region));
+        self.regions.push(entry);
+        depth
+    }
+
+    /// The depth of the `idx`-th element of the region, counting from the bottom of the region,
+    /// which is the input to `OP_PICK` and `OP_ROLL`.
+    pub fn offset(&self, region: StackRegion, idx: usize) -> usize {
+        let index = self.index(region);
+        let size = self.regions[index].1;
+        assert!(
+            idx < size,
+            "element {} is out of the region {} of size {}",
+            idx,
+            region,
+            size
+        );
+
+        let above: usize = self.regions[index + 1..].iter().map(|(_, size)| size).sum();
+        above + size - 1 - idx
+    }
+
+    /// The position of the `idx`-th QM31 element of the region, counting from the bottom of the
+    /// region, which is the input to `qm31_copy` and `qm31_roll`.
+    pub fn qm31_offset(&self, region: StackRegion, idx: usize) -> usize {
+        let depth = self.offset(region, 4 * idx + 3);
+        assert_eq!(
+            depth % 4,
+            0,
+            "the QM31 element {} of the region {} is not aligned",
+            idx,
+            region
+        );
+        depth / 4
+    }
+
+    /// The depth of the bottom element of the region.
+    pub fn bottom(&self, region: StackRegion) -> usize {
+        self.offset(region, 0)
+    }
+
+    /// The total number of elements in the layout.
+    pub fn len(&self) -> usize {
+        self.regions.iter().map(|(_, size)| size).sum()
+    }
+
+    fn position(&self, region: StackRegion) -> Option<usize> {
+        self.regions.iter().position(|(r, _)| *r == region)
+    }
+
+    fn index(&self, region: StackRegion) -> usize {
+        self.position(region)
+            .unwrap_or_else(|| panic!("region {} is not in the stack layout", region))
+    }
+}
+
+impl Display for StackLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (region, size) in self.regions.iter() {
+            writeln!(f, "{} ({})", region, size)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+    use crate::{FibonacciClaimMode, VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE};
+
+    #[test]
+    fn test_layout_sizes() {
+        for (log_size, n_queries) in [(FIB_LOG_SIZE, 8), (6, 4), (10, 12)] {
+            let config = VerifierConfig {
+                n_queries,
+                ..VerifierConfig::default()
+            };
+            let log_size_usize = log_size as usize;
+
+            assert_eq!(
+                StackLayout::fiat_shamir_output(log_size, config).len(),
+                8 + 24 + (2 + 8 + 1) * n_queries + 4 + (1 + 4) * log_size_usize + 4 + 4 + 16 + 12
+            );
+            assert_eq!(
+                StackLayout::prepare_output(log_size, config).len(),
+                24 + 4
+                    + 12
+                    + 16
+                    + 12
+                    + 8
+                    + 24
+                    + (2 + 8 + 1) * n_queries
+                    + 4
+                    + (1 + 4) * log_size_usize
+                    + 4
+            );
+            assert_eq!(
+                StackLayout::prepare_output(log_size, config)
+                    .with_claim(FibonacciClaimMode::PublicInput)
+                    .len(),
+                StackLayout::prepare_output(log_size, config).len() + 1
+            );
+            assert_eq!(
+                StackLayout::prepare_output(log_size, config)
+                    .with_claim(FibonacciClaimMode::Constant(FIB_CLAIM))
+                    .len(),
+                StackLayout::prepare_output(log_size, config).len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_layout_offsets() {
+        let mut layout = StackLayout::new()
+            .with(StackRegion::TraceCommitment, 1)
+            .with(StackRegion::OodsPoint, 8)
+            .with(StackRegion::Queries, 3);
+
+        assert_eq!(layout.bottom(StackRegion::Queries), 2);
+        assert_eq!(layout.offset(StackRegion::Queries, 2), 0);
+        assert_eq!(layout.offset(StackRegion::OodsPoint, 4), 6);
+
+        assert_eq!(layout.roll(StackRegion::TraceCommitment), 11);
+        assert_eq!(layout.bottom(StackRegion::TraceCommitment), 0);
+
+        layout.push(StackRegion::TraceQueries, 6);
+        assert_eq!(layout.remove(StackRegion::TraceCommitment), 6);
+        assert_eq!(layout.len(), 8 + 3 + 6);
+
+        assert_eq!(
+            layout.to_string(),
+            "oods point (8)\nqueries (3)\ntrace queries (6)\n"
+        );
+
+        let layout = StackLayout::new()
+            .with(StackRegion::OodsPoint, 8)
+            .with(StackRegion::RandomCoeff, 4);
+        assert_eq!(layout.qm31_offset(StackRegion::OodsPoint, 0), 2);
+        assert_eq!(layout.qm31_offset(StackRegion::OodsPoint, 1), 1);
+        assert_eq!(layout.qm31_offset(StackRegion::RandomCoeff, 0), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_layout_out_of_region() {
+        StackLayout::new()
+            .with(StackRegion::OodsPoint, 8)
+            .offset(StackRegion::OodsPoint, 8);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::BroadcastError;
+use crate::rpc::{RpcClient, RpcError, RPC_IN_WARMUP, RPC_VERIFY_ALREADY_IN_CHAIN};
+use anyhow::Result;
+use bitcoin::{OutPoint, Transaction, Txid};
+use std::time::{Duration, Instant};
+
+/// The output of the caboose in the transactions of a covenant program.
+const CABOOSE_VOUT: u32 = 1;
+
+/// The options of broadcasting a transaction.
+#[derive(Clone, Debug)]
+pub struct BroadcastOptions {
+    /// The number of confirmations to wait for, where 0 waits for the mempool acceptance only.
+    pub confirmations: u64,
+    /// The time between two polls of the node, and before a retry.
+    pub poll_interval: Duration,
+    /// The number of retries after transient errors, i.e., when the node is unreachable or is
+    /// warming up.
+    pub max_retries: usize,
+    /// The time to wait for the confirmations before giving up, if any.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for BroadcastOptions {
+    fn default() -> Self {
+        Self {
+            confirmations: 0,
+            poll_interval: Duration::from_secs(10),
+            max_retries: 5,
+            timeout: None,
+        }
+    }
+}
+
+/// Whether the error may go away by retrying the same call.
+fn is_transient(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<RpcError>() {
+        Some(rpc_error) => rpc_error.code == RPC_IN_WARMUP,
+        None => err.downcast_ref::<std::io::Error>().is_some(),
+    }
+}
+
+/// Broadcast a transaction of a covenant program, and wait until it is in the mempool or has the
+/// confirmations.
+///
+/// A transaction that is already in the mempool or in a block is not an error, so a broadcast that
+/// stops halfway can be started again from the first transaction. A rejection by the node is
+/// returned as a `BroadcastError`, while the last transient error is returned once the retries
+/// run out.
+pub fn broadcast_tx(rpc: &RpcClient, tx: &Transaction, options: &BroadcastOptions) -> Result<Txid> {
+    let txid = tx.compute_txid();
+    let mut retries = 0;
+    let mut retry = |err: anyhow::Error| {
+        if is_transient(&err) && retries < options.max_retries {
+            retries += 1;
+            std::thread::sleep(options.poll_interval);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    };
+
+    loop {
+        let err = match rpc.send_raw_transaction(tx) {
+            Ok(_) => break,
+            Err(err) => err,
+        };
+        match err.downcast_ref::<RpcError>().cloned() {
+            Some(rpc_error) if rpc_error.code == RPC_VERIFY_ALREADY_IN_CHAIN => break,
+            Some(rpc_error) if rpc_error.code != RPC_IN_WARMUP => {
+                return Err(BroadcastError::Rejected {
+                    txid,
+                    code: rpc_error.code,
+                    message: rpc_error.message,
+                }
+                .into());
+            }
+            _ => retry(err)?,
+        }
+    }
+
+    // the caboose is never spent by the next transaction, so it has the confirmations of the
+    // transaction even after the chain moves on
+    let caboose = OutPoint {
+        txid,
+        vout: CABOOSE_VOUT,
+    };
+    let start = Instant::now();
+    loop {
+        match rpc.get_tx_out_confirmations(&caboose) {
+            Ok(Some(confirmations)) if confirmations >= options.confirmations => return Ok(txid),
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(BroadcastError::Dropped { txid }.into()),
+            Err(err) => {
+                retry(err)?;
+                continue;
+            }
+        }
+
+        if let Some(timeout) = options.timeout {
+            if start.elapsed() >= timeout {
+                return Err(BroadcastError::Timeout {
+                    txid,
+                    confirmations: options.confirmations,
+                }
+                .into());
+            }
+        }
+        std::thread::sleep(options.poll_interval);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::broadcast::{broadcast_tx, BroadcastOptions};
+    use crate::chain::test::build_chain;
+    use crate::error::BroadcastError;
+    use crate::mock_rpc::{serve, MockNode};
+    use crate::rpc::{RpcClient, RpcError, RPC_IN_WARMUP, RPC_VERIFY_REJECTED};
+    use bitcoin::Network;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_broadcast_tx() {
+        let (funding_tx, txs) = build_chain();
+
+        let node = Arc::new(Mutex::new(MockNode::new(Network::Regtest)));
+        node.lock().unwrap().add_to_mempool(funding_tx);
+        node.lock().unwrap().mine();
+        let rpc = RpcClient::new(serve(node.clone()), None);
+
+        let mut options = BroadcastOptions {
+            confirmations: 0,
+            poll_interval: Duration::from_millis(10),
+            max_retries: 3,
+            timeout: Some(Duration::from_millis(100)),
+        };
+
+        // the node warming up is retried
+        node.lock().unwrap().warmup_calls = 2;
+        let txid = broadcast_tx(&rpc, &txs[0], &options).unwrap();
+        assert_eq!(txid, txs[0].compute_txid());
+        assert!(node.lock().unwrap().mempool.contains(&txid));
+
+        // broadcasting again is fine
+        assert_eq!(broadcast_tx(&rpc, &txs[0], &options).unwrap(), txid);
+
+        // waiting for a confirmation
+        options.confirmations = 1;
+        node.lock().unwrap().auto_mine = true;
+        let txid = broadcast_tx(&rpc, &txs[1], &options).unwrap();
+        assert!(node.lock().unwrap().heights.contains_key(&txid));
+        assert_eq!(broadcast_tx(&rpc, &txs[1], &options).unwrap(), txid);
+
+        // no block is mined before the timeout
+        node.lock().unwrap().auto_mine = false;
+        let err = broadcast_tx(&rpc, &txs[2], &options).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BroadcastError>(),
+            Some(&BroadcastError::Timeout {
+                txid: txs[2].compute_txid(),
+                confirmations: 1,
+            })
+        );
+
+        // the rejection stops the broadcast
+        options.confirmations = 0;
+        let message = "mandatory-script-verify-flag-failed".to_string();
+        node.lock()
+            .unwrap()
+            .rejections
+            .insert(txs[3].compute_txid(), message.clone());
+        let err = broadcast_tx(&rpc, &txs[3], &options).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BroadcastError>(),
+            Some(&BroadcastError::Rejected {
+                txid: txs[3].compute_txid(),
+                code: RPC_VERIFY_REJECTED,
+                message,
+            })
+        );
+
+        // a transaction that skips a step spends an output that does not exist yet
+        let err = broadcast_tx(&rpc, &txs[4], &options).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BroadcastError>(),
+            Some(BroadcastError::Rejected { code: -25, .. })
+        ));
+
+        // the retries run out
+        node.lock().unwrap().rejections.clear();
+        node.lock().unwrap().warmup_calls = 10;
+        let err = broadcast_tx(&rpc, &txs[3], &options).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RpcError>()
+                .map(|rpc_error| rpc_error.code),
+            Some(RPC_IN_WARMUP)
+        );
+    }
+}
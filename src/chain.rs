@@ -0,0 +1,504 @@
+use crate::error::{ChainError, DecodeError};
+use bitcoin::hashes::Hash;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::{OP_PUSHBYTES_36, OP_RETURN};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::taproot::{ControlBlock, TAPROOT_ANNEX_PREFIX};
+use bitcoin::{OutPoint, ScriptBuf, TapLeafHash, Transaction, TxOut, Txid, WScriptHash};
+use bitcoin_scriptexec::{Exec, ExecCtx, Options, TxTemplate};
+use covenants_gadgets::{get_script_pub_key, CovenantProgram};
+use std::collections::HashMap;
+
+/// The script pub key of the caboose output, which commits to the state and the randomizer.
+pub fn caboose_script_pub_key<T: CovenantProgram>(state: &T::State, randomizer: u32) -> ScriptBuf {
+    let hash = T::get_hash(state);
+
+    let mut bytes = vec![OP_RETURN.to_u8(), OP_PUSHBYTES_36.to_u8()];
+    bytes.extend_from_slice(&hash);
+    bytes.extend_from_slice(&randomizer.to_le_bytes());
+
+    ScriptBuf::new_p2wsh(&WScriptHash::hash(&bytes))
+}
+
+/// The witness of the program input of a transaction of a covenant program, i.e., its first input,
+/// split into the elements of the covenant and the elements of the program.
+///
+/// The covenant pushes twelve elements, which are followed by the hint of the common prefix of the
+/// program (the old and new states), the input of the step, the script, and the control block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CovenantWitness {
+    /// The balance of the program before the transaction.
+    pub old_balance: u64,
+    /// The balance of the program after the transaction.
+    pub new_balance: u64,
+    /// The randomizer of the caboose of the previous transaction.
+    pub old_randomizer: u32,
+    /// The randomizer of the caboose of the transaction.
+    pub new_randomizer: u32,
+    /// The hash of the old state.
+    pub old_state_hash: Vec<u8>,
+    /// The hash of the new state.
+    pub new_state_hash: Vec<u8>,
+    /// The txid of the previous transaction.
+    pub old_txid: Txid,
+    /// The elements pushed by the program, which start with the hint of the common prefix.
+    pub program_elements: Vec<Vec<u8>>,
+    /// The script of the step.
+    pub script: ScriptBuf,
+    /// The control block, which commits the script to the output being spent.
+    pub control_block: ControlBlock,
+}
+
+impl CovenantWitness {
+    /// The number of elements pushed by the covenant.
+    pub const NUM_COVENANT_ELEMENTS: usize = 12;
+
+    const NEW_BALANCE: usize = 0;
+    const NEW_STATE_HASH: usize = 2;
+    const OLD_STATE_HASH: usize = 3;
+    const NEW_RANDOMIZER: usize = 4;
+    const OLD_TXID: usize = 5;
+    const OLD_BALANCE: usize = 6;
+    const OLD_RANDOMIZER: usize = 11;
+
+    /// Split the witness of the program input of the transaction.
+    pub fn from_tx(tx: &Transaction) -> Result<Self, DecodeError> {
+        let witness = tx
+            .input
+            .first()
+            .ok_or(DecodeError::MissingInput)?
+            .witness
+            .to_vec();
+
+        // the covenant elements, the script, and the control block
+        if witness.len() < Self::NUM_COVENANT_ELEMENTS + 2 {
+            return Err(DecodeError::UnexpectedWitnessLength { len: witness.len() });
+        }
+
+        let fixed = |index: usize, len: usize| {
+            if witness[index].len() == len {
+                Ok(witness[index].as_slice())
+            } else {
+                Err(DecodeError::MalformedElement { index })
+            }
+        };
+        let read_u64 = |index: usize| {
+            Ok::<_, DecodeError>(u64::from_le_bytes(fixed(index, 8)?.try_into().unwrap()))
+        };
+        let read_u32 = |index: usize| {
+            Ok::<_, DecodeError>(u32::from_le_bytes(fixed(index, 4)?.try_into().unwrap()))
+        };
+
+        Ok(Self {
+            old_balance: read_u64(Self::OLD_BALANCE)?,
+            new_balance: read_u64(Self::NEW_BALANCE)?,
+            old_randomizer: read_u32(Self::OLD_RANDOMIZER)?,
+            new_randomizer: read_u32(Self::NEW_RANDOMIZER)?,
+            old_state_hash: fixed(Self::OLD_STATE_HASH, 32)?.to_vec(),
+            new_state_hash: fixed(Self::NEW_STATE_HASH, 32)?.to_vec(),
+            old_txid: Txid::from_byte_array(fixed(Self::OLD_TXID, 32)?.try_into().unwrap()),
+            program_elements: witness[Self::NUM_COVENANT_ELEMENTS..witness.len() - 2].to_vec(),
+            script: ScriptBuf::from_bytes(witness[witness.len() - 2].clone()),
+            control_block: ControlBlock::decode(&witness[witness.len() - 1]).map_err(|_| {
+                DecodeError::MalformedElement {
+                    index: witness.len() - 1,
+                }
+            })?,
+        })
+    }
+}
+
+/// Validate a chain of transactions of the program as a node would, where the funding transaction
+/// pays the program in its first output, and each transaction spends the program output of the
+/// previous one in its first input.
+///
+/// The program input is executed with the transaction that it belongs to and the outputs that it
+/// spends, so that the signatures, which the covenant relies on to introspect the transaction, are
+/// checked against the actual sighash. The other inputs, such as deposits, are signed by their own
+/// keys, so they are not executed, but the outputs that they spend must be in the chain or in the
+/// extra prevouts, since the sighash of the program input commits to them.
+pub fn validate_chain<T: CovenantProgram>(
+    funding_tx: &Transaction,
+    txs: &[Transaction],
+    extra_prevouts: &HashMap<OutPoint, TxOut>,
+) -> Result<(), ChainError> {
+    if funding_tx.output.first().map(|txout| &txout.script_pubkey)
+        != Some(&get_script_pub_key::<T>())
+    {
+        return Err(ChainError::MissingProgramOutput);
+    }
+
+    let mut outputs = extra_prevouts.clone();
+    add_outputs(&mut outputs, funding_tx);
+
+    let mut expected = OutPoint {
+        txid: funding_tx.compute_txid(),
+        vout: 0,
+    };
+    for (tx_index, tx) in txs.iter().enumerate() {
+        let actual = tx
+            .input
+            .first()
+            .map(|txin| txin.previous_output)
+            .unwrap_or(OutPoint::null());
+        if actual != expected {
+            return Err(ChainError::NotSpendingProgram {
+                tx: tx_index,
+                expected,
+                actual,
+            });
+        }
+
+        let prevouts = tx
+            .input
+            .iter()
+            .enumerate()
+            .map(|(input, txin)| {
+                outputs
+                    .get(&txin.previous_output)
+                    .cloned()
+                    .ok_or(ChainError::UnknownPrevout {
+                        tx: tx_index,
+                        input,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        execute_input(tx, tx_index, 0, &prevouts)?;
+
+        add_outputs(&mut outputs, tx);
+        expected = OutPoint {
+            txid: tx.compute_txid(),
+            vout: 0,
+        };
+    }
+
+    Ok(())
+}
+
+fn add_outputs(outputs: &mut HashMap<OutPoint, TxOut>, tx: &Transaction) {
+    let txid = tx.compute_txid();
+    for (vout, txout) in tx.output.iter().enumerate() {
+        outputs.insert(
+            OutPoint {
+                txid,
+                vout: vout as u32,
+            },
+            txout.clone(),
+        );
+    }
+}
+
+/// Check that the input is a tapscript spend of the output, and execute its script.
+fn execute_input(
+    tx: &Transaction,
+    tx_index: usize,
+    input: usize,
+    prevouts: &[TxOut],
+) -> Result<(), ChainError> {
+    let mut exec = input_exec(tx, tx_index, input, prevouts, Options::default())?;
+    while exec.exec_next().is_ok() {}
+
+    let result = exec.result().unwrap();
+    if result.success {
+        Ok(())
+    } else {
+        Err(ChainError::ScriptFailure {
+            tx: tx_index,
+            input,
+            error: match &result.error {
+                Some(e) => format!("{:?}", e),
+                None => "the script does not leave true on the stack".to_string(),
+            },
+        })
+    }
+}
+
+/// Check that the input is a tapscript spend of the output, and set up the execution of its
+/// script with the given options, starting from the witness as a node would.
+pub(crate) fn input_exec(
+    tx: &Transaction,
+    tx_index: usize,
+    input: usize,
+    prevouts: &[TxOut],
+    options: Options,
+) -> Result<Exec, ChainError> {
+    let mut witness = tx.input[input].witness.to_vec();
+
+    // the annex is the last item starting with 0x50, if there are at least two items
+    let annex =
+        if witness.len() >= 2 && witness.last().unwrap().first() == Some(&TAPROOT_ANNEX_PREFIX) {
+            witness.pop()
+        } else {
+            None
+        };
+
+    let not_tapscript = ChainError::NotTapscriptSpend {
+        tx: tx_index,
+        input,
+    };
+    if witness.len() < 2 {
+        return Err(not_tapscript);
+    }
+    let control_block = ControlBlock::decode(&witness.pop().unwrap()).map_err(|_| not_tapscript)?;
+    let script = ScriptBuf::from_bytes(witness.pop().unwrap());
+
+    let script_pubkey = &prevouts[input].script_pubkey;
+    let output_key = if script_pubkey.is_p2tr() {
+        XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..34]).ok()
+    } else {
+        None
+    };
+    let committed = match output_key {
+        Some(output_key) => control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            output_key,
+            &script,
+        ),
+        None => false,
+    };
+    if !committed {
+        return Err(ChainError::InvalidTaprootCommitment {
+            tx: tx_index,
+            input,
+        });
+    }
+
+    let leaf_hash = TapLeafHash::from_script(&script, control_block.leaf_version);
+    Exec::new(
+        ExecCtx::Tapscript,
+        options,
+        TxTemplate {
+            tx: tx.clone(),
+            prevouts: prevouts.to_vec(),
+            input_idx: input,
+            taproot_annex_scriptleaf: Some((leaf_hash, annex)),
+        },
+        script,
+        witness,
+    )
+    .map_err(|e| ChainError::ScriptFailure {
+        tx: tx_index,
+        input,
+        error: format!("{:?}", e),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::chain::{caboose_script_pub_key, validate_chain, CovenantWitness};
+    use crate::error::{ChainError, DecodeError};
+    use crate::proof::test::test_proof;
+    use crate::serialization::SerializedVerifierHints;
+    use crate::split::{DefaultFibonacciSplitParameters, FibonacciSplitProgram};
+    use crate::{verify_with_hints, VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE};
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, WScriptHash, Witness,
+    };
+    use covenants_gadgets::{
+        get_script_pub_key, get_tx, CovenantInput, CovenantProgram, DepositInput, DUST_AMOUNT,
+    };
+    use std::collections::HashMap;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+
+    /// The fee of the transaction of a step at the fee rate, from the virtual size of the
+    /// transaction built by `get_tx`, which does not depend on the txids and the balances.
+    pub(crate) fn fee_of_step<T: CovenantProgram>(
+        program_index: usize,
+        old_state: &T::State,
+        input: &T::Input,
+        fee_rate: u64,
+    ) -> u64 {
+        let new_state = T::run(program_index, old_state, input).unwrap();
+        let info = CovenantInput {
+            old_randomizer: 12,
+            old_balance: 100_000_000,
+            old_txid: Txid::all_zeros(),
+            input_outpoint1: OutPoint::null(),
+            input_outpoint2: None,
+            optional_deposit_input: None,
+            new_balance: 100_000_000 - DUST_AMOUNT,
+        };
+        let (tx_template, _) = get_tx::<T>(&info, program_index, old_state, &new_state, input);
+        tx_template.tx.vsize() as u64 * fee_rate
+    }
+
+    /// Build a funding transaction of the program, with a dummy input and the caboose of the initial
+    /// state with the randomizer 12.
+    pub(crate) fn funding_tx<T: CovenantProgram>(initial_balance: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: Txid::all_zeros(),
+                    vout: 0,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(initial_balance),
+                    script_pubkey: get_script_pub_key::<T>(),
+                },
+                TxOut {
+                    value: Amount::from_sat(DUST_AMOUNT),
+                    script_pubkey: caboose_script_pub_key::<T>(&T::new(), 12),
+                },
+            ],
+        }
+    }
+
+    /// Build the funding transaction and the transactions of all the steps of the default program.
+    pub(crate) fn build_chain() -> (Transaction, Vec<Transaction>) {
+        build_chain_with_deposit(None)
+    }
+
+    /// Build the funding transaction and the transactions of all the steps of the default program,
+    /// where the transaction of the given step also spends the deposit, as its second input.
+    fn build_chain_with_deposit(
+        deposit: Option<(usize, OutPoint, TxOut)>,
+    ) -> (Transaction, Vec<Transaction>) {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+        let hints = SerializedVerifierHints::from(
+            &verify_with_hints(proof, &fib.air, verifier_config).unwrap(),
+        );
+
+        let initial_balance = 10000000;
+        let funding_tx = funding_tx::<Program>(initial_balance);
+        let funding_outpoint = funding_tx.input[0].previous_output;
+
+        let mut old_state = Program::new();
+        let mut info = CovenantInput {
+            old_randomizer: 12,
+            old_balance: initial_balance,
+            old_txid: funding_tx.compute_txid(),
+            input_outpoint1: funding_outpoint,
+            input_outpoint2: None,
+            optional_deposit_input: None,
+            new_balance: 0,
+        };
+
+        let mut txs = vec![];
+        for step in 0..Program::num_steps() {
+            let input = Program::input_for_state(&old_state, None, &hints);
+            let new_state = Program::run(old_state.pc, &old_state, &input).unwrap();
+
+            info.optional_deposit_input = match &deposit {
+                Some((deposit_step, outpoint, txout)) if *deposit_step == step => {
+                    Some(DepositInput {
+                        outpoint: *outpoint,
+                        script_pubkey: txout.script_pubkey.clone(),
+                        amount: txout.value,
+                    })
+                }
+                _ => None,
+            };
+            let deposit_amount = match &info.optional_deposit_input {
+                Some(deposit_input) => deposit_input.amount.to_sat(),
+                None => 0,
+            };
+
+            info.new_balance = info.old_balance + deposit_amount - 100000 - DUST_AMOUNT;
+            let (tx_template, randomizer) =
+                get_tx::<Program>(&info, old_state.pc, &old_state, &new_state, &input);
+
+            info.old_randomizer = randomizer;
+            info.old_balance = info.new_balance;
+            info.old_txid = tx_template.tx.compute_txid();
+            info.input_outpoint1 = tx_template.tx.input[0].previous_output;
+            info.input_outpoint2 = tx_template.tx.input.get(1).map(|txin| txin.previous_output);
+            old_state = new_state;
+
+            txs.push(tx_template.tx);
+        }
+
+        (funding_tx, txs)
+    }
+
+    #[test]
+    fn test_validate_chain() {
+        let (funding_tx, txs) = build_chain();
+        validate_chain::<Program>(&funding_tx, &txs, &HashMap::new()).unwrap();
+
+        // a tampered hint fails the script of that transaction, while the txids stay the same
+        let mut tampered_txs = txs.clone();
+        let mut witness = tampered_txs[3].input[0].witness.to_vec();
+        witness[16].push(1);
+        tampered_txs[3].input[0].witness = Witness::from_slice(&witness);
+        assert!(matches!(
+            validate_chain::<Program>(&funding_tx, &tampered_txs, &HashMap::new()),
+            Err(ChainError::ScriptFailure {
+                tx: 3,
+                input: 0,
+                ..
+            })
+        ));
+
+        // a transaction that skips a step does not spend the previous program output
+        assert!(matches!(
+            validate_chain::<Program>(&funding_tx, &txs[1..], &HashMap::new()),
+            Err(ChainError::NotSpendingProgram { tx: 0, .. })
+        ));
+
+        // the funding transaction must pay the program
+        let mut funding_tx_without_program = funding_tx.clone();
+        funding_tx_without_program.output.remove(0);
+        assert_eq!(
+            validate_chain::<Program>(&funding_tx_without_program, &txs, &HashMap::new()),
+            Err(ChainError::MissingProgramOutput)
+        );
+    }
+
+    #[test]
+    fn test_validate_chain_with_deposit() {
+        let deposit_outpoint = OutPoint {
+            txid: Txid::from_byte_array([1u8; 32]),
+            vout: 0,
+        };
+        let deposit = TxOut {
+            value: Amount::from_sat(50000),
+            script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::hash(&[])),
+        };
+        let (funding_tx, txs) =
+            build_chain_with_deposit(Some((2, deposit_outpoint, deposit.clone())));
+        assert_eq!(txs[2].input[1].previous_output, deposit_outpoint);
+
+        // the deposit is not executed, but the output that it spends must be known
+        assert_eq!(
+            validate_chain::<Program>(&funding_tx, &txs, &HashMap::new()),
+            Err(ChainError::UnknownPrevout { tx: 2, input: 1 })
+        );
+        let extra_prevouts = HashMap::from([(deposit_outpoint, deposit)]);
+        validate_chain::<Program>(&funding_tx, &txs, &extra_prevouts).unwrap();
+    }
+
+    #[test]
+    fn test_covenant_witness() {
+        let (funding_tx, txs) = build_chain();
+
+        let mut old_tx = &funding_tx;
+        for tx in txs.iter() {
+            let witness = CovenantWitness::from_tx(tx).unwrap();
+            assert_eq!(witness.old_txid, old_tx.compute_txid());
+            assert_eq!(witness.old_balance, old_tx.output[0].value.to_sat());
+            assert_eq!(witness.new_balance, tx.output[0].value.to_sat());
+            old_tx = tx;
+        }
+
+        // the funding transaction is not spent through the covenant
+        assert_eq!(
+            CovenantWitness::from_tx(&funding_tx),
+            Err(DecodeError::UnexpectedWitnessLength { len: 0 })
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use crate::{MAX_LOG_BLOWUP_FACTOR, MIN_LOG_BLOWUP_FACTOR};
+use bitcoin::{OutPoint, Txid};
+use std::fmt::{Display, Formatter};
+use stwo_prover::core::prover::VerificationError;
+
+/// An error from generating the hints, which indicates that the proof is malformed or invalid.
+#[derive(Debug)]
+pub enum HintError {
+    /// The configuration of the verifier is not supported.
+    InvalidConfig(ConfigError),
+    /// The proof is rejected by the verifier, e.g., the OODS values or the FRI layers are invalid.
+    Verification(VerificationError),
+    /// The proof does not have the commitments of the trace and the composition.
+    UnexpectedCommitments,
+    /// The sampled values are not laid out as expected by the Fibonacci AIR.
+    UnexpectedSampleLayout,
+    /// The queried values are not laid out as expected by the Fibonacci AIR.
+    UnexpectedQueriedValuesLayout,
+    /// A column of the trace (tree 0) or the composition (tree 1) does not have two queried
+    /// values, for the query and its twin, per query.
+    UnexpectedNumQueriedValues {
+        /// The index of the tree.
+        tree: usize,
+        /// The number of queried values for the queries.
+        expected: usize,
+        /// The number of queried values found.
+        actual: usize,
+    },
+    /// The proof does not have the decommitments of the trace and the composition.
+    UnexpectedDecommitments,
+    /// The columns of the commitment scheme do not have the expected sizes.
+    UnexpectedColumnSizes,
+    /// The queries are not opened on the expected domain.
+    UnexpectedQueryDomain,
+    /// The number of queries does not match the configuration.
+    UnexpectedNumQueries {
+        /// The number of queries in the configuration.
+        expected: usize,
+        /// The number of queries found.
+        actual: usize,
+    },
+    /// The proof of work does not have enough trailing zeros.
+    ProofOfWork,
+    /// A Merkle twin proof of the trace (tree 0) or the composition (tree 1) fails to verify.
+    InvalidMerkleTwinProof {
+        /// The index of the tree.
+        tree: usize,
+        /// The index of the query.
+        query: usize,
+    },
+    /// The column line coefficients do not match the ones computed by stwo.
+    ColumnLineCoeffsMismatch,
+    /// The batch random coefficients do not match the ones computed by stwo.
+    RandomCoeffsMismatch,
+    /// The decommitment of a FRI layer does not have the expected number of values.
+    InvalidFriDecommitment {
+        /// The index of the FRI layer.
+        layer: usize,
+    },
+    /// A Merkle twin proof of a FRI layer fails to verify.
+    InvalidFriMerkleProof {
+        /// The index of the FRI layer.
+        layer: usize,
+        /// The index of the query.
+        query: usize,
+    },
+    /// The last layer does not have exactly one element.
+    UnexpectedLastLayerSize,
+    /// The result of folding does not match the last layer.
+    LastLayerMismatch,
+}
+
+impl Display for HintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HintError::InvalidConfig(e) => write!(f, "invalid configuration: {}", e),
+            HintError::Verification(e) => write!(f, "verification failed: {}", e),
+            HintError::UnexpectedCommitments => write!(f, "unexpected number of commitments"),
+            HintError::UnexpectedSampleLayout => write!(f, "unexpected layout of sampled values"),
+            HintError::UnexpectedQueriedValuesLayout => {
+                write!(f, "unexpected layout of queried values")
+            }
+            HintError::UnexpectedNumQueriedValues {
+                tree,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "unexpected number of queried values for tree {}: expected {}, found {}",
+                tree, expected, actual
+            ),
+            HintError::UnexpectedDecommitments => write!(f, "unexpected number of decommitments"),
+            HintError::UnexpectedColumnSizes => write!(f, "unexpected column sizes"),
+            HintError::UnexpectedQueryDomain => write!(f, "unexpected query domain"),
+            HintError::UnexpectedNumQueries { expected, actual } => write!(
+                f,
+                "unexpected number of queries: expected {}, found {}",
+                expected, actual
+            ),
+            HintError::ProofOfWork => write!(f, "proof of work verification failed"),
+            HintError::InvalidMerkleTwinProof { tree, query } => write!(
+                f,
+                "invalid Merkle twin proof for tree {} at query {}",
+                tree, query
+            ),
+            HintError::ColumnLineCoeffsMismatch => write!(f, "column line coefficients mismatch"),
+            HintError::RandomCoeffsMismatch => write!(f, "batch random coefficients mismatch"),
+            HintError::InvalidFriDecommitment { layer } => {
+                write!(f, "invalid FRI decommitment at layer {}", layer)
+            }
+            HintError::InvalidFriMerkleProof { layer, query } => write!(
+                f,
+                "invalid FRI Merkle proof at layer {} for query {}",
+                layer, query
+            ),
+            HintError::UnexpectedLastLayerSize => write!(f, "unexpected size of the last layer"),
+            HintError::LastLayerMismatch => write!(f, "last layer mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for HintError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HintError::InvalidConfig(e) => Some(e),
+            HintError::Verification(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VerificationError> for HintError {
+    fn from(e: VerificationError) -> Self {
+        HintError::Verification(e)
+    }
+}
+
+impl From<ConfigError> for HintError {
+    fn from(e: ConfigError) -> Self {
+        HintError::InvalidConfig(e)
+    }
+}
+
+/// An error from checking the configuration of the verifier, which the script cannot be built
+/// for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// There are no FRI queries.
+    NoQueries,
+    /// The log of the blowup factor is outside of the range that FRI supports.
+    LogBlowupFactorOutOfRange {
+        /// The log of the blowup factor.
+        log_blowup_factor: u32,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::NoQueries => write!(f, "the number of queries must be positive"),
+            ConfigError::LogBlowupFactorOutOfRange { log_blowup_factor } => write!(
+                f,
+                "the log blowup factor {} is not between {} and {}",
+                log_blowup_factor, MIN_LOG_BLOWUP_FACTOR, MAX_LOG_BLOWUP_FACTOR
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// An error from validating a chain of transactions of a covenant program, which indicates the
+/// first input that a node would reject.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// The funding transaction does not pay the program in its first output.
+    MissingProgramOutput,
+    /// The first input of a transaction does not spend the program output of the previous one.
+    NotSpendingProgram {
+        /// The index of the transaction in the chain, starting from 0.
+        tx: usize,
+        /// The outpoint of the program output of the previous transaction.
+        expected: OutPoint,
+        /// The outpoint spent by the input.
+        actual: OutPoint,
+    },
+    /// An input spends an output that is not part of the chain, so it cannot be executed.
+    UnknownPrevout {
+        /// The index of the transaction in the chain, starting from 0.
+        tx: usize,
+        /// The index of the input.
+        input: usize,
+    },
+    /// The witness of an input is not a tapscript spend.
+    NotTapscriptSpend {
+        /// The index of the transaction in the chain, starting from 0.
+        tx: usize,
+        /// The index of the input.
+        input: usize,
+    },
+    /// The control block of an input does not commit the script to the output being spent.
+    InvalidTaprootCommitment {
+        /// The index of the transaction in the chain, starting from 0.
+        tx: usize,
+        /// The index of the input.
+        input: usize,
+    },
+    /// The script of an input fails.
+    ScriptFailure {
+        /// The index of the transaction in the chain, starting from 0.
+        tx: usize,
+        /// The index of the input.
+        input: usize,
+        /// The error of the execution.
+        error: String,
+    },
+}
+
+impl Display for ChainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainError::MissingProgramOutput => {
+                write!(f, "the funding transaction does not pay the program")
+            }
+            ChainError::NotSpendingProgram {
+                tx,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "transaction {} spends {} instead of the program output {}",
+                tx, actual, expected
+            ),
+            ChainError::UnknownPrevout { tx, input } => write!(
+                f,
+                "input {} of transaction {} spends an output outside the chain",
+                input, tx
+            ),
+            ChainError::NotTapscriptSpend { tx, input } => write!(
+                f,
+                "input {} of transaction {} is not a tapscript spend",
+                input, tx
+            ),
+            ChainError::InvalidTaprootCommitment { tx, input } => write!(
+                f,
+                "the control block of input {} of transaction {} does not match the output",
+                input, tx
+            ),
+            ChainError::ScriptFailure { tx, input, error } => write!(
+                f,
+                "the script of input {} of transaction {} fails: {}",
+                input, tx, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// An error from decoding a transaction of a covenant program, which indicates that it is not
+/// produced by the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The transaction does not have any input.
+    MissingInput,
+    /// The witness does not have enough elements for the covenant and the states.
+    UnexpectedWitnessLength {
+        /// The number of elements in the witness.
+        len: usize,
+    },
+    /// An element of the witness is malformed.
+    MalformedElement {
+        /// The index of the element in the witness.
+        index: usize,
+    },
+    /// The script is not one of the scripts of the program, or the control block does not commit
+    /// it to the output of the program.
+    UnknownScript,
+    /// The program counter of the old state does not match the script.
+    ProgramCounterMismatch {
+        /// The program counter of the old state.
+        pc: usize,
+        /// The index of the script.
+        program_index: usize,
+    },
+    /// The old state or the new state does not match its hash in the covenant.
+    StateHashMismatch,
+    /// The stack carried by the old state is not found in the input.
+    StackNotFound,
+    /// The stack computed from the input does not match the stack hash of the new state.
+    StackHashMismatch,
+    /// The input has elements that the step does not consume.
+    UnexpectedHints {
+        /// The number of elements left.
+        len: usize,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::MissingInput => write!(f, "the transaction has no input"),
+            DecodeError::UnexpectedWitnessLength { len } => {
+                write!(f, "unexpected number of witness elements: {}", len)
+            }
+            DecodeError::MalformedElement { index } => {
+                write!(f, "malformed witness element {}", index)
+            }
+            DecodeError::UnknownScript => write!(f, "the script is not a script of the program"),
+            DecodeError::ProgramCounterMismatch { pc, program_index } => write!(
+                f,
+                "the program counter {} does not match the script {}",
+                pc, program_index
+            ),
+            DecodeError::StateHashMismatch => write!(f, "state hash mismatch"),
+            DecodeError::StackNotFound => write!(f, "the carried stack is not in the input"),
+            DecodeError::StackHashMismatch => write!(f, "stack hash mismatch"),
+            DecodeError::UnexpectedHints { len } => {
+                write!(f, "{} elements of the input are not consumed", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// An error from broadcasting a transaction, which is not resolved by retrying.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastError {
+    /// The node rejects the transaction.
+    Rejected {
+        /// The txid of the transaction.
+        txid: Txid,
+        /// The error code of the node.
+        code: i64,
+        /// The reason given by the node.
+        message: String,
+    },
+    /// The transaction is accepted, but then disappears from the mempool before its confirmation.
+    Dropped {
+        /// The txid of the transaction.
+        txid: Txid,
+    },
+    /// The transaction does not have the confirmations before the timeout.
+    Timeout {
+        /// The txid of the transaction.
+        txid: Txid,
+        /// The number of confirmations waited for.
+        confirmations: u64,
+    },
+}
+
+impl Display for BroadcastError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BroadcastError::Rejected {
+                txid,
+                code,
+                message,
+            } => write!(
+                f,
+                "the node rejects transaction {} with error {}: {}",
+                txid, code, message
+            ),
+            BroadcastError::Dropped { txid } => write!(
+                f,
+                "transaction {} is dropped from the mempool before its confirmation",
+                txid
+            ),
+            BroadcastError::Timeout {
+                txid,
+                confirmations,
+            } => write!(
+                f,
+                "transaction {} does not have {} confirmations before the timeout",
+                txid, confirmations
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BroadcastError {}
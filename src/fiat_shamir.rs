@@ -1,4 +1,6 @@
-use crate::sampled_values_to_mask;
+use crate::error::HintError;
+use crate::serialization::mirror;
+use crate::{sampled_values_to_mask, VerifierConfig};
 use bitcoin_circle_stark::air::CompositionHint;
 use bitcoin_circle_stark::channel::{ChannelWithHint, DrawHints};
 use bitcoin_circle_stark::fri::QueriesWithHint;
@@ -7,6 +9,7 @@ use bitcoin_circle_stark::oods::{OODSHint, OODS};
 use bitcoin_circle_stark::pow::PoWHint;
 use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use stwo_prover::core::air::accumulation::PointEvaluationAccumulator;
 use stwo_prover::core::air::ComponentProvers;
 use stwo_prover::core::air::{AirProver, Component};
@@ -16,15 +19,11 @@ use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::qm31::{SecureField, QM31};
 use stwo_prover::core::fields::secure_column::SECURE_EXTENSION_DEGREE;
 use stwo_prover::core::fri::{
-    get_opening_positions, CirclePolyDegreeBound, FriConfig, FriLayerVerifier,
-    FriVerificationError, FOLD_STEP,
+    get_opening_positions, CirclePolyDegreeBound, FriLayerVerifier, FriVerificationError, FOLD_STEP,
 };
-use stwo_prover::core::pcs::{CommitmentSchemeVerifier, PcsConfig, TreeVec};
+use stwo_prover::core::pcs::{CommitmentSchemeVerifier, TreeVec};
 use stwo_prover::core::poly::line::LineDomain;
-use stwo_prover::core::prover::{
-    StarkProof, VerificationError, LOG_BLOWUP_FACTOR, LOG_LAST_LAYER_DEGREE_BOUND, N_QUERIES,
-    PROOF_OF_WORK_BITS,
-};
+use stwo_prover::core::prover::{StarkProof, VerificationError};
 use stwo_prover::core::queries::{Queries, SparseSubCircleDomain};
 use stwo_prover::core::vcs::sha256_hash::{Sha256Hash, Sha256Hasher};
 use stwo_prover::core::vcs::sha256_merkle::{Sha256MerkleChannel, Sha256MerkleHasher};
@@ -32,49 +31,63 @@ use stwo_prover::core::{ColumnVec, InteractionElements, LookupValues};
 use stwo_prover::examples::fibonacci::air::FibonacciAir;
 use stwo_prover::trace_generation::AirTraceGenerator;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 /// Hints for performing the Fiat-Shamir transform until finalizing the queries.
 pub struct FiatShamirHints {
     /// Commitments from the proof.
+    #[serde(with = "mirror")]
     pub commitments: [Sha256Hash; 2],
 
     /// random_coeff comes from adding `proof.commitments[0]` to the channel.
+    #[serde(with = "mirror")]
     pub random_coeff_hint: DrawHints,
 
     /// OODS hint.
+    #[serde(with = "mirror")]
     pub oods_hint: OODSHint,
 
     /// trace oods values.
+    #[serde(with = "mirror")]
     pub trace_oods_values: [SecureField; 3],
 
     /// composition odds raw values.
+    #[serde(with = "mirror")]
     pub composition_oods_values: [SecureField; 4],
 
     /// Composition hint.
+    #[serde(with = "mirror")]
     pub composition_hint: CompositionHint,
 
     /// second random_coeff hint
+    #[serde(with = "mirror")]
     pub random_coeff_hint2: DrawHints,
 
     /// circle_poly_alpha hint
+    #[serde(with = "mirror")]
     pub circle_poly_alpha_hint: DrawHints,
 
     /// fri commit and hints for deriving the folding parameter
+    #[serde(with = "mirror")]
     pub fri_commitment_and_folding_hints: Vec<(Sha256Hash, DrawHints)>,
 
     /// last layer poly (assuming only one element)
+    #[serde(with = "mirror")]
     pub last_layer: QM31,
 
     /// PoW hint
+    #[serde(with = "mirror")]
     pub pow_hint: PoWHint,
 
     /// Query sampling hints
+    #[serde(with = "mirror")]
     pub queries_hints: DrawHints,
 
     /// Merkle proofs for the trace Merkle tree.
+    #[serde(with = "mirror")]
     pub merkle_proofs_traces: Vec<MerkleTreeTwinProof>,
 
     /// Merkle proofs for the composition Merkle tree.
+    #[serde(with = "mirror")]
     pub merkle_proofs_compositions: Vec<MerkleTreeTwinProof>,
 }
 
@@ -112,9 +125,15 @@ impl Pushable for FiatShamirHints {
 
 /// Fiat Shamir hints along with fri inputs
 pub struct FiatShamirOutput {
+    /// log size of the Fibonacci trace
+    pub log_size: u32,
+
     /// log blowup factor
     pub fri_log_blowup_factor: u32,
 
+    /// number of queries
+    pub n_queries: usize,
+
     /// log degree bound of column
     pub max_column_log_degree_bound: u32,
 
@@ -164,13 +183,67 @@ pub struct FiatShamirOutput {
     pub fri_commitment_and_folding_hints: Vec<(Sha256Hash, DrawHints)>,
 }
 
+/// Check that the proof has the commitments, the sampled values, the queried values, and the
+/// decommitments of the trace and the composition, laid out as expected by the Fibonacci AIR.
+pub(crate) fn check_proof_layout(proof: &StarkProof<Sha256MerkleHasher>) -> Result<(), HintError> {
+    if proof.commitments.len() != 2 {
+        return Err(HintError::UnexpectedCommitments);
+    }
+
+    // trace polynomials are evaluated on oods, oods+1, oods+2, and composition polynomials are
+    // evaluated on oods 4 times
+    let sampled_values = &proof.commitment_scheme_proof.sampled_values.0;
+    if sampled_values.len() != 2
+        || sampled_values[0].len() != 1
+        || sampled_values[0][0].len() != 3
+        || sampled_values[1].len() != 4
+        || sampled_values[1].iter().any(|column| column.len() != 1)
+    {
+        return Err(HintError::UnexpectedSampleLayout);
+    }
+
+    // the queried values are from one trace column and four composition columns
+    let queried_values = &proof.commitment_scheme_proof.queried_values.0;
+    if queried_values.len() != 2 || queried_values[0].len() != 1 || queried_values[1].len() != 4 {
+        return Err(HintError::UnexpectedQueriedValuesLayout);
+    }
+    if proof.commitment_scheme_proof.decommitments.len() != 2 {
+        return Err(HintError::UnexpectedDecommitments);
+    }
+
+    Ok(())
+}
+
+/// Check that each queried column has the values of the queries and their twins, given the number
+/// of distinct twins that are queried.
+pub(crate) fn check_num_queried_values(
+    queried_values: &[ColumnVec<Vec<M31>>],
+    num_queries_parents: usize,
+) -> Result<(), HintError> {
+    for (tree, columns) in queried_values.iter().enumerate() {
+        for column in columns.iter() {
+            if column.len() != 2 * num_queries_parents {
+                return Err(HintError::UnexpectedNumQueriedValues {
+                    tree,
+                    expected: 2 * num_queries_parents,
+                    actual: column.len(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Generate Fiat Shamir hints along with fri inputs
 pub fn compute_fiat_shamir_hints(
     proof: StarkProof<Sha256MerkleHasher>,
     channel: &mut Sha256Channel,
     air: &FibonacciAir,
-) -> Result<(FiatShamirOutput, FiatShamirHints), VerificationError> {
-    let config = PcsConfig::default();
+    verifier_config: VerifierConfig,
+) -> Result<(FiatShamirOutput, FiatShamirHints), HintError> {
+    let config = verifier_config.pcs_config();
+
+    check_proof_layout(&proof)?;
     // Read trace commitment.
     let mut commitment_scheme: CommitmentSchemeVerifier<Sha256MerkleChannel> =
         CommitmentSchemeVerifier::new(config);
@@ -230,12 +303,7 @@ pub fn compute_fiat_shamir_hints(
     // TODO(spapini): Save clone.
     let (trace_oods_values, composition_oods_value) =
         sampled_values_to_mask(air, proof.commitment_scheme_proof.sampled_values.clone())
-            .map_err(|_| {
-                VerificationError::InvalidStructure(
-                    "Unexpected sampled_values structure".to_string(),
-                )
-            })
-            .unwrap();
+            .map_err(|_| HintError::UnexpectedSampleLayout)?;
 
     let mut evaluation_accumulator = PointEvaluationAccumulator::new(random_coeff);
     air.component.evaluate_constraint_quotients_at_point(
@@ -248,7 +316,7 @@ pub fn compute_fiat_shamir_hints(
     let oods_value = evaluation_accumulator.finalize();
 
     if composition_oods_value != oods_value {
-        return Err(VerificationError::OodsNotMatching);
+        return Err(VerificationError::OodsNotMatching.into());
     }
 
     let composition_hint = CompositionHint {
@@ -279,7 +347,10 @@ pub fn compute_fiat_shamir_hints(
         .column_log_sizes()
         .zip_cols(&sampled_points)
         .map_cols(|(log_size, sampled_points)| {
-            vec![CirclePolyDegreeBound::new(log_size - LOG_BLOWUP_FACTOR); sampled_points.len()]
+            vec![
+                CirclePolyDegreeBound::new(log_size - verifier_config.log_blowup_factor);
+                sampled_points.len()
+            ]
         })
         .flatten_cols()
         .into_iter()
@@ -289,7 +360,7 @@ pub fn compute_fiat_shamir_hints(
         .collect_vec();
 
     // FRI commitment phase on OODS quotients.
-    let fri_config = FriConfig::new(LOG_LAST_LAYER_DEGREE_BOUND, LOG_BLOWUP_FACTOR, N_QUERIES);
+    let fri_config = verifier_config.pcs_config().fri_config;
 
     // from fri-verifier
     let max_column_bound = bounds[0];
@@ -332,25 +403,31 @@ pub fn compute_fiat_shamir_hints(
             proof,
         });
 
-        layer_bound = layer_bound
-            .fold(FOLD_STEP)
-            .ok_or(FriVerificationError::InvalidNumFriLayers)?;
+        layer_bound = layer_bound.fold(FOLD_STEP).ok_or(VerificationError::Fri(
+            FriVerificationError::InvalidNumFriLayers,
+        ))?;
         layer_domain = layer_domain.double();
     }
 
     if layer_bound.log_degree_bound != fri_config.log_last_layer_degree_bound {
-        return Err(VerificationError::Fri(
-            FriVerificationError::InvalidNumFriLayers,
-        ));
+        return Err(VerificationError::Fri(FriVerificationError::InvalidNumFriLayers).into());
+    }
+
+    // the script folds once per FRI layer, which is one per bit of the trace size
+    if folding_alphas.len() != air.component.log_size as usize {
+        return Err(VerificationError::Fri(FriVerificationError::InvalidNumFriLayers).into());
     }
 
     let last_layer_domain = layer_domain;
     let last_layer_poly = proof.commitment_scheme_proof.fri_proof.last_layer_poly;
 
     if last_layer_poly.len() > (1 << fri_config.log_last_layer_degree_bound) {
-        return Err(VerificationError::Fri(
-            FriVerificationError::LastLayerDegreeInvalid,
-        ));
+        return Err(VerificationError::Fri(FriVerificationError::LastLayerDegreeInvalid).into());
+    }
+
+    // the script assumes that the last layer has only one element
+    if last_layer_poly.len() != 1 {
+        return Err(HintError::UnexpectedLastLayerSize);
     }
 
     channel.mix_felts(&last_layer_poly);
@@ -358,13 +435,13 @@ pub fn compute_fiat_shamir_hints(
     let pow_hint = PoWHint::new(
         channel.digest,
         proof.commitment_scheme_proof.proof_of_work,
-        PROOF_OF_WORK_BITS,
+        verifier_config.pow_bits,
     );
 
     // Verify proof of work.
     channel.mix_nonce(proof.commitment_scheme_proof.proof_of_work);
-    if channel.trailing_zeros() < PROOF_OF_WORK_BITS {
-        return Err(VerificationError::ProofOfWork);
+    if channel.trailing_zeros() < verifier_config.pow_bits {
+        return Err(HintError::ProofOfWork);
     }
 
     let column_log_sizes = bounds
@@ -378,21 +455,30 @@ pub fn compute_fiat_shamir_hints(
 
     let fri_query_domains = get_opening_positions(&queries, &column_log_sizes);
 
-    assert_eq!(fri_query_domains.len(), 1);
+    if fri_query_domains.len() != 1 {
+        return Err(HintError::UnexpectedQueryDomain);
+    }
     let query_domain = fri_query_domains.first_key_value().unwrap();
-    assert_eq!(
-        *query_domain.0,
-        max_column_bound.log_degree_bound + fri_config.log_blowup_factor
-    );
+    if *query_domain.0 != max_column_bound.log_degree_bound + fri_config.log_blowup_factor {
+        return Err(HintError::UnexpectedQueryDomain);
+    }
 
     let queries_parents: Vec<usize> = query_domain
         .1
         .iter()
         .map(|subdomain| {
-            assert_eq!(subdomain.log_size, 1);
-            subdomain.coset_index
+            if subdomain.log_size == 1 {
+                Ok(subdomain.coset_index)
+            } else {
+                Err(HintError::UnexpectedQueryDomain)
+            }
         })
-        .collect();
+        .collect::<Result<_, _>>()?;
+
+    check_num_queried_values(
+        &proof.commitment_scheme_proof.queried_values.0,
+        queries_parents.len(),
+    )?;
 
     let merkle_proofs_traces = MerkleTreeTwinProof::from_stwo_proof(
         (max_column_bound.log_degree_bound + fri_config.log_blowup_factor) as usize,
@@ -407,23 +493,19 @@ pub fn compute_fiat_shamir_hints(
         &proof.commitment_scheme_proof.decommitments[1],
     );
 
-    for (&query, twin_proof) in queries_parents.iter().zip(merkle_proofs_traces.iter()) {
-        assert!(twin_proof.verify(
-            &proof.commitments[0],
-            (max_column_bound.log_degree_bound + fri_config.log_blowup_factor) as usize,
-            query << 1
-        ));
-    }
-
-    for (&query, twin_proof) in queries_parents
-        .iter()
-        .zip(merkle_proofs_compositions.iter())
+    for (tree, merkle_proofs) in [&merkle_proofs_traces, &merkle_proofs_compositions]
+        .into_iter()
+        .enumerate()
     {
-        assert!(twin_proof.verify(
-            &proof.commitments[1],
-            (max_column_bound.log_degree_bound + fri_config.log_blowup_factor) as usize,
-            query << 1
-        ));
+        for (&query, twin_proof) in queries_parents.iter().zip(merkle_proofs.iter()) {
+            if !twin_proof.verify(
+                &proof.commitments[tree],
+                (max_column_bound.log_degree_bound + fri_config.log_blowup_factor) as usize,
+                query << 1,
+            ) {
+                return Err(HintError::InvalidMerkleTwinProof { tree, query });
+            }
+        }
     }
 
     let mut queried_values_left = vec![];
@@ -474,7 +556,9 @@ pub fn compute_fiat_shamir_hints(
     };
 
     let fiat_shamir_output = FiatShamirOutput {
+        log_size: air.component.log_size,
         fri_log_blowup_factor: fri_config.log_blowup_factor,
+        n_queries: fri_config.n_queries,
         max_column_log_degree_bound: max_column_bound.log_degree_bound,
         column_log_sizes,
         commitment_scheme_column_log_sizes: commitment_scheme.column_log_sizes(),
@@ -1,20 +1,25 @@
+use crate::error::HintError;
 use crate::fiat_shamir::FiatShamirOutput;
 use crate::prepare::PrepareOutput;
 use crate::quotients::QuotientsOutput;
+use crate::serialization::mirror;
 use bitcoin_circle_stark::merkle_tree::MerkleTreeTwinProof;
 use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use stwo_prover::core::fft::ibutterfly;
 use stwo_prover::core::fields::m31::M31;
 use stwo_prover::core::fields::qm31::SecureField;
-use stwo_prover::core::fri::FriProof;
+use stwo_prover::core::fri::{FriProof, FriVerificationError};
+use stwo_prover::core::prover::VerificationError;
 use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleHasher;
 
 /// The hints for folding for each query.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PerQueryFoldHints {
     /// Merkle proofs for the commitments on intermediate folding results.
+    #[serde(with = "mirror")]
     pub twin_proofs: Vec<MerkleTreeTwinProof>,
 }
 
@@ -27,22 +32,60 @@ impl Pushable for PerQueryFoldHints {
     }
 }
 
+/// Fill in the siblings of the positions of a FRI layer that are not already known from the
+/// decommitment of the layer, in the order of the positions, where the decommitment must have
+/// exactly the missing values.
+///
+/// On failure, returns the position whose sibling is missing, or `None` if the decommitment has
+/// more values than missing.
+pub(crate) fn fill_fri_siblings(
+    values: &mut BTreeMap<usize, SecureField>,
+    evals_subset: &[SecureField],
+) -> Result<(), Option<usize>> {
+    let mut evals_subset = evals_subset.iter();
+
+    let positions = values.keys().copied().collect_vec();
+    for position in positions {
+        if !values.contains_key(&(position ^ 1)) {
+            let value = evals_subset.next().ok_or(Some(position))?;
+            values.insert(position ^ 1, *value);
+        }
+    }
+    if evals_subset.next().is_some() {
+        return Err(None);
+    }
+
+    Ok(())
+}
+
 /// Compute the hints for folding.
 pub fn compute_fold_hints(
     fri_proof: &FriProof<Sha256MerkleHasher>,
     fs_output: &FiatShamirOutput,
     prepare_output: &PrepareOutput,
     quotients_output: &QuotientsOutput,
-) -> Vec<PerQueryFoldHints> {
+) -> Result<Vec<PerQueryFoldHints>, HintError> {
     let mut layers = vec![];
 
     let num_fri_steps = fri_proof.inner_layers.len();
+    if num_fri_steps != fs_output.log_size as usize
+        || num_fri_steps != fs_output.folding_alphas.len()
+        || num_fri_steps != fs_output.fri_commitment_and_folding_hints.len()
+    {
+        return Err(VerificationError::Fri(FriVerificationError::InvalidNumFriLayers).into());
+    }
+    if quotients_output.fold_results.len() != fs_output.queries_parents.len() {
+        return Err(HintError::UnexpectedNumQueries {
+            expected: fs_output.queries_parents.len(),
+            actual: quotients_output.fold_results.len(),
+        });
+    }
 
     let mut queries_and_results = BTreeMap::new();
     for (&queries_parent, &value) in fs_output
         .queries_parents
         .iter()
-        .zip_eq(quotients_output.fold_results.iter())
+        .zip(quotients_output.fold_results.iter())
     {
         queries_and_results.insert(queries_parent, value);
     }
@@ -68,34 +111,35 @@ pub fn compute_fold_hints(
 
     let mut depth = prepare_output.precomputed_merkle_tree.layers.len() - 1;
 
-    for (((layer_twiddles, fri_layer_proof), &folding_alpha), twin_proofs_mut) in twiddles
+    for (layer, (((layer_twiddles, fri_layer_proof), &folding_alpha), twin_proofs_mut)) in twiddles
         .iter()
-        .zip_eq(fri_proof.inner_layers.iter())
-        .zip_eq(fs_output.folding_alphas.iter())
-        .zip_eq(twin_proofs.iter_mut())
+        .zip(fri_proof.inner_layers.iter())
+        .zip(fs_output.folding_alphas.iter())
+        .zip(twin_proofs.iter_mut())
+        .enumerate()
     {
-        let mut iter = fri_layer_proof.evals_subset.iter();
-
-        let mut queries_parent_sorted = queries_and_results.keys().copied().collect_vec();
-        queries_parent_sorted.dedup();
-        queries_parent_sorted.sort_unstable();
+        let queries_parent_sorted = queries_and_results.keys().copied().collect_vec();
 
-        for &queries_parent in queries_parent_sorted.iter() {
-            let sibling = queries_parent ^ 1;
-            if queries_and_results.get(&sibling).is_none() {
-                queries_and_results.insert(sibling, *iter.next().unwrap());
-            }
-        }
-        assert_eq!(iter.next(), None);
+        fill_fri_siblings(&mut queries_and_results, &fri_layer_proof.evals_subset)
+            .map_err(|_| HintError::InvalidFriDecommitment { layer })?;
 
         layers.push(queries_and_results.clone());
 
+        let value_at = |position: usize| {
+            queries_and_results
+                .get(&position)
+                .copied()
+                .ok_or(HintError::InvalidFriDecommitment { layer })
+        };
+
         let mut new_queries_and_results = BTreeMap::<usize, SecureField>::new();
 
         for &queries_parent in queries_parent_sorted.iter() {
-            let f_p = *queries_and_results.get(&queries_parent).unwrap();
-            let f_neg_p = *queries_and_results.get(&(queries_parent ^ 1)).unwrap();
-            let itwid = *layer_twiddles.get(&queries_parent).unwrap();
+            let f_p = value_at(queries_parent)?;
+            let f_neg_p = value_at(queries_parent ^ 1)?;
+            let itwid = *layer_twiddles
+                .get(&queries_parent)
+                .ok_or(HintError::UnexpectedQueryDomain)?;
 
             let (mut f0_px, mut f1_px) = if queries_parent % 2 == 0 {
                 (f_p, f_neg_p)
@@ -118,13 +162,13 @@ pub fn compute_fold_hints(
                 (queries_parent ^ 1, queries_parent)
             };
 
-            let f_p = *queries_and_results.get(&left).unwrap();
+            let f_p = value_at(left)?;
             values[0].push(f_p.0 .0);
             values[1].push(f_p.0 .1);
             values[2].push(f_p.1 .0);
             values[3].push(f_p.1 .1);
 
-            let f_neg_p = *queries_and_results.get(&right).unwrap();
+            let f_neg_p = value_at(right)?;
             values[0].push(f_neg_p.0 .0);
             values[1].push(f_neg_p.0 .1);
             values[2].push(f_neg_p.1 .0);
@@ -147,7 +191,9 @@ pub fn compute_fold_hints(
     }
 
     for (_, &v) in queries_and_results.iter() {
-        assert_eq!(v, fs_output.last_layer);
+        if v != fs_output.last_layer {
+            return Err(HintError::LastLayerMismatch);
+        }
     }
 
     let mut all_fold_hints = vec![];
@@ -156,8 +202,14 @@ pub fn compute_fold_hints(
         let mut idx = queries_parent;
         let mut proofs = vec![];
 
-        for layer_twin_proofs in twin_proofs.iter() {
-            proofs.push(layer_twin_proofs.get(&idx).unwrap().clone());
+        for (layer, layer_twin_proofs) in twin_proofs.iter().enumerate() {
+            let proof = layer_twin_proofs
+                .get(&idx)
+                .ok_or(HintError::InvalidFriMerkleProof {
+                    layer,
+                    query: queries_parent,
+                })?;
+            proofs.push(proof.clone());
             idx >>= 1;
         }
 
@@ -165,11 +217,17 @@ pub fn compute_fold_hints(
         let mut depth = prepare_output.precomputed_merkle_tree.layers.len() - 1;
         let mut idx = queries_parent;
 
-        for (proof, (commitment, _)) in proofs
+        for (layer, (proof, (commitment, _))) in proofs
             .iter()
             .zip(fs_output.fri_commitment_and_folding_hints.iter())
+            .enumerate()
         {
-            assert!(proof.verify(commitment, depth, (idx >> 1) << 1));
+            if !proof.verify(commitment, depth, (idx >> 1) << 1) {
+                return Err(HintError::InvalidFriMerkleProof {
+                    layer,
+                    query: queries_parent,
+                });
+            }
             depth -= 1;
             idx >>= 1;
         }
@@ -179,5 +237,5 @@ pub fn compute_fold_hints(
         });
     }
 
-    all_fold_hints
+    Ok(all_fold_hints)
 }
@@ -1,37 +1,127 @@
 pub(crate) mod bitcoin_script;
 
+/// Module for broadcasting the transactions through the RPC of a node.
+pub mod broadcast;
+/// Module for the chain of transactions of a covenant program.
+pub mod chain;
+/// Module for errors.
+pub mod error;
 /// Module for Fiat-Shamir.
 pub mod fiat_shamir;
 /// Module for folding.
 pub mod fold;
+/// Module for a mock RPC node in the tests.
+#[cfg(test)]
+pub(crate) mod mock_rpc;
+/// Module for the mempool policy of the transactions.
+pub mod policy;
 /// Module for prepare.
 pub mod prepare;
+/// Module for reading and writing proofs.
+pub mod proof;
 /// Module for quotients.
 pub mod quotients;
+/// Module for a minimal client of the RPC of a node.
+pub mod rpc;
+/// Module for the serialization of the hints.
+pub mod serialization;
+/// Module for tampering the hints in the tests.
+#[cfg(test)]
+pub(crate) mod tamper;
+/// Module for the native verifier.
+pub mod verifier;
 
 /// The implementation of a verifier split into multiple transactions.
 pub mod split;
 
 pub use bitcoin_script::*;
 use itertools::Itertools;
+use num_traits::One;
 
+use crate::error::{ConfigError, HintError};
 use crate::fiat_shamir::{compute_fiat_shamir_hints, FiatShamirHints};
 use crate::fold::{compute_fold_hints, PerQueryFoldHints};
 use crate::prepare::{compute_prepare_hints, PrepareHints};
 use crate::quotients::compute_quotients_hints;
 use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
 use quotients::PerQueryQuotientHint;
+use serde::{Deserialize, Serialize};
 use stwo_prover::core::air::Air;
 use stwo_prover::core::channel::Sha256Channel;
 use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::fields::m31::{BaseField, M31};
 use stwo_prover::core::fields::qm31::SecureField;
-use stwo_prover::core::pcs::TreeVec;
-use stwo_prover::core::prover::{InvalidOodsSampleStructure, StarkProof, VerificationError};
+use stwo_prover::core::fields::IntoSlice;
+use stwo_prover::core::fri::FriConfig;
+use stwo_prover::core::pcs::{PcsConfig, TreeVec};
+use stwo_prover::core::prover::{
+    InvalidOodsSampleStructure, StarkProof, LOG_BLOWUP_FACTOR, LOG_LAST_LAYER_DEGREE_BOUND,
+    N_QUERIES, PROOF_OF_WORK_BITS,
+};
+use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
 use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleHasher;
 use stwo_prover::core::ColumnVec;
 use stwo_prover::examples::fibonacci::air::FibonacciAir;
 
+/// The configuration of the verifier, which the prover, the hints, and the script must agree on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierConfig {
+    /// The number of FRI queries.
+    pub n_queries: usize,
+    /// The log of the blowup factor.
+    pub log_blowup_factor: u32,
+    /// The number of bits of proof-of-work.
+    pub pow_bits: u32,
+}
+
+/// The smallest log of the blowup factor that FRI, and the folding in the script, support.
+pub const MIN_LOG_BLOWUP_FACTOR: u32 = 1;
+
+/// The largest log of the blowup factor that FRI supports.
+pub const MAX_LOG_BLOWUP_FACTOR: u32 = 16;
+
+impl VerifierConfig {
+    /// The default configuration, which follows the defaults of the prover.
+    pub const DEFAULT: Self = Self {
+        n_queries: N_QUERIES,
+        log_blowup_factor: LOG_BLOWUP_FACTOR,
+        pow_bits: PROOF_OF_WORK_BITS,
+    };
+
+    /// Check that the script and the hints can be built for the configuration.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.n_queries == 0 {
+            return Err(ConfigError::NoQueries);
+        }
+        if !(MIN_LOG_BLOWUP_FACTOR..=MAX_LOG_BLOWUP_FACTOR).contains(&self.log_blowup_factor) {
+            return Err(ConfigError::LogBlowupFactorOutOfRange {
+                log_blowup_factor: self.log_blowup_factor,
+            });
+        }
+        Ok(())
+    }
+
+    /// The configuration of the polynomial commitment scheme, used by the prover and the hints.
+    pub fn pcs_config(&self) -> PcsConfig {
+        PcsConfig {
+            pow_bits: self.pow_bits,
+            fri_config: FriConfig::new(
+                LOG_LAST_LAYER_DEGREE_BOUND,
+                self.log_blowup_factor,
+                self.n_queries,
+            ),
+        }
+    }
+}
+
+impl Default for VerifierConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
 /// All the hints for the verifier (note: proof is also provided as a hint).
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VerifierHints {
     /// Fiat-Shamir hints.
     pub fiat_shamir_hints: FiatShamirHints,
@@ -63,27 +153,48 @@ impl Pushable for VerifierHints {
     }
 }
 
-/// A verifier program that generates hints.
+/// Compute the claim of a Fibonacci trace of size `2^log_size`, i.e., its last element.
+pub fn fibonacci_claim(log_size: u32) -> M31 {
+    let mut a = M31::one();
+    let mut b = M31::one();
+    for _ in 0..(1 << log_size) - 2 {
+        (a, b) = (b, a * a + b * b);
+    }
+    b
+}
+
+/// Create the channel for proving and verifying a Fibonacci claim, seeded with the hash of the claim.
+///
+/// Both the prover and the verifier script (see `FibonacciFiatShamirGadget`) start from this channel.
+pub fn channel_for_claim(claim: M31) -> Sha256Channel {
+    let mut channel = Sha256Channel::default();
+    channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[claim])));
+    channel
+}
+
+/// A verifier program that generates hints, with the channel seeded from the claim of the AIR.
 pub fn verify_with_hints(
     proof: StarkProof<Sha256MerkleHasher>,
     air: &FibonacciAir,
-    channel: &mut Sha256Channel,
-) -> Result<VerifierHints, VerificationError> {
+    config: VerifierConfig,
+) -> Result<VerifierHints, HintError> {
+    config.check()?;
+
+    let channel = &mut channel_for_claim(air.component.claim);
     let (fiat_shamir_output, fiat_shamir_hints) =
-        compute_fiat_shamir_hints(proof.clone(), channel, air).unwrap();
+        compute_fiat_shamir_hints(proof.clone(), channel, air, config)?;
 
-    let (prepare_output, prepare_hints) =
-        compute_prepare_hints(&fiat_shamir_output, &proof).unwrap();
+    let (prepare_output, prepare_hints) = compute_prepare_hints(&fiat_shamir_output, &proof)?;
 
     let (quotients_output, per_query_quotients_hints) =
-        compute_quotients_hints(&fiat_shamir_output, &prepare_output);
+        compute_quotients_hints(&fiat_shamir_output, &prepare_output)?;
 
     let per_query_fold_hints = compute_fold_hints(
         &proof.commitment_scheme_proof.fri_proof,
         &fiat_shamir_output,
         &prepare_output,
         &quotients_output,
-    );
+    )?;
 
     Ok(VerifierHints {
         fiat_shamir_hints,
@@ -130,37 +241,117 @@ fn sampled_values_to_mask(
 
 #[cfg(test)]
 mod test {
-    use stwo_prover::core::channel::Sha256Channel;
-    use stwo_prover::core::fields::m31::{BaseField, M31};
-    use stwo_prover::core::fields::IntoSlice;
+    use crate::error::HintError;
+    use crate::proof::test::test_proof;
+    use crate::{
+        channel_for_claim, fibonacci_claim, verify_with_hints, VerifierConfig, FIB_CLAIM,
+        FIB_LOG_SIZE,
+    };
+    use num_traits::One;
+    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::core::fri::FriVerificationError;
     use stwo_prover::core::pcs::PcsConfig;
-    use stwo_prover::core::prover::StarkProof;
-    use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
-    use stwo_prover::core::vcs::sha256_merkle::{Sha256MerkleChannel, Sha256MerkleHasher};
+    use stwo_prover::core::prover::VerificationError;
+    use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleChannel;
     use stwo_prover::examples::fibonacci::Fibonacci;
-    use stwo_prover::trace_generation::{commit_and_prove, commit_and_verify};
+    use stwo_prover::trace_generation::commit_and_verify;
+
+    #[test]
+    fn test_fibonacci_claim() {
+        assert_eq!(fibonacci_claim(FIB_LOG_SIZE), FIB_CLAIM);
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = VerifierConfig::default().pcs_config();
+        let default_config = PcsConfig::default();
+        assert_eq!(config.pow_bits, default_config.pow_bits);
+        assert_eq!(
+            config.fri_config.log_last_layer_degree_bound,
+            default_config.fri_config.log_last_layer_degree_bound
+        );
+        assert_eq!(
+            config.fri_config.log_blowup_factor,
+            default_config.fri_config.log_blowup_factor
+        );
+        assert_eq!(
+            config.fri_config.n_queries,
+            default_config.fri_config.n_queries
+        );
+    }
 
     #[test]
     fn test_fib_prove() {
-        const FIB_LOG_SIZE: u32 = 5;
-        let fib = Fibonacci::new(FIB_LOG_SIZE, M31::reduce(443693538));
-        let config = PcsConfig::default();
-
-        let trace = fib.get_trace();
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let proof: StarkProof<Sha256MerkleHasher> =
-            commit_and_prove::<_, Sha256MerkleChannel>(&fib.air, channel, vec![trace], config)
-                .unwrap();
-
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let config = VerifierConfig::default().pcs_config();
+        let proof = test_proof();
+
+        let channel = &mut channel_for_claim(fib.air.component.claim);
         commit_and_verify::<Sha256MerkleChannel>(proof, &fib.air, channel, config).unwrap()
     }
+
+    #[test]
+    fn test_verify_with_hints_errors() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+
+        assert!(verify_with_hints(proof.clone(), &fib.air, verifier_config).is_ok());
+
+        let mut missing_commitment = proof.clone();
+        missing_commitment.commitments.0.pop();
+        assert!(matches!(
+            verify_with_hints(missing_commitment, &fib.air, verifier_config),
+            Err(HintError::UnexpectedCommitments)
+        ));
+
+        let mut missing_sample = proof.clone();
+        missing_sample.commitment_scheme_proof.sampled_values.0[0][0].pop();
+        assert!(matches!(
+            verify_with_hints(missing_sample, &fib.air, verifier_config),
+            Err(HintError::UnexpectedSampleLayout)
+        ));
+
+        let mut wrong_sample = proof.clone();
+        wrong_sample.commitment_scheme_proof.sampled_values.0[0][0][0] += QM31::one();
+        assert!(matches!(
+            verify_with_hints(wrong_sample, &fib.air, verifier_config),
+            Err(HintError::Verification(VerificationError::OodsNotMatching))
+        ));
+
+        let mut missing_decommitment = proof.clone();
+        missing_decommitment
+            .commitment_scheme_proof
+            .decommitments
+            .0
+            .pop();
+        assert!(matches!(
+            verify_with_hints(missing_decommitment, &fib.air, verifier_config),
+            Err(HintError::UnexpectedDecommitments)
+        ));
+
+        let mut short_queried_values = proof.clone();
+        short_queried_values
+            .commitment_scheme_proof
+            .queried_values
+            .0[1][2]
+            .pop();
+        assert!(matches!(
+            verify_with_hints(short_queried_values, &fib.air, verifier_config),
+            Err(HintError::UnexpectedNumQueriedValues { tree: 1, .. })
+        ));
+
+        let mut missing_fri_layer = proof;
+        missing_fri_layer
+            .commitment_scheme_proof
+            .fri_proof
+            .inner_layers
+            .pop();
+        assert!(matches!(
+            verify_with_hints(missing_fri_layer, &fib.air, verifier_config),
+            Err(HintError::Verification(VerificationError::Fri(
+                FriVerificationError::InvalidNumFriLayers
+            )))
+        ));
+    }
 }
@@ -0,0 +1,290 @@
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::{Address, Network, OutPoint, Transaction, TxOut, Txid};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// A node that keeps the transactions in memory and serves the RPC methods of Bitcoin Core used
+/// by the tools, with the errors that Bitcoin Core returns.
+pub(crate) struct MockNode {
+    /// The network of the addresses.
+    pub(crate) network: Network,
+    /// All the transactions, in the mempool or in blocks.
+    pub(crate) txs: HashMap<Txid, Transaction>,
+    /// The transactions in the mempool, in the order that they are added.
+    pub(crate) mempool: Vec<Txid>,
+    /// The heights of the transactions in blocks.
+    pub(crate) heights: HashMap<Txid, u64>,
+    /// The height of the chain.
+    pub(crate) height: u64,
+    /// The messages of the transactions that the mempool rejects.
+    pub(crate) rejections: HashMap<Txid, String>,
+    /// The number of calls to fail before the node finishes warming up.
+    pub(crate) warmup_calls: usize,
+    /// Whether to mine a block after each transaction accepted by sendrawtransaction.
+    pub(crate) auto_mine: bool,
+}
+
+impl MockNode {
+    /// Create a node without any transaction.
+    pub(crate) fn new(network: Network) -> Self {
+        Self {
+            network,
+            txs: HashMap::new(),
+            mempool: vec![],
+            heights: HashMap::new(),
+            height: 0,
+            rejections: HashMap::new(),
+            warmup_calls: 0,
+            auto_mine: false,
+        }
+    }
+
+    /// Add a transaction to the mempool.
+    pub(crate) fn add_to_mempool(&mut self, tx: Transaction) {
+        let txid = tx.compute_txid();
+        self.txs.insert(txid, tx);
+        self.mempool.push(txid);
+    }
+
+    /// Mine a block with all the transactions in the mempool.
+    pub(crate) fn mine(&mut self) {
+        self.height += 1;
+        for txid in self.mempool.drain(..) {
+            self.heights.insert(txid, self.height);
+        }
+    }
+
+    /// The output, if it exists and is not spent, where the mempool is considered if requested.
+    fn unspent_output(&self, outpoint: &OutPoint, include_mempool: bool) -> Option<&TxOut> {
+        let visible = |txid: &Txid| self.heights.contains_key(txid) || include_mempool;
+
+        if !visible(&outpoint.txid) {
+            return None;
+        }
+        let txout = self
+            .txs
+            .get(&outpoint.txid)?
+            .output
+            .get(outpoint.vout as usize)?;
+
+        let spent = self.txs.iter().any(|(txid, tx)| {
+            visible(txid)
+                && tx
+                    .input
+                    .iter()
+                    .any(|txin| txin.previous_output == *outpoint)
+        });
+        if spent {
+            None
+        } else {
+            Some(txout)
+        }
+    }
+
+    /// Handle a call, and return either its result or the code and the message of its error.
+    pub(crate) fn handle(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let invalid_params = || (-8, "invalid parameters".to_string());
+        let txid_param = |param: &Value| {
+            param
+                .as_str()
+                .and_then(|txid| Txid::from_str(txid).ok())
+                .ok_or_else(invalid_params)
+        };
+
+        if self.warmup_calls > 0 {
+            self.warmup_calls -= 1;
+            return Err((-28, "Loading block index...".to_string()));
+        }
+
+        match method {
+            "sendrawtransaction" => {
+                let tx: Transaction = params[0]
+                    .as_str()
+                    .and_then(|tx_hex| hex::decode(tx_hex).ok())
+                    .and_then(|bytes| deserialize(&bytes).ok())
+                    .ok_or_else(|| (-22, "TX decode failed".to_string()))?;
+                let txid = tx.compute_txid();
+
+                if let Some(message) = self.rejections.get(&txid) {
+                    return Err((-26, message.clone()));
+                }
+                if self.heights.contains_key(&txid) {
+                    return Err((-27, "Transaction already in block chain".to_string()));
+                }
+                if !self.mempool.contains(&txid) {
+                    let missing_inputs = tx
+                        .input
+                        .iter()
+                        .any(|txin| self.unspent_output(&txin.previous_output, true).is_none());
+                    if missing_inputs {
+                        return Err((-25, "bad-txns-inputs-missingorspent".to_string()));
+                    }
+                    self.add_to_mempool(tx);
+                    if self.auto_mine {
+                        self.mine();
+                    }
+                }
+                Ok(json!(txid.to_string()))
+            }
+            "getrawtransaction" => {
+                let txid = txid_param(&params[0])?;
+                match self.txs.get(&txid) {
+                    Some(tx) => Ok(json!(hex::encode(serialize(tx)))),
+                    None => Err((-5, "No such mempool or blockchain transaction".to_string())),
+                }
+            }
+            "gettxout" => {
+                let outpoint = OutPoint {
+                    txid: txid_param(&params[0])?,
+                    vout: params[1].as_u64().ok_or_else(invalid_params)? as u32,
+                };
+                let include_mempool = params[2].as_bool().unwrap_or(true);
+                match self.unspent_output(&outpoint, include_mempool) {
+                    Some(txout) => Ok(json!({
+                        "value": txout.value.to_btc(),
+                        "confirmations": self
+                            .heights
+                            .get(&outpoint.txid)
+                            .map_or(0, |height| self.height - height + 1),
+                    })),
+                    None => Ok(Value::Null),
+                }
+            }
+            "gettxspendingprevout" => {
+                let prevouts = params[0].as_array().ok_or_else(invalid_params)?;
+                let mut result = vec![];
+                for prevout in prevouts {
+                    let outpoint = OutPoint {
+                        txid: txid_param(&prevout["txid"])?,
+                        vout: prevout["vout"].as_u64().ok_or_else(invalid_params)? as u32,
+                    };
+                    let spending_txid = self.mempool.iter().find(|txid| {
+                        self.txs[*txid]
+                            .input
+                            .iter()
+                            .any(|txin| txin.previous_output == outpoint)
+                    });
+                    let mut entry = json!({
+                        "txid": outpoint.txid.to_string(),
+                        "vout": outpoint.vout,
+                    });
+                    if let Some(txid) = spending_txid {
+                        entry["spendingtxid"] = json!(txid.to_string());
+                    }
+                    result.push(entry);
+                }
+                Ok(json!(result))
+            }
+            "scantxoutset" => {
+                let descriptors = params[1].as_array().ok_or_else(invalid_params)?;
+                let addresses = descriptors
+                    .iter()
+                    .map(|descriptor| {
+                        descriptor
+                            .as_str()
+                            .and_then(|descriptor| descriptor.strip_prefix("addr("))
+                            .and_then(|descriptor| descriptor.strip_suffix(')'))
+                            .map(|address| address.to_string())
+                            .ok_or_else(invalid_params)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut unspents = vec![];
+                for (txid, height) in self.heights.iter() {
+                    for (vout, txout) in self.txs[txid].output.iter().enumerate() {
+                        let outpoint = OutPoint {
+                            txid: *txid,
+                            vout: vout as u32,
+                        };
+                        let address =
+                            Address::from_script(txout.script_pubkey.as_script(), self.network);
+                        let matched = match address {
+                            Ok(address) => addresses.contains(&address.to_string()),
+                            Err(_) => false,
+                        };
+                        if matched && self.unspent_output(&outpoint, false).is_some() {
+                            unspents.push(json!({
+                                "txid": txid.to_string(),
+                                "vout": vout,
+                                "amount": txout.value.to_btc(),
+                                "height": height,
+                            }));
+                        }
+                    }
+                }
+                Ok(json!({
+                    "success": true,
+                    "height": self.height,
+                    "unspents": unspents,
+                }))
+            }
+            _ => Err((-32601, "Method not found".to_string())),
+        }
+    }
+}
+
+/// Serve the node over HTTP on a local port, and return the address of the server.
+pub(crate) fn serve(node: Arc<Mutex<MockNode>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let result = node
+                .lock()
+                .unwrap()
+                .handle(request["method"].as_str().unwrap(), &request["params"]);
+
+            let (status, response) = match result {
+                Ok(result) => (
+                    "200 OK",
+                    json!({"result": result, "error": null, "id": request["id"]}),
+                ),
+                Err((code, message)) => (
+                    "500 Internal Server Error",
+                    json!({
+                        "result": null,
+                        "error": {"code": code, "message": message},
+                        "id": request["id"],
+                    }),
+                ),
+            };
+            let response = response.to_string();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    });
+
+    address
+}
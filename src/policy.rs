@@ -0,0 +1,329 @@
+use bitcoin::{Amount, OutPoint, Transaction, TxOut};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// The maximum weight of a standard transaction.
+pub const MAX_STANDARD_TX_WEIGHT: u64 = 400000;
+
+/// The maximum size of a stack item in the witness of a standard tapscript spend, excluding the
+/// script and the control block.
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+
+/// The maximum size of a tapscript.
+///
+/// The policy does not limit tapscripts on their own, but a tapscript is part of the witness, so
+/// it cannot be larger than the weight limit of the transaction.
+pub const MAX_STANDARD_TAPSCRIPT_SIZE: usize = MAX_STANDARD_TX_WEIGHT as usize;
+
+/// The default minimum fee rate for relaying a transaction, in sat/vB.
+pub const DEFAULT_MIN_RELAY_FEE_RATE: u64 = 1;
+
+/// A policy rule that a transaction violates, which prevents it from being relayed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The transaction is heavier than `MAX_STANDARD_TX_WEIGHT`.
+    Weight {
+        /// The weight of the transaction.
+        weight: u64,
+    },
+    /// The witness of an input has an annex, which is reserved for future upgrades.
+    Annex {
+        /// The index of the input.
+        input: usize,
+    },
+    /// A stack item of a tapscript spend is larger than `MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE`.
+    WitnessItemSize {
+        /// The index of the input.
+        input: usize,
+        /// The index of the item in the witness.
+        item: usize,
+        /// The size of the item.
+        size: usize,
+    },
+    /// The tapscript of an input is larger than `MAX_STANDARD_TAPSCRIPT_SIZE`.
+    TaprootScriptSize {
+        /// The index of the input.
+        input: usize,
+        /// The size of the script.
+        size: usize,
+    },
+    /// An output is below the dust threshold of its script.
+    DustOutput {
+        /// The index of the output.
+        output: usize,
+        /// The value of the output.
+        value: Amount,
+        /// The smallest value that is not dust.
+        min_value: Amount,
+    },
+    /// The previous output of an input is unknown, so the fee cannot be computed.
+    UnknownPrevout {
+        /// The index of the input.
+        input: usize,
+    },
+    /// The outputs are worth more than the inputs.
+    NegativeFee,
+    /// The fee rate is below the minimum, in sat/vB.
+    FeeRate {
+        /// The fee of the transaction.
+        fee: Amount,
+        /// The virtual size of the transaction.
+        vsize: u64,
+        /// The minimum fee rate, in sat/vB.
+        min_fee_rate: u64,
+    },
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::Weight { weight } => write!(
+                f,
+                "the weight {} is above the standard limit {}",
+                weight, MAX_STANDARD_TX_WEIGHT
+            ),
+            PolicyViolation::Annex { input } => {
+                write!(f, "the witness of input {} has an annex", input)
+            }
+            PolicyViolation::WitnessItemSize { input, item, size } => write!(
+                f,
+                "the witness item {} of input {} has {} bytes, above the tapscript limit {}",
+                item, input, size, MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE
+            ),
+            PolicyViolation::TaprootScriptSize { input, size } => write!(
+                f,
+                "the tapscript of input {} has {} bytes, above the limit {}",
+                input, size, MAX_STANDARD_TAPSCRIPT_SIZE
+            ),
+            PolicyViolation::DustOutput {
+                output,
+                value,
+                min_value,
+            } => write!(
+                f,
+                "the output {} of {} is dust, as it is below {}",
+                output, value, min_value
+            ),
+            PolicyViolation::UnknownPrevout { input } => write!(
+                f,
+                "the previous output of input {} is unknown, so the fee cannot be checked",
+                input
+            ),
+            PolicyViolation::NegativeFee => write!(f, "the outputs are worth more than the inputs"),
+            PolicyViolation::FeeRate {
+                fee,
+                vsize,
+                min_fee_rate,
+            } => write!(
+                f,
+                "the fee {} for {} vB is below the minimum fee rate of {} sat/vB",
+                fee, vsize, min_fee_rate
+            ),
+        }
+    }
+}
+
+/// Check a transaction against the mempool policy, where `prevouts` contains the outputs spent by
+/// its inputs, and return all the rules that it violates.
+pub fn check_standardness(
+    tx: &Transaction,
+    prevouts: &HashMap<OutPoint, TxOut>,
+    min_fee_rate: u64,
+) -> Vec<PolicyViolation> {
+    let mut violations = vec![];
+
+    let weight = tx.weight().to_wu();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        violations.push(PolicyViolation::Weight { weight });
+    }
+
+    for (input, txin) in tx.input.iter().enumerate() {
+        let mut items = txin.witness.iter().collect::<Vec<_>>();
+
+        // the annex is the last item starting with 0x50, if there are at least two items
+        if items.len() >= 2 && items.last().unwrap().first() == Some(&0x50) {
+            violations.push(PolicyViolation::Annex { input });
+            items.pop();
+        }
+
+        // a tapscript spend ends with the script and the control block, which is 33 + 32k bytes
+        // with the leaf version 0xc0
+        let is_tapscript = items.len() >= 2 && {
+            let control_block = items[items.len() - 1];
+            control_block.len() >= 33
+                && (control_block.len() - 33) % 32 == 0
+                && control_block[0] & 0xfe == 0xc0
+        };
+        if !is_tapscript {
+            continue;
+        }
+
+        let script = items[items.len() - 2];
+        if script.len() > MAX_STANDARD_TAPSCRIPT_SIZE {
+            violations.push(PolicyViolation::TaprootScriptSize {
+                input,
+                size: script.len(),
+            });
+        }
+
+        for (item, elem) in items[..items.len() - 2].iter().enumerate() {
+            if elem.len() > MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE {
+                violations.push(PolicyViolation::WitnessItemSize {
+                    input,
+                    item,
+                    size: elem.len(),
+                });
+            }
+        }
+    }
+
+    for (output, txout) in tx.output.iter().enumerate() {
+        if txout.script_pubkey.is_op_return() {
+            continue;
+        }
+        let min_value = txout.script_pubkey.minimal_non_dust();
+        if txout.value < min_value {
+            violations.push(PolicyViolation::DustOutput {
+                output,
+                value: txout.value,
+                min_value,
+            });
+        }
+    }
+
+    let mut input_value = Amount::ZERO;
+    let mut all_known = true;
+    for (input, txin) in tx.input.iter().enumerate() {
+        match prevouts.get(&txin.previous_output) {
+            Some(prevout) => input_value += prevout.value,
+            None => {
+                violations.push(PolicyViolation::UnknownPrevout { input });
+                all_known = false;
+            }
+        }
+    }
+
+    if all_known {
+        let output_value = tx.output.iter().map(|txout| txout.value).sum::<Amount>();
+        match input_value.checked_sub(output_value) {
+            Some(fee) => {
+                let vsize = weight.div_ceil(4);
+                if fee.to_sat() < vsize * min_fee_rate {
+                    violations.push(PolicyViolation::FeeRate {
+                        fee,
+                        vsize,
+                        min_fee_rate,
+                    });
+                }
+            }
+            None => violations.push(PolicyViolation::NegativeFee),
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use crate::policy::{
+        check_standardness, PolicyViolation, DEFAULT_MIN_RELAY_FEE_RATE,
+        MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE,
+    };
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, WScriptHash, Witness,
+    };
+    use std::collections::HashMap;
+
+    fn tapscript_spend(items: Vec<Vec<u8>>, value: u64) -> (Transaction, HashMap<OutPoint, TxOut>) {
+        let script_pubkey = ScriptBuf::new_p2wsh(&WScriptHash::all_zeros());
+
+        let mut witness = Witness::new();
+        for item in items {
+            witness.push(item);
+        }
+        witness.push(vec![0x51u8; 1000]);
+        witness.push([vec![0xc0u8], vec![0x02u8; 32]].concat());
+
+        let previous_output = OutPoint {
+            txid: Txid::all_zeros(),
+            vout: 0,
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness,
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: script_pubkey.clone(),
+            }],
+        };
+
+        let mut prevouts = HashMap::new();
+        prevouts.insert(
+            previous_output,
+            TxOut {
+                value: Amount::from_sat(10000),
+                script_pubkey,
+            },
+        );
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn test_standard() {
+        let (tx, prevouts) = tapscript_spend(vec![vec![1u8; 32], vec![]], 5000);
+        assert!(check_standardness(&tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE).is_empty());
+    }
+
+    #[test]
+    fn test_violations() {
+        let (tx, prevouts) = tapscript_spend(
+            vec![vec![1u8; MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE + 1]],
+            5000,
+        );
+        assert_eq!(
+            check_standardness(&tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE),
+            vec![PolicyViolation::WitnessItemSize {
+                input: 0,
+                item: 0,
+                size: MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE + 1
+            }]
+        );
+
+        let (tx, prevouts) = tapscript_spend(vec![], 100);
+        assert!(matches!(
+            check_standardness(&tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE)[..],
+            [PolicyViolation::DustOutput { output: 0, .. }]
+        ));
+
+        let (tx, prevouts) = tapscript_spend(vec![], 9900);
+        assert!(matches!(
+            check_standardness(&tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE)[..],
+            [PolicyViolation::FeeRate { .. }]
+        ));
+
+        let (tx, prevouts) = tapscript_spend(vec![], 20000);
+        assert_eq!(
+            check_standardness(&tx, &prevouts, DEFAULT_MIN_RELAY_FEE_RATE),
+            vec![PolicyViolation::NegativeFee]
+        );
+
+        let (tx, _) = tapscript_spend(vec![vec![0u8; 400000]], 5000);
+        assert!(matches!(
+            check_standardness(&tx, &HashMap::new(), DEFAULT_MIN_RELAY_FEE_RATE)[..],
+            [
+                PolicyViolation::Weight { .. },
+                PolicyViolation::WitnessItemSize { .. },
+                PolicyViolation::UnknownPrevout { input: 0 }
+            ]
+        ));
+    }
+}
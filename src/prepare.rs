@@ -1,10 +1,13 @@
+use crate::error::HintError;
 use crate::fiat_shamir::FiatShamirOutput;
+use crate::serialization::mirror;
 use bitcoin_circle_stark::constraints::{
     ColumnLineCoeffs, ColumnLineCoeffsHint, PreparedPairVanishingHint,
 };
 use bitcoin_circle_stark::precomputed_merkle_tree::PrecomputedMerkleTree;
 use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use std::iter::zip;
 use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleHasher;
 use stwo_prover::core::{
@@ -13,16 +16,18 @@ use stwo_prover::core::{
     fields::{cm31::CM31, FieldExpOps},
     pcs::quotients::{ColumnSampleBatch, PointSample},
     poly::circle::CanonicCoset,
-    prover::{StarkProof, VerificationError, N_QUERIES},
+    prover::StarkProof,
 };
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 /// Hints for the prepare step.
 pub struct PrepareHints {
     /// Column line coeff hints.
+    #[serde(with = "mirror")]
     pub column_line_coeffs_hints: Vec<ColumnLineCoeffsHint>,
 
     /// Prepared pair vanishing hints.
+    #[serde(with = "mirror")]
     pub prepared_pair_vanishing_hints: Vec<PreparedPairVanishingHint>,
 }
 
@@ -54,7 +59,7 @@ pub struct PrepareOutput {
 pub fn compute_prepare_hints(
     fs_output: &FiatShamirOutput,
     proof: &StarkProof<Sha256MerkleHasher>,
-) -> Result<(PrepareOutput, PrepareHints), VerificationError> {
+) -> Result<(PrepareOutput, PrepareHints), HintError> {
     let column_size: Vec<u32> = fs_output
         .commitment_scheme_column_log_sizes
         .clone()
@@ -62,25 +67,23 @@ pub fn compute_prepare_hints(
         .into_iter()
         .dedup()
         .collect();
-    assert_eq!(column_size.len(), 1);
-    assert_eq!(
-        column_size[0],
-        fs_output.max_column_log_degree_bound + fs_output.fri_log_blowup_factor
-    );
-
-    // trace polynomials are evaluated on oods, oods+1, oods+2
-    assert_eq!(proof.commitment_scheme_proof.sampled_values.0[0].len(), 1);
-    assert_eq!(
-        proof.commitment_scheme_proof.sampled_values.0[0][0].len(),
-        3
-    );
+    if column_size.len() != 1
+        || column_size[0] != fs_output.max_column_log_degree_bound + fs_output.fri_log_blowup_factor
+    {
+        return Err(HintError::UnexpectedColumnSizes);
+    }
 
-    // composition polynomials are evaluated on oods 4 times
-    assert_eq!(proof.commitment_scheme_proof.sampled_values.0[1].len(), 4);
-    assert_eq!(
-        proof.commitment_scheme_proof.sampled_values.0[1][0].len(),
-        1
-    );
+    let sampled_values = &proof.commitment_scheme_proof.sampled_values.0;
+    if sampled_values.len() != 2
+        // trace polynomials are evaluated on oods, oods+1, oods+2
+        || sampled_values[0].len() != 1
+        || sampled_values[0][0].len() != 3
+        // composition polynomials are evaluated on oods 4 times
+        || sampled_values[1].len() != 4
+        || sampled_values[1].iter().any(|column| column.len() != 1)
+    {
+        return Err(HintError::UnexpectedSampleLayout);
+    }
 
     // construct the list of samples
     // Answer FRI queries.
@@ -134,24 +137,18 @@ pub fn compute_prepare_hints(
     ];
 
     for i in 0..3 {
-        assert_eq!(
-            expected_line_coeffs[i][0].0,
-            column_line_coeffs[i].fp_imag_div_y_imag[0]
-        );
-        assert_eq!(
-            expected_line_coeffs[i][0].1,
-            column_line_coeffs[i].cross_term[0]
-        );
+        if expected_line_coeffs[i][0].0 != column_line_coeffs[i].fp_imag_div_y_imag[0]
+            || expected_line_coeffs[i][0].1 != column_line_coeffs[i].cross_term[0]
+        {
+            return Err(HintError::ColumnLineCoeffsMismatch);
+        }
     }
     for j in 0..4 {
-        assert_eq!(
-            expected_line_coeffs[3][j].0,
-            column_line_coeffs[3].fp_imag_div_y_imag[j]
-        );
-        assert_eq!(
-            expected_line_coeffs[3][j].1,
-            column_line_coeffs[3].cross_term[j]
-        );
+        if expected_line_coeffs[3][j].0 != column_line_coeffs[3].fp_imag_div_y_imag[j]
+            || expected_line_coeffs[3][j].1 != column_line_coeffs[3].cross_term[j]
+        {
+            return Err(HintError::ColumnLineCoeffsMismatch);
+        }
     }
 
     let column_line_coeffs_hints = vec![
@@ -170,13 +167,13 @@ pub fn compute_prepare_hints(
 
     let expected_batch_random_coeffs =
         { batch_random_coeffs(&column_sample_batches, fs_output.random_coeff) };
-    assert_eq!(expected_batch_random_coeffs[0], fs_output.random_coeff);
-    assert_eq!(expected_batch_random_coeffs[1], fs_output.random_coeff);
-    assert_eq!(expected_batch_random_coeffs[2], fs_output.random_coeff);
-    assert_eq!(
-        expected_batch_random_coeffs[3],
-        fs_output.random_coeff.square().square()
-    );
+    if expected_batch_random_coeffs[0] != fs_output.random_coeff
+        || expected_batch_random_coeffs[1] != fs_output.random_coeff
+        || expected_batch_random_coeffs[2] != fs_output.random_coeff
+        || expected_batch_random_coeffs[3] != fs_output.random_coeff.square().square()
+    {
+        return Err(HintError::RandomCoeffsMismatch);
+    }
 
     let precomputed_merkle_tree = PrecomputedMerkleTree::new(
         (fs_output.max_column_log_degree_bound + fs_output.fri_log_blowup_factor - 1) as usize,
@@ -194,7 +191,12 @@ pub fn compute_prepare_hints(
             denominator_inverses(&column_sample_batches, domain)
         })
         .collect::<Vec<_>>();
-    assert_eq!(denominator_inverses_expected.len(), N_QUERIES);
+    if denominator_inverses_expected.len() != fs_output.n_queries {
+        return Err(HintError::UnexpectedNumQueries {
+            expected: fs_output.n_queries,
+            actual: denominator_inverses_expected.len(),
+        });
+    }
 
     let prepare_hints = PrepareHints {
         column_line_coeffs_hints,
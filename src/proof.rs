@@ -0,0 +1,416 @@
+use crate::VerifierConfig;
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use stwo_prover::core::fields::m31::{M31, P};
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::fri::{FriLayerProof, FriProof};
+use stwo_prover::core::pcs::{CommitmentSchemeProof, TreeVec};
+use stwo_prover::core::poly::line::LinePoly;
+use stwo_prover::core::prover::StarkProof;
+use stwo_prover::core::vcs::prover::MerkleDecommitment;
+use stwo_prover::core::vcs::sha256_hash::Sha256Hash;
+use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleHasher;
+use stwo_prover::core::LookupValues;
+
+/// A proof of a Fibonacci claim, together with the statement and the configuration it is
+/// generated for, as stored in a JSON file.
+///
+/// M31 elements are stored as integers, QM31 elements as arrays of four M31 elements, and hashes
+/// as hex strings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FibonacciProofFile {
+    /// The log size of the Fibonacci trace.
+    pub log_size: u32,
+    /// The claim, i.e., the last element of the Fibonacci trace.
+    pub claim: u32,
+    /// The configuration that the proof is generated with.
+    pub config: VerifierConfig,
+    /// The proof.
+    pub proof: SerializedStarkProof,
+}
+
+/// The serialized representation of `StarkProof<Sha256MerkleHasher>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerializedStarkProof {
+    /// Commitments of the trace and the composition.
+    pub commitments: Vec<String>,
+    /// Lookup values.
+    pub lookup_values: BTreeMap<String, u32>,
+    /// Sampled values, for each tree and each column.
+    pub sampled_values: Vec<Vec<Vec<[u32; 4]>>>,
+    /// Merkle decommitments, for each tree.
+    pub decommitments: Vec<SerializedMerkleDecommitment>,
+    /// Queried values, for each tree and each column.
+    pub queried_values: Vec<Vec<Vec<u32>>>,
+    /// Nonce of the proof of work.
+    pub proof_of_work: u64,
+    /// FRI inner layers.
+    pub fri_inner_layers: Vec<SerializedFriLayerProof>,
+    /// Coefficients of the FRI last layer polynomial.
+    pub fri_last_layer_poly: Vec<[u32; 4]>,
+}
+
+/// The serialized representation of `MerkleDecommitment<Sha256MerkleHasher>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerializedMerkleDecommitment {
+    /// Hash witness.
+    pub hash_witness: Vec<String>,
+    /// Column witness.
+    pub column_witness: Vec<u32>,
+}
+
+/// The serialized representation of `FriLayerProof<Sha256MerkleHasher>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerializedFriLayerProof {
+    /// Evaluations of the subset that the verifier cannot compute.
+    pub evals_subset: Vec<[u32; 4]>,
+    /// Merkle decommitment.
+    pub decommitment: SerializedMerkleDecommitment,
+    /// Commitment of the layer.
+    pub commitment: String,
+}
+
+impl FibonacciProofFile {
+    /// Create the file content for a proof.
+    pub fn new(
+        log_size: u32,
+        claim: M31,
+        config: VerifierConfig,
+        proof: &StarkProof<Sha256MerkleHasher>,
+    ) -> Self {
+        Self {
+            log_size,
+            claim: claim.0,
+            config,
+            proof: SerializedStarkProof::from(proof),
+        }
+    }
+
+    /// Read a proof file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Write the proof file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The claim as an M31 element.
+    pub fn claim(&self) -> Result<M31> {
+        m31_from_u32(self.claim)
+    }
+
+    /// Reconstruct the proof.
+    pub fn proof(&self) -> Result<StarkProof<Sha256MerkleHasher>> {
+        self.proof.to_proof()
+    }
+}
+
+impl From<&StarkProof<Sha256MerkleHasher>> for SerializedStarkProof {
+    fn from(proof: &StarkProof<Sha256MerkleHasher>) -> Self {
+        let commitment_scheme_proof = &proof.commitment_scheme_proof;
+        let fri_proof = &commitment_scheme_proof.fri_proof;
+
+        Self {
+            commitments: proof.commitments.iter().map(hash_to_hex).collect(),
+            lookup_values: proof
+                .lookup_values
+                .0
+                .iter()
+                .map(|(k, v)| (k.clone(), v.0))
+                .collect(),
+            sampled_values: commitment_scheme_proof
+                .sampled_values
+                .iter()
+                .map(|tree| {
+                    tree.iter()
+                        .map(|column| column.iter().map(qm31_to_array).collect())
+                        .collect()
+                })
+                .collect(),
+            decommitments: commitment_scheme_proof
+                .decommitments
+                .iter()
+                .map(SerializedMerkleDecommitment::from)
+                .collect(),
+            queried_values: commitment_scheme_proof
+                .queried_values
+                .iter()
+                .map(|tree| {
+                    tree.iter()
+                        .map(|column| column.iter().map(|v| v.0).collect())
+                        .collect()
+                })
+                .collect(),
+            proof_of_work: commitment_scheme_proof.proof_of_work,
+            fri_inner_layers: fri_proof
+                .inner_layers
+                .iter()
+                .map(|layer| SerializedFriLayerProof {
+                    evals_subset: layer.evals_subset.iter().map(qm31_to_array).collect(),
+                    decommitment: SerializedMerkleDecommitment::from(&layer.decommitment),
+                    commitment: hash_to_hex(&layer.commitment),
+                })
+                .collect(),
+            fri_last_layer_poly: fri_proof
+                .last_layer_poly
+                .iter()
+                .map(qm31_to_array)
+                .collect(),
+        }
+    }
+}
+
+impl SerializedStarkProof {
+    /// Reconstruct the proof, checking that all the field elements and hashes are well-formed.
+    pub fn to_proof(&self) -> Result<StarkProof<Sha256MerkleHasher>> {
+        let commitments = self
+            .commitments
+            .iter()
+            .map(|h| hash_from_hex(h))
+            .collect::<Result<Vec<_>>>()?;
+
+        let lookup_values = self
+            .lookup_values
+            .iter()
+            .map(|(k, &v)| Ok((k.clone(), m31_from_u32(v)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let sampled_values = self
+            .sampled_values
+            .iter()
+            .map(|tree| {
+                tree.iter()
+                    .map(|column| column.iter().map(qm31_from_array).collect())
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<Vec<_>>>>>()?;
+
+        let decommitments = self
+            .decommitments
+            .iter()
+            .map(|d| d.to_decommitment())
+            .collect::<Result<Vec<_>>>()?;
+
+        let queried_values = self
+            .queried_values
+            .iter()
+            .map(|tree| {
+                tree.iter()
+                    .map(|column| column.iter().map(|&v| m31_from_u32(v)).collect())
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<Vec<_>>>>>()?;
+
+        let inner_layers = self
+            .fri_inner_layers
+            .iter()
+            .map(|layer| {
+                Ok(FriLayerProof {
+                    evals_subset: layer
+                        .evals_subset
+                        .iter()
+                        .map(qm31_from_array)
+                        .collect::<Result<_>>()?,
+                    decommitment: layer.decommitment.to_decommitment()?,
+                    commitment: hash_from_hex(&layer.commitment)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let last_layer_poly = self
+            .fri_last_layer_poly
+            .iter()
+            .map(qm31_from_array)
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            last_layer_poly.len().is_power_of_two(),
+            "the last layer polynomial must have a power-of-two number of coefficients"
+        );
+
+        Ok(StarkProof {
+            commitments: TreeVec::new(commitments),
+            lookup_values: LookupValues(lookup_values),
+            commitment_scheme_proof: CommitmentSchemeProof {
+                sampled_values: TreeVec::new(sampled_values),
+                decommitments: TreeVec::new(decommitments),
+                queried_values: TreeVec::new(queried_values),
+                proof_of_work: self.proof_of_work,
+                fri_proof: FriProof {
+                    inner_layers,
+                    last_layer_poly: LinePoly::new(last_layer_poly),
+                },
+            },
+        })
+    }
+}
+
+impl From<&MerkleDecommitment<Sha256MerkleHasher>> for SerializedMerkleDecommitment {
+    fn from(decommitment: &MerkleDecommitment<Sha256MerkleHasher>) -> Self {
+        Self {
+            hash_witness: decommitment.hash_witness.iter().map(hash_to_hex).collect(),
+            column_witness: decommitment.column_witness.iter().map(|v| v.0).collect(),
+        }
+    }
+}
+
+impl SerializedMerkleDecommitment {
+    fn to_decommitment(&self) -> Result<MerkleDecommitment<Sha256MerkleHasher>> {
+        Ok(MerkleDecommitment {
+            hash_witness: self
+                .hash_witness
+                .iter()
+                .map(|h| hash_from_hex(h))
+                .collect::<Result<_>>()?,
+            column_witness: self
+                .column_witness
+                .iter()
+                .map(|&v| m31_from_u32(v))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+pub(crate) fn m31_from_u32(v: u32) -> Result<M31> {
+    ensure!(v < P, "{} is not a canonical M31 element", v);
+    Ok(M31::from_u32_unchecked(v))
+}
+
+pub(crate) fn qm31_to_array(v: &QM31) -> [u32; 4] {
+    v.to_m31_array().map(|v| v.0)
+}
+
+pub(crate) fn qm31_from_array(v: &[u32; 4]) -> Result<QM31> {
+    Ok(QM31::from_m31_array([
+        m31_from_u32(v[0])?,
+        m31_from_u32(v[1])?,
+        m31_from_u32(v[2])?,
+        m31_from_u32(v[3])?,
+    ]))
+}
+
+pub(crate) fn hash_to_hex(hash: &Sha256Hash) -> String {
+    hex::encode(hash.as_ref())
+}
+
+pub(crate) fn hash_from_hex(hex: &str) -> Result<Sha256Hash> {
+    let bytes = hex::decode(hex)?;
+    ensure!(bytes.len() == 32, "a SHA256 hash must have 32 bytes");
+    Ok(Sha256Hash::from(bytes.as_slice()))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::proof::FibonacciProofFile;
+    use crate::{
+        channel_for_claim, verify_with_hints, FibonacciClaimMode, FibonacciVerifierGadget,
+        VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE,
+    };
+    use bitcoin_circle_stark::treepp::*;
+    use bitcoin_scriptexec::execute_script_with_witness_unlimited_stack;
+    use std::sync::OnceLock;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::prover::StarkProof;
+    use stwo_prover::core::vcs::sha256_merkle::{Sha256MerkleChannel, Sha256MerkleHasher};
+    use stwo_prover::examples::fibonacci::Fibonacci;
+    use stwo_prover::trace_generation::{commit_and_prove, commit_and_verify};
+
+    fn generate_proof(
+        log_size: u32,
+        claim: M31,
+        config: VerifierConfig,
+    ) -> StarkProof<Sha256MerkleHasher> {
+        let fib = Fibonacci::new(log_size, claim);
+        let trace = fib.get_trace();
+        let channel = &mut channel_for_claim(claim);
+        commit_and_prove::<_, Sha256MerkleChannel>(
+            &fib.air,
+            channel,
+            vec![trace],
+            config.pcs_config(),
+        )
+        .unwrap()
+    }
+
+    /// The proof of `FIB_CLAIM` in the default configuration, which is generated once and shared
+    /// by the tests of the same binary instead of being generated by each test.
+    pub(crate) fn test_proof() -> StarkProof<Sha256MerkleHasher> {
+        static PROOF: OnceLock<StarkProof<Sha256MerkleHasher>> = OnceLock::new();
+
+        PROOF
+            .get_or_init(|| generate_proof(FIB_LOG_SIZE, FIB_CLAIM, VerifierConfig::default()))
+            .clone()
+    }
+
+    /// A proof of the Fibonacci claim of the log size, which is the shared proof of the tests for
+    /// the default statement and configuration, and a new proof otherwise.
+    pub(crate) fn proof_for(
+        log_size: u32,
+        claim: M31,
+        config: VerifierConfig,
+    ) -> StarkProof<Sha256MerkleHasher> {
+        if log_size == FIB_LOG_SIZE && claim == FIB_CLAIM && config == VerifierConfig::default() {
+            test_proof()
+        } else {
+            generate_proof(log_size, claim, config)
+        }
+    }
+
+    #[test]
+    fn test_proof_file_round_trip() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+
+        let path = std::env::temp_dir().join("fibonacci-proof-round-trip.json");
+        FibonacciProofFile::new(FIB_LOG_SIZE, FIB_CLAIM, verifier_config, &proof)
+            .write(&path)
+            .unwrap();
+
+        let file = FibonacciProofFile::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file.log_size, FIB_LOG_SIZE);
+        assert_eq!(file.claim().unwrap(), FIB_CLAIM);
+        assert_eq!(file.config, verifier_config);
+
+        let fib = Fibonacci::new(file.log_size, file.claim().unwrap());
+        let loaded_proof = file.proof().unwrap();
+
+        let channel = &mut channel_for_claim(fib.air.component.claim);
+        commit_and_verify::<Sha256MerkleChannel>(
+            loaded_proof.clone(),
+            &fib.air,
+            channel,
+            file.config.pcs_config(),
+        )
+        .unwrap();
+
+        let hints = verify_with_hints(loaded_proof, &fib.air, file.config).unwrap();
+        let script = script! {
+            { FibonacciVerifierGadget::run_verifier(FibonacciClaimMode::Constant(FIB_CLAIM), FIB_LOG_SIZE, file.config) }
+            OP_TRUE
+        };
+        let exec_result = execute_script_with_witness_unlimited_stack(
+            script,
+            convert_to_witness(script! {
+                { hints }
+            })
+            .unwrap(),
+        );
+        assert!(exec_result.success);
+    }
+
+    #[test]
+    fn test_proof_file_rejects_non_canonical_elements() {
+        let json = r#"{"log_size":5,"claim":2147483647,"config":{"n_queries":8,"log_blowup_factor":1,"pow_bits":5},"proof":{"commitments":[],"lookup_values":{},"sampled_values":[],"decommitments":[],"queried_values":[],"proof_of_work":0,"fri_inner_layers":[],"fri_last_layer_poly":[]}}"#;
+        let file: FibonacciProofFile = serde_json::from_str(json).unwrap();
+        assert!(file.claim().is_err());
+        assert!(file.proof().is_err());
+    }
+}
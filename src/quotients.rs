@@ -1,8 +1,11 @@
+use crate::error::HintError;
 use crate::fiat_shamir::FiatShamirOutput;
 use crate::prepare::PrepareOutput;
+use crate::serialization::mirror;
 use bitcoin_circle_stark::constraints::DenominatorInverseHint;
 use bitcoin_circle_stark::precomputed_merkle_tree::PrecomputedMerkleTreeProof;
 use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
+use serde::{Deserialize, Serialize};
 use stwo_prover::core::fft::ibutterfly;
 use stwo_prover::core::fields::qm31::QM31;
 use stwo_prover::core::fields::FieldExpOps;
@@ -18,7 +21,27 @@ pub struct QuotientsOutput {
 pub fn compute_quotients_hints(
     fs_output: &FiatShamirOutput,
     prepare_output: &PrepareOutput,
-) -> (QuotientsOutput, Vec<PerQueryQuotientHint>) {
+) -> Result<(QuotientsOutput, Vec<PerQueryQuotientHint>), HintError> {
+    let n_queries = fs_output.queries_parents.len();
+    if prepare_output.denominator_inverses_expected.len() != n_queries {
+        return Err(HintError::UnexpectedNumQueries {
+            expected: n_queries,
+            actual: prepare_output.denominator_inverses_expected.len(),
+        });
+    }
+
+    // each query opens one trace column and four composition columns
+    if fs_output.queried_values_left.len() != n_queries
+        || fs_output.queried_values_right.len() != n_queries
+        || fs_output
+            .queried_values_left
+            .iter()
+            .chain(fs_output.queried_values_right.iter())
+            .any(|values| values.len() != 1 + 4)
+    {
+        return Err(HintError::UnexpectedQueriedValuesLayout);
+    }
+
     let mut hints = vec![];
     let mut fold_results = vec![];
 
@@ -118,16 +141,18 @@ pub fn compute_quotients_hints(
         });
     }
 
-    (QuotientsOutput { fold_results }, hints)
+    Ok((QuotientsOutput { fold_results }, hints))
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 /// Hint that repeats for each query.
 pub struct PerQueryQuotientHint {
     /// Precomputed tree Merkle proofs.
+    #[serde(with = "mirror")]
     pub precomputed_merkle_proofs: Vec<PrecomputedMerkleTreeProof>,
 
     /// Denominator inverse hints.
+    #[serde(with = "mirror")]
     pub denominator_inverse_hints: Vec<DenominatorInverseHint>,
 }
 
@@ -0,0 +1,199 @@
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::{Address, OutPoint, Transaction, Txid};
+use serde_json::{json, Value};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The timeout of reading from and writing to the node.
+const RPC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The error code when the node is still loading the blocks after the start.
+pub const RPC_IN_WARMUP: i64 = -28;
+
+/// The error code when a transaction is rejected by the mempool, e.g., for a failing script.
+pub const RPC_VERIFY_REJECTED: i64 = -26;
+
+/// The error code when a transaction is already in a block.
+pub const RPC_VERIFY_ALREADY_IN_CHAIN: i64 = -27;
+
+/// An error returned by the node, as opposed to a failure to reach it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpcError {
+    /// The error code, e.g., -26 when a transaction is rejected by the mempool.
+    pub code: i64,
+    /// The error message.
+    pub message: String,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// A minimal client of the JSON-RPC interface of Bitcoin Core, which sends each call in its own
+/// HTTP request.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    /// The address of the node, as host:port.
+    address: String,
+    /// The value of the basic authorization header.
+    authorization: Option<String>,
+}
+
+impl RpcClient {
+    /// Create a client of the node at the address (host:port), with the user and the password
+    /// if the node requires them.
+    pub fn new(address: impl Into<String>, auth: Option<(&str, &str)>) -> Self {
+        Self {
+            address: address.into(),
+            authorization: auth.map(|(user, password)| {
+                format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", user, password))
+                )
+            }),
+        }
+    }
+
+    /// Call a method, and return its result, where an error returned by the node is an
+    /// `RpcError`.
+    pub fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.address,
+            body.len()
+        );
+        if let Some(authorization) = &self.authorization {
+            request.push_str(&format!("Authorization: {}\r\n", authorization));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(RPC_TIMEOUT))?;
+        stream.set_write_timeout(Some(RPC_TIMEOUT))?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+
+        // the node answers errors with a status other than 200, but still with a JSON body
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| anyhow!("malformed HTTP response from the node"))?;
+        let status = head.split(' ').nth(1).unwrap_or_default();
+        if status == "401" {
+            bail!("the node rejects the RPC credentials");
+        }
+
+        let response: Value = serde_json::from_str(body)
+            .map_err(|e| anyhow!("unexpected response with HTTP status {}: {}", status, e))?;
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            return Err(RpcError {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            }
+            .into());
+        }
+        Ok(response["result"].clone())
+    }
+
+    /// Get a transaction, which must be in the mempool or, if the node has `-txindex`, in a block.
+    pub fn get_raw_transaction(&self, txid: &Txid) -> Result<Transaction> {
+        let result = self.call("getrawtransaction", json!([txid.to_string()]))?;
+        let tx_hex = result
+            .as_str()
+            .ok_or_else(|| anyhow!("unexpected result of getrawtransaction"))?;
+        Ok(deserialize(&hex::decode(tx_hex)?)?)
+    }
+
+    /// Submit a transaction to the mempool, which is accepted if it is already there.
+    pub fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid> {
+        let result = self.call("sendrawtransaction", json!([hex::encode(serialize(tx))]))?;
+        let txid = result
+            .as_str()
+            .ok_or_else(|| anyhow!("unexpected result of sendrawtransaction"))?;
+        Ok(Txid::from_str(txid)?)
+    }
+
+    /// The number of confirmations of the output, which is 0 in the mempool, or `None` if it does
+    /// not exist or is spent.
+    pub fn get_tx_out_confirmations(&self, outpoint: &OutPoint) -> Result<Option<u64>> {
+        let result = self.call(
+            "gettxout",
+            json!([outpoint.txid.to_string(), outpoint.vout, true]),
+        )?;
+        if result.is_null() {
+            return Ok(None);
+        }
+        let confirmations = result["confirmations"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("unexpected result of gettxout"))?;
+        Ok(Some(confirmations))
+    }
+
+    /// Whether the output exists and is not spent, including by the mempool.
+    pub fn is_unspent(&self, outpoint: &OutPoint) -> Result<bool> {
+        let result = self.call(
+            "gettxout",
+            json!([outpoint.txid.to_string(), outpoint.vout, true]),
+        )?;
+        Ok(!result.is_null())
+    }
+
+    /// The transaction in the mempool that spends the output, if any.
+    pub fn get_tx_spending_prevout(&self, outpoint: &OutPoint) -> Result<Option<Txid>> {
+        let result = self.call(
+            "gettxspendingprevout",
+            json!([[{"txid": outpoint.txid.to_string(), "vout": outpoint.vout}]]),
+        )?;
+        match result[0].get("spendingtxid").and_then(Value::as_str) {
+            Some(txid) => Ok(Some(Txid::from_str(txid)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The confirmed unspent outputs that pay the address.
+    pub fn scan_tx_out_set(&self, address: &Address) -> Result<Vec<OutPoint>> {
+        let result = self.call(
+            "scantxoutset",
+            json!(["start", [format!("addr({})", address)]]),
+        )?;
+        let unspents = result["unspents"]
+            .as_array()
+            .ok_or_else(|| anyhow!("unexpected result of scantxoutset"))?;
+
+        unspents
+            .iter()
+            .map(|unspent| {
+                let txid = unspent["txid"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("unexpected result of scantxoutset"))?;
+                let vout = unspent["vout"]
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("unexpected result of scantxoutset"))?;
+                Ok(OutPoint {
+                    txid: Txid::from_str(txid)?,
+                    vout: vout as u32,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,662 @@
+use crate::proof::{hash_from_hex, hash_to_hex, m31_from_u32, qm31_from_array, qm31_to_array};
+use crate::{VerifierConfig, VerifierHints};
+use anyhow::{anyhow, bail, ensure, Result};
+use bitcoin_circle_stark::air::CompositionHint;
+use bitcoin_circle_stark::channel::DrawHints;
+use bitcoin_circle_stark::constraints::{
+    ColumnLineCoeffsHint, DenominatorInverseHint, PreparedPairVanishingHint,
+};
+use bitcoin_circle_stark::merkle_tree::MerkleTreeTwinProof;
+use bitcoin_circle_stark::oods::OODSHint;
+use bitcoin_circle_stark::pow::PoWHint;
+use bitcoin_circle_stark::precomputed_merkle_tree::PrecomputedMerkleTreeProof;
+use bitcoin_circle_stark::treepp::pushable::{Builder, Pushable};
+use bitcoin_circle_stark::treepp::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::fields::cm31::CM31;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::sha256_hash::Sha256Hash;
+
+/// The magic bytes at the start of a hints file in the compact binary format, which a JSON file
+/// cannot start with.
+const BINARY_MAGIC: &[u8; 8] = b"\0FIBHINT";
+
+/// The hints of a proof, together with the statement and the configuration they are computed
+/// for, as stored in a file.
+///
+/// The file is either JSON or the compact binary format, which starts with `BINARY_MAGIC`. M31
+/// elements are stored as integers, QM31 elements as arrays of four M31 elements, and hashes as
+/// hex strings.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VerifierHintsFile {
+    /// The log size of the Fibonacci trace.
+    pub log_size: u32,
+    /// The claim, i.e., the last element of the Fibonacci trace.
+    pub claim: u32,
+    /// The configuration that the proof is generated with.
+    pub config: VerifierConfig,
+    /// The hints.
+    pub hints: VerifierHints,
+}
+
+impl VerifierHintsFile {
+    /// Create the file content for the hints.
+    pub fn new(log_size: u32, claim: M31, config: VerifierConfig, hints: VerifierHints) -> Self {
+        Self {
+            log_size,
+            claim: claim.0,
+            config,
+            hints,
+        }
+    }
+
+    /// Serialize the file into JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Deserialize the file from JSON.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Serialize the file into the compact binary format, including the magic bytes.
+    pub fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend(bincode::serialize(self)?);
+        Ok(bytes)
+    }
+
+    /// Deserialize the file from the compact binary format, including the magic bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.strip_prefix(BINARY_MAGIC) {
+            Some(bytes) => Ok(bincode::deserialize(bytes)?),
+            None => bail!("the hints are not in the compact binary format"),
+        }
+    }
+
+    /// Write the file, in JSON or in the compact binary format.
+    pub fn write(&self, path: impl AsRef<Path>, binary: bool) -> Result<()> {
+        if binary {
+            std::fs::write(path, self.to_bytes()?)?;
+        } else {
+            std::fs::write(path, self.to_json()?)?;
+        }
+        Ok(())
+    }
+
+    /// Read a file written by `write`, whose format is told by the magic bytes.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_bytes(&bytes)
+        } else {
+            Ok(Self::from_json(std::str::from_utf8(&bytes)?)?)
+        }
+    }
+
+    /// The claim as an M31 element.
+    pub fn claim(&self) -> Result<M31> {
+        m31_from_u32(self.claim)
+    }
+
+    /// Check that the hints are for the statement and the configuration of a program.
+    pub fn check(&self, log_size: u32, claim: M31, config: VerifierConfig) -> Result<()> {
+        ensure!(
+            self.log_size == log_size,
+            "the hints are for log size {}, not {}",
+            self.log_size,
+            log_size
+        );
+        ensure!(
+            self.claim()? == claim,
+            "the hints are for the claim {}, not {}",
+            self.claim,
+            claim.0
+        );
+        ensure!(
+            self.config == config,
+            "the hints are for {:?}, not {:?}",
+            self.config,
+            config
+        );
+        ensure!(
+            self.hints.per_query_quotients_hints.len() == config.n_queries
+                && self.hints.per_query_fold_hints.len() == config.n_queries,
+            "the hints are not for {} queries",
+            config.n_queries
+        );
+        Ok(())
+    }
+}
+
+/// A hint in the form of the witness elements that it pushes, which is exactly how the script
+/// consumes it and how it is decoded from a transaction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WitnessHint(pub Vec<Vec<u8>>);
+
+impl WitnessHint {
+    /// Convert a hint into its witness elements.
+    pub fn from_hint<T: Pushable + Clone>(hint: &T) -> Self {
+        Self(
+            convert_to_witness(script! {
+                { hint.clone() }
+            })
+            .unwrap(),
+        )
+    }
+}
+
+impl Pushable for WitnessHint {
+    fn bitcoin_script_push(&self, mut builder: Builder) -> Builder {
+        for elem in self.0.iter() {
+            builder = elem.bitcoin_script_push(builder);
+        }
+        builder
+    }
+}
+
+/// All the hints for the verifier in the form of the witness elements that they push, which is
+/// how the split program consumes them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SerializedVerifierHints {
+    /// Fiat-Shamir hints.
+    pub fiat_shamir_hints: WitnessHint,
+
+    /// Prepare hints.
+    pub prepare_hints: WitnessHint,
+
+    /// Per query quotients hints.
+    pub per_query_quotients_hints: Vec<WitnessHint>,
+
+    /// Per query folding hints.
+    pub per_query_fold_hints: Vec<WitnessHint>,
+}
+
+impl From<&VerifierHints> for SerializedVerifierHints {
+    fn from(hints: &VerifierHints) -> Self {
+        Self {
+            fiat_shamir_hints: WitnessHint::from_hint(&hints.fiat_shamir_hints),
+            prepare_hints: WitnessHint::from_hint(&hints.prepare_hints),
+            per_query_quotients_hints: hints
+                .per_query_quotients_hints
+                .iter()
+                .map(WitnessHint::from_hint)
+                .collect(),
+            per_query_fold_hints: hints
+                .per_query_fold_hints
+                .iter()
+                .map(WitnessHint::from_hint)
+                .collect(),
+        }
+    }
+}
+
+impl Pushable for SerializedVerifierHints {
+    fn bitcoin_script_push(&self, mut builder: Builder) -> Builder {
+        builder = self.fiat_shamir_hints.bitcoin_script_push(builder);
+        builder = self.prepare_hints.bitcoin_script_push(builder);
+
+        for (quotients_hint, fold_hint) in self
+            .per_query_quotients_hints
+            .iter()
+            .zip(self.per_query_fold_hints.iter())
+        {
+            builder = quotients_hint.bitcoin_script_push(builder);
+            builder = fold_hint.bitcoin_script_push(builder);
+        }
+        builder
+    }
+}
+
+/// A type of the dependencies that is serialized through a plain representation, which is
+/// checked when deserialized, e.g., M31 elements must be canonical.
+pub(crate) trait Mirror: Sized {
+    /// The plain representation.
+    type Repr: Serialize + DeserializeOwned;
+
+    /// Convert into the plain representation.
+    fn to_repr(&self) -> Self::Repr;
+
+    /// Convert from the plain representation.
+    fn from_repr(repr: Self::Repr) -> Result<Self>;
+}
+
+/// (De)serialize a field through its plain representation, as in `#[serde(with = "mirror")]`.
+pub(crate) mod mirror {
+    use super::Mirror;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Mirror, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.to_repr().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: Mirror, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        T::from_repr(T::Repr::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl<T: Mirror> Mirror for Vec<T> {
+    type Repr = Vec<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter().map(T::to_repr).collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        repr.into_iter().map(T::from_repr).collect()
+    }
+}
+
+impl<T: Mirror, const N: usize> Mirror for [T; N] {
+    type Repr = Vec<T::Repr>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.iter().map(T::to_repr).collect()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        let values = repr
+            .into_iter()
+            .map(T::from_repr)
+            .collect::<Result<Vec<_>>>()?;
+        values
+            .try_into()
+            .map_err(|values: Vec<T>| anyhow!("expected {} elements, found {}", N, values.len()))
+    }
+}
+
+impl<A: Mirror, B: Mirror> Mirror for (A, B) {
+    type Repr = (A::Repr, B::Repr);
+
+    fn to_repr(&self) -> Self::Repr {
+        (self.0.to_repr(), self.1.to_repr())
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok((A::from_repr(repr.0)?, B::from_repr(repr.1)?))
+    }
+}
+
+impl Mirror for M31 {
+    type Repr = u32;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.0
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        m31_from_u32(repr)
+    }
+}
+
+impl Mirror for CM31 {
+    type Repr = [u32; 2];
+
+    fn to_repr(&self) -> Self::Repr {
+        [self.0 .0, self.1 .0]
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(CM31(m31_from_u32(repr[0])?, m31_from_u32(repr[1])?))
+    }
+}
+
+impl Mirror for QM31 {
+    type Repr = [u32; 4];
+
+    fn to_repr(&self) -> Self::Repr {
+        qm31_to_array(self)
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        qm31_from_array(&repr)
+    }
+}
+
+impl Mirror for CirclePoint<M31> {
+    type Repr = [u32; 2];
+
+    fn to_repr(&self) -> Self::Repr {
+        [self.x.0, self.y.0]
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(CirclePoint {
+            x: m31_from_u32(repr[0])?,
+            y: m31_from_u32(repr[1])?,
+        })
+    }
+}
+
+impl Mirror for Sha256Hash {
+    type Repr = String;
+
+    fn to_repr(&self) -> Self::Repr {
+        hash_to_hex(self)
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        hash_from_hex(&repr)
+    }
+}
+
+/// The plain representation of `DrawHints`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DrawHintsRepr {
+    /// Hashes of the channel, as hex strings.
+    hashes: Vec<String>,
+    /// The remaining bytes, as a hex string.
+    residue: String,
+}
+
+impl Mirror for DrawHints {
+    type Repr = DrawHintsRepr;
+
+    fn to_repr(&self) -> Self::Repr {
+        DrawHintsRepr {
+            hashes: self.hashes.iter().map(hex::encode).collect(),
+            residue: hex::encode(&self.residue),
+        }
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        let hashes = repr
+            .hashes
+            .iter()
+            .map(|hash| {
+                let bytes = hex::decode(hash)?;
+                match <[u8; 32]>::try_from(bytes.as_slice()) {
+                    Ok(hash) => Ok(hash),
+                    Err(_) => bail!("a hash of the channel must have 32 bytes"),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(DrawHints {
+            hashes,
+            residue: hex::decode(&repr.residue)?,
+        })
+    }
+}
+
+/// The plain representation of `OODSHint`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct OODSHintRepr {
+    /// The x coordinate of the OODS point.
+    x: [u32; 4],
+    /// The y coordinate of the OODS point.
+    y: [u32; 4],
+    /// The hints for drawing the OODS point.
+    draw_hints: DrawHintsRepr,
+}
+
+impl Mirror for OODSHint {
+    type Repr = OODSHintRepr;
+
+    fn to_repr(&self) -> Self::Repr {
+        OODSHintRepr {
+            x: self.x.to_repr(),
+            y: self.y.to_repr(),
+            draw_hints: self.draw_hints.to_repr(),
+        }
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(OODSHint {
+            x: QM31::from_repr(repr.x)?,
+            y: QM31::from_repr(repr.y)?,
+            draw_hints: DrawHints::from_repr(repr.draw_hints)?,
+        })
+    }
+}
+
+/// The plain representation of `PoWHint`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PoWHintRepr {
+    /// The nonce.
+    nonce: u64,
+    /// The leading bytes of the hash, as a hex string.
+    prefix: String,
+    /// The most significant byte after the prefix, if the bits are not a multiple of 8.
+    msb: Option<u8>,
+}
+
+impl Mirror for PoWHint {
+    type Repr = PoWHintRepr;
+
+    fn to_repr(&self) -> Self::Repr {
+        PoWHintRepr {
+            nonce: self.nonce,
+            prefix: hex::encode(&self.prefix),
+            msb: self.msb,
+        }
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(PoWHint {
+            nonce: repr.nonce,
+            prefix: hex::decode(&repr.prefix)?,
+            msb: repr.msb,
+        })
+    }
+}
+
+impl Mirror for CompositionHint {
+    type Repr = Vec<[u32; 4]>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.constraint_eval_quotients_by_mask.to_repr()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(CompositionHint {
+            constraint_eval_quotients_by_mask: Vec::from_repr(repr)?,
+        })
+    }
+}
+
+/// The plain representation of `MerkleTreeTwinProof`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct MerkleTreeTwinProofRepr {
+    /// The values of the left leaf.
+    left: Vec<u32>,
+    /// The values of the right leaf.
+    right: Vec<u32>,
+    /// The siblings on the path to the root, as hex strings.
+    siblings: Vec<String>,
+}
+
+impl Mirror for MerkleTreeTwinProof {
+    type Repr = MerkleTreeTwinProofRepr;
+
+    fn to_repr(&self) -> Self::Repr {
+        MerkleTreeTwinProofRepr {
+            left: self.left.to_repr(),
+            right: self.right.to_repr(),
+            siblings: self.siblings.to_repr(),
+        }
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(MerkleTreeTwinProof {
+            left: Vec::from_repr(repr.left)?,
+            right: Vec::from_repr(repr.right)?,
+            siblings: Vec::from_repr(repr.siblings)?,
+        })
+    }
+}
+
+/// The plain representation of `PrecomputedMerkleTreeProof`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PrecomputedMerkleTreeProofRepr {
+    /// The precomputed point of the query.
+    circle_point: [u32; 2],
+    /// The siblings on the path to the root, as hex strings.
+    siblings: Vec<String>,
+}
+
+impl Mirror for PrecomputedMerkleTreeProof {
+    type Repr = PrecomputedMerkleTreeProofRepr;
+
+    fn to_repr(&self) -> Self::Repr {
+        PrecomputedMerkleTreeProofRepr {
+            circle_point: self.circle_point.to_repr(),
+            siblings: self.siblings.to_repr(),
+        }
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(PrecomputedMerkleTreeProof {
+            circle_point: CirclePoint::from_repr(repr.circle_point)?,
+            siblings: Vec::from_repr(repr.siblings)?,
+        })
+    }
+}
+
+impl Mirror for ColumnLineCoeffsHint {
+    type Repr = [u32; 2];
+
+    fn to_repr(&self) -> Self::Repr {
+        self.y_imag_inv.to_repr()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(ColumnLineCoeffsHint {
+            y_imag_inv: CM31::from_repr(repr)?,
+        })
+    }
+}
+
+impl Mirror for PreparedPairVanishingHint {
+    type Repr = [u32; 2];
+
+    fn to_repr(&self) -> Self::Repr {
+        self.x_imag_dbl_inv.to_repr()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(PreparedPairVanishingHint {
+            x_imag_dbl_inv: CM31::from_repr(repr)?,
+        })
+    }
+}
+
+impl Mirror for DenominatorInverseHint {
+    type Repr = Vec<[u32; 2]>;
+
+    fn to_repr(&self) -> Self::Repr {
+        self.inverses.to_repr()
+    }
+
+    fn from_repr(repr: Self::Repr) -> Result<Self> {
+        Ok(DenominatorInverseHint {
+            inverses: <[CM31; 2]>::from_repr(repr)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::proof::test::test_proof;
+    use crate::serialization::VerifierHintsFile;
+    use crate::{
+        verify_with_hints, FibonacciClaimMode, FibonacciVerifierGadget, VerifierConfig, FIB_CLAIM,
+        FIB_LOG_SIZE,
+    };
+    use bitcoin_circle_stark::treepp::*;
+    use bitcoin_scriptexec::execute_script_with_witness_unlimited_stack;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+
+        let hints = verify_with_hints(proof, &fib.air, verifier_config).unwrap();
+        let expected_witness = convert_to_witness(script! {
+            { hints.clone() }
+        })
+        .unwrap();
+        let file = VerifierHintsFile::new(FIB_LOG_SIZE, FIB_CLAIM, verifier_config, hints);
+
+        let from_json = VerifierHintsFile::from_json(&file.to_json().unwrap()).unwrap();
+        let from_bytes = VerifierHintsFile::from_bytes(&file.to_bytes().unwrap()).unwrap();
+
+        for deserialized in [from_json, from_bytes] {
+            deserialized
+                .check(FIB_LOG_SIZE, FIB_CLAIM, verifier_config)
+                .unwrap();
+
+            let witness = convert_to_witness(script! {
+                { deserialized.hints }
+            })
+            .unwrap();
+            assert_eq!(witness, expected_witness);
+
+            let script = script! {
+                { FibonacciVerifierGadget::run_verifier(FibonacciClaimMode::Constant(FIB_CLAIM), FIB_LOG_SIZE, verifier_config) }
+                OP_TRUE
+            };
+            let exec_result = execute_script_with_witness_unlimited_stack(script, witness);
+            assert!(exec_result.success);
+        }
+    }
+
+    #[test]
+    fn test_hints_file_format() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let hints = verify_with_hints(test_proof(), &fib.air, verifier_config).unwrap();
+        let file = VerifierHintsFile::new(FIB_LOG_SIZE, FIB_CLAIM, verifier_config, hints);
+
+        // the format is told by the magic bytes, and a broken JSON file fails with the JSON error
+        for binary in [false, true] {
+            let path = std::env::temp_dir().join(format!("fibonacci-hints-format-{}", binary));
+            file.write(&path, binary).unwrap();
+            let read_file = VerifierHintsFile::read(&path).unwrap();
+            assert_eq!(read_file.to_json().unwrap(), file.to_json().unwrap());
+
+            if !binary {
+                let json = file.to_json().unwrap();
+                std::fs::write(&path, &json[..json.len() - 1]).unwrap();
+                let err = VerifierHintsFile::read(&path).unwrap_err();
+                assert!(err.downcast_ref::<serde_json::Error>().is_some());
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+
+        // the hints of another statement or configuration are rejected
+        assert!(file
+            .check(FIB_LOG_SIZE + 1, FIB_CLAIM, verifier_config)
+            .is_err());
+        assert!(file
+            .check(
+                FIB_LOG_SIZE,
+                FIB_CLAIM + M31::from_u32_unchecked(1),
+                verifier_config
+            )
+            .is_err());
+        let other_config = VerifierConfig {
+            pow_bits: verifier_config.pow_bits + 1,
+            ..verifier_config
+        };
+        assert!(file.check(FIB_LOG_SIZE, FIB_CLAIM, other_config).is_err());
+
+        // the field elements must be canonical
+        let mut value: serde_json::Value = serde_json::from_str(&file.to_json().unwrap()).unwrap();
+        value["hints"]["fiat_shamir_hints"]["last_layer"][0] = 2147483647.into();
+        assert!(VerifierHintsFile::from_json(&value.to_string()).is_err());
+    }
+}
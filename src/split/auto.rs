@@ -0,0 +1,787 @@
+use crate::bitcoin_script::fiat_shamir::FibonacciFiatShamirGadget;
+use crate::bitcoin_script::fold::FibonacciPerQueryFoldGadget;
+use crate::bitcoin_script::prepare::FibonacciPrepareGadget;
+use crate::bitcoin_script::quotients::FibonacciPerQueryQuotientGadget;
+use crate::bitcoin_script::stack_layout::{StackLayout, StackRegion};
+use crate::serialization::SerializedVerifierHints;
+use crate::split::limits::{StackUsage, MAX_STACK_SIZE};
+use crate::split::{
+    FibonacciSplitParameters, FibonacciSplitProgram, FibonacciSplitState, FibonacciSplitStepSize,
+};
+use crate::{FibonacciClaimMode, VerifierConfig};
+use anyhow::bail;
+use bitcoin_circle_stark::treepp::*;
+use bitcoin_circle_stark::utils::clean_stack;
+use covenants_gadgets::utils::stack_hash::StackHash;
+use covenants_gadgets::CovenantProgram;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use stwo_prover::core::fields::m31::M31;
+
+/// A gadget of the verifier, which is the unit that the splitter places into the steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifierSegment {
+    /// The Fiat-Shamir gadget.
+    FiatShamir,
+    /// The prepare gadget.
+    Prepare,
+    /// The quotient gadget of the given query.
+    Quotients(usize),
+    /// The folding gadget of the given query.
+    Fold(usize),
+}
+
+impl Display for VerifierSegment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifierSegment::FiatShamir => write!(f, "Fiat-Shamir"),
+            VerifierSegment::Prepare => write!(f, "prepare"),
+            VerifierSegment::Quotients(query) => write!(f, "quotients of query {}", query),
+            VerifierSegment::Fold(query) => write!(f, "folding of query {}", query),
+        }
+    }
+}
+
+impl VerifierSegment {
+    /// All the segments, in the order of `FibonacciVerifierGadget::run_verifier`.
+    pub fn all(config: VerifierConfig) -> Vec<Self> {
+        let mut segments = vec![Self::FiatShamir, Self::Prepare];
+        for query in 0..config.n_queries {
+            segments.push(Self::Quotients(query));
+            segments.push(Self::Fold(query));
+        }
+        segments
+    }
+
+    /// The script of the gadget.
+    pub fn script(
+        &self,
+        claim: FibonacciClaimMode,
+        log_size: u32,
+        config: VerifierConfig,
+    ) -> Script {
+        match *self {
+            Self::FiatShamir => FibonacciFiatShamirGadget::run(claim, log_size, config),
+            Self::Prepare => FibonacciPrepareGadget::run(log_size, config),
+            Self::Quotients(query) => FibonacciPerQueryQuotientGadget::run(query, log_size, config),
+            Self::Fold(query) => FibonacciPerQueryFoldGadget::run(query, log_size, config),
+        }
+    }
+
+    /// The number of stack elements left by the gadget, which is the input of the next one.
+    pub fn output_size(
+        &self,
+        claim: FibonacciClaimMode,
+        log_size: u32,
+        config: VerifierConfig,
+    ) -> usize {
+        let prepare_output = StackLayout::prepare_output(log_size, config).with_claim(claim);
+        match self {
+            Self::FiatShamir => StackLayout::fiat_shamir_output(log_size, config)
+                .with_claim(claim)
+                .len(),
+            Self::Prepare | Self::Fold(_) => prepare_output.len(),
+            // the twiddle factors and the two answers (qm31) of the query, used by the folding
+            Self::Quotients(_) => {
+                prepare_output
+                    .with(
+                        StackRegion::TwiddleFactors,
+                        (log_size + config.log_blowup_factor) as usize,
+                    )
+                    .len()
+                    + 2 * 4
+            }
+        }
+    }
+
+    /// The witness elements of the hint of the gadget, which starts with the claim in the
+    /// public-input mode.
+    pub fn hint(&self, public_claim: Option<M31>, hints: &SerializedVerifierHints) -> Vec<Vec<u8>> {
+        let script = match *self {
+            Self::FiatShamir => match public_claim {
+                Some(claim) => script! {
+                    { claim }
+                    { hints.fiat_shamir_hints.clone() }
+                },
+                None => script! {
+                    { hints.fiat_shamir_hints.clone() }
+                },
+            },
+            Self::Prepare => script! {
+                { hints.prepare_hints.clone() }
+            },
+            Self::Quotients(query) => script! {
+                { hints.per_query_quotients_hints[query].clone() }
+            },
+            Self::Fold(query) => script! {
+                { hints.per_query_fold_hints[query].clone() }
+            },
+        };
+        convert_to_witness(script).unwrap()
+    }
+}
+
+/// The limits that each step of an automatically split program must respect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitBudget {
+    /// The maximum size of the script plus the witness from the input, in bytes.
+    pub max_size: usize,
+    /// The maximum number of elements in the main stack and the altstack together, at any point
+    /// of the step.
+    pub max_stack_elements: usize,
+}
+
+impl SplitBudget {
+    /// The limits of a standard transaction, i.e., 400000 weight units, minus some room for the
+    /// rest of the transaction, and the consensus limit of the stack.
+    pub const STANDARD: Self = Self {
+        max_size: 395000,
+        max_stack_elements: MAX_STACK_SIZE,
+    };
+}
+
+/// A partition of the segments of the verifier into consecutive steps.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitPlan {
+    /// The segments of the verifier.
+    pub segments: Vec<VerifierSegment>,
+    /// The range of the segments verified in each step.
+    pub steps: Vec<Range<usize>>,
+    /// The sizes of each step.
+    pub sizes: Vec<FibonacciSplitStepSize>,
+}
+
+impl SplitPlan {
+    /// Partition the verifier into the fewest steps within the budget.
+    ///
+    /// The sizes of the witness are measured on the given hints, and the stack carried between
+    /// the segments is obtained by running the gadgets on them.
+    pub fn new<P: FibonacciSplitParameters>(
+        public_claim: Option<M31>,
+        hints: &SerializedVerifierHints,
+        budget: SplitBudget,
+    ) -> anyhow::Result<Self> {
+        let segments = VerifierSegment::all(P::CONFIG);
+        let n = segments.len();
+
+        let scripts = segments
+            .iter()
+            .map(|segment| segment.script(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG))
+            .collect::<Vec<_>>();
+        let segment_hints = segments
+            .iter()
+            .map(|segment| segment.hint(public_claim, hints))
+            .collect::<Vec<_>>();
+
+        // stacks[i] is the stack before the i-th segment, and peaks[i] is the peak stack depth
+        // of the i-th segment
+        let mut stacks = vec![vec![]];
+        let mut peaks = vec![];
+        for ((segment, script), hint) in segments
+            .iter()
+            .zip(scripts.iter())
+            .zip(segment_hints.iter())
+        {
+            let mut witness = hint.clone();
+            witness.extend_from_slice(stacks.last().unwrap());
+
+            peaks.push(StackUsage::measure(0, script.clone(), witness.clone()).peak_stack_depth);
+
+            let final_stack = get_final_stack(script.clone(), witness);
+            let output_size = segment.output_size(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG);
+            if final_stack.len() != output_size {
+                bail!(
+                    "the {} gadget leaves {} stack elements instead of {}",
+                    segment,
+                    final_stack.len(),
+                    output_size
+                );
+            }
+            stacks.push(final_stack);
+        }
+
+        let gadgets_of_step = |range: Range<usize>| {
+            script! {
+                for script in scripts[range].iter() {
+                    { script.clone() }
+                }
+            }
+        };
+
+        let witness_of_step = |range: Range<usize>| {
+            stacks[range.start]
+                .iter()
+                .chain(segment_hints[range].iter().flatten())
+                .map(|elem| elem.len())
+                .collect::<Vec<_>>()
+        };
+
+        // the size of a step, where the script size is estimated by adding up the sizes of the
+        // gadgets to the size of an empty step
+        let estimated_size_of_step = |index: usize, range: Range<usize>| {
+            let script_size = step_script(
+                index,
+                range.end == n,
+                stacks[range.start].len(),
+                stacks[range.end].len(),
+                script! {},
+            )
+            .len()
+                + scripts[range.clone()]
+                    .iter()
+                    .map(|script| script.len())
+                    .sum::<usize>();
+            let witness = witness_of_step(range);
+
+            FibonacciSplitStepSize {
+                index,
+                script_size,
+                num_witness_elements: witness.len(),
+                witness_size: witness.iter().sum(),
+            }
+        };
+
+        let fits = |index: usize, range: Range<usize>| {
+            let size = estimated_size_of_step(index, range.clone());
+            if size.script_size + size.witness_size > budget.max_size {
+                return false;
+            }
+            // the peak of a step is the peak of one of its gadgets, with the hints of the later
+            // gadgets below, and the states or the stack hashes
+            range.clone().all(|i| {
+                let pending_hints: usize = segment_hints[i + 1..range.end]
+                    .iter()
+                    .map(|hint| hint.len())
+                    .sum();
+                peaks[i] + pending_hints + 4 <= budget.max_stack_elements
+            })
+        };
+
+        // best[j] is the fewest steps for the first j segments, and the start of the last step
+        let mut best: Vec<Option<(usize, usize)>> = vec![None; n + 1];
+        best[0] = Some((0, 0));
+        for end in 1..=n {
+            for start in 0..end {
+                if let Some((num_steps, _)) = best[start] {
+                    let better = match best[end] {
+                        Some((current, _)) => num_steps + 1 < current,
+                        None => true,
+                    };
+                    if better && fits(num_steps, start..end) {
+                        best[end] = Some((num_steps + 1, start));
+                    }
+                }
+            }
+        }
+
+        if best[n].is_none() {
+            match (0..n).find(|&i| !fits(0, i..i + 1)) {
+                Some(i) => bail!("the {} gadget does not fit into a step", segments[i]),
+                None => bail!("the verifier cannot be split within the budget"),
+            }
+        }
+
+        let mut steps = vec![];
+        let mut end = n;
+        while end > 0 {
+            let (_, start) = best[end].unwrap();
+            steps.push(start..end);
+            end = start;
+        }
+        steps.reverse();
+
+        let sizes = steps
+            .iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let script = step_script(
+                    index,
+                    range.end == n,
+                    stacks[range.start].len(),
+                    stacks[range.end].len(),
+                    gadgets_of_step(range.clone()),
+                );
+                let witness = witness_of_step(range.clone());
+
+                FibonacciSplitStepSize {
+                    index,
+                    script_size: script.len(),
+                    num_witness_elements: witness.len(),
+                    witness_size: witness.iter().sum(),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            segments,
+            steps,
+            sizes,
+        })
+    }
+}
+
+/// The script of a step that runs the given gadgets on the stack carried from the previous step,
+/// and commits to the resulting stack, or resets the program if it is the last step.
+fn step_script(
+    index: usize,
+    is_last: bool,
+    input_size: usize,
+    output_size: usize,
+    gadgets: Script,
+) -> Script {
+    script! {
+        // input:
+        // - old pc
+        // - old stack hash
+        // - new pc
+        // - new stack hash
+
+        if is_last {
+            // the last step resets the program
+            OP_SWAP 0 OP_EQUALVERIFY
+            OP_ROT { index } OP_EQUALVERIFY
+
+            // stack:
+            // - old stack hash
+            // - new stack hash
+            { [0u8; 32].to_vec() } OP_EQUALVERIFY
+        } else {
+            OP_SWAP { index + 1 } OP_EQUALVERIFY
+            OP_ROT { index } OP_EQUALVERIFY
+
+            // stack:
+            // - old stack hash
+            // - new stack hash
+            OP_TOALTSTACK
+        }
+
+        if input_size == 0 {
+            { [0u8; 32].to_vec() } OP_EQUALVERIFY
+        } else {
+            OP_TOALTSTACK
+            { StackHash::hash_from_hint(input_size) }
+            OP_FROMALTSTACK OP_EQUALVERIFY
+        }
+
+        { gadgets }
+
+        OP_DEPTH
+        { output_size }
+        OP_EQUALVERIFY
+
+        if is_last {
+            { clean_stack(output_size) }
+        } else {
+            { StackHash::hash_drop(output_size) }
+            OP_FROMALTSTACK OP_EQUALVERIFY
+        }
+        OP_TRUE
+    }
+}
+
+/// The parameters of an automatically split Fibonacci program.
+///
+/// `QUERIES_PER_STEP` is ignored, as the splitter decides which gadgets go into each step.
+pub trait AutoSplitParameters: FibonacciSplitParameters {
+    /// The limits of each step.
+    const BUDGET: SplitBudget;
+}
+
+/// The input to the automatically split program.
+#[derive(Clone)]
+pub struct AutoSplitInput {
+    /// The stack from the previous step.
+    pub stack: Vec<Vec<u8>>,
+    /// The hints of the gadgets of the step.
+    pub hints: Vec<Vec<u8>>,
+}
+
+impl From<AutoSplitInput> for Script {
+    fn from(v: AutoSplitInput) -> Self {
+        script! {
+            for elem in v.stack {
+                { elem }
+            }
+            for elem in v.hints {
+                { elem }
+            }
+        }
+    }
+}
+
+/// A Fibonacci split program where the steps are chosen by `SplitPlan` under the budget of the
+/// parameters.
+///
+/// The scripts are laid out as follows:
+/// - 0, ..., num_steps - 1: the steps of the plan, where the last one resets the program
+/// - num_steps: reset, signed by the operator, whose input has the signature as the only hint
+///
+/// The plan is an input of the program, set by `set_plan` before the program is used, and is kept
+/// by `CACHE_NAME`.
+pub struct AutoSplitProgram<P: AutoSplitParameters> {
+    _marker: PhantomData<P>,
+}
+
+impl<P: AutoSplitParameters> AutoSplitProgram<P> {
+    fn plans() -> MutexGuard<'static, HashMap<&'static str, SplitPlan>> {
+        static PLANS: OnceLock<Mutex<HashMap<&'static str, SplitPlan>>> = OnceLock::new();
+        PLANS.get_or_init(Default::default).lock().unwrap()
+    }
+
+    /// Set the plan of the program, as computed by `SplitPlan::new` under the budget of the
+    /// parameters.
+    ///
+    /// The hints of all the proofs have the same sizes, so the plan can be computed from the hints
+    /// of any proof, such as the first one to verify. Setting the plan again is only allowed with
+    /// the same plan, since the scripts of the program depend on it.
+    pub fn set_plan(plan: SplitPlan) -> anyhow::Result<()> {
+        if plan.segments != VerifierSegment::all(P::CONFIG) {
+            bail!("the plan is not made of the segments of the verifier");
+        }
+
+        let mut plans = Self::plans();
+        match plans.get(P::CACHE_NAME) {
+            Some(current) if *current != plan => {
+                bail!("a different plan is already set for {}", P::CACHE_NAME)
+            }
+            _ => {
+                plans.insert(P::CACHE_NAME, plan);
+                Ok(())
+            }
+        }
+    }
+
+    /// The plan of the program, which must have been set by `set_plan`.
+    pub fn plan() -> SplitPlan {
+        Self::plans()
+            .get(P::CACHE_NAME)
+            .cloned()
+            .unwrap_or_else(|| panic!("the plan of {} is not set", P::CACHE_NAME))
+    }
+
+    /// The number of steps to verify a proof, which is also the number of transactions.
+    pub fn num_steps() -> usize {
+        Self::plan().steps.len()
+    }
+
+    /// The index of the script that resets the program.
+    pub fn reset_index() -> usize {
+        Self::num_steps()
+    }
+
+    /// The input for the step following the given state, taken from the hints.
+    pub fn input_for_state(
+        state: &FibonacciSplitState,
+        public_claim: Option<M31>,
+        hints: &SerializedVerifierHints,
+    ) -> AutoSplitInput {
+        let plan = Self::plan();
+        AutoSplitInput {
+            stack: state.stack.clone(),
+            hints: plan.segments[plan.steps[state.pc].clone()]
+                .iter()
+                .flat_map(|segment| segment.hint(public_claim, hints))
+                .collect(),
+        }
+    }
+}
+
+impl<P: AutoSplitParameters> CovenantProgram for AutoSplitProgram<P> {
+    type State = FibonacciSplitState;
+    type Input = AutoSplitInput;
+    const CACHE_NAME: &'static str = P::CACHE_NAME;
+
+    fn new() -> Self::State {
+        FibonacciSplitState {
+            pc: 0,
+            stack_hash: vec![0u8; 32],
+            stack: vec![],
+        }
+    }
+
+    fn get_hash(state: &Self::State) -> Vec<u8> {
+        FibonacciSplitProgram::<P>::get_hash(state)
+    }
+
+    fn get_all_scripts() -> BTreeMap<usize, Script> {
+        let plan = Self::plan();
+        let num_steps = plan.steps.len();
+
+        let mut map = BTreeMap::new();
+        for (index, range) in plan.steps.iter().enumerate() {
+            let input_size = match range.start {
+                0 => 0,
+                start => {
+                    plan.segments[start - 1].output_size(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG)
+                }
+            };
+            let output_size =
+                plan.segments[range.end - 1].output_size(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG);
+
+            map.insert(
+                index,
+                step_script(
+                    index,
+                    index == num_steps - 1,
+                    input_size,
+                    output_size,
+                    script! {
+                        for segment in plan.segments[range.clone()].iter() {
+                            { segment.script(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG) }
+                        }
+                    },
+                ),
+            );
+        }
+        map.insert(num_steps, FibonacciSplitProgram::<P>::reset_script());
+        map
+    }
+
+    fn get_common_prefix() -> Script {
+        FibonacciSplitProgram::<P>::get_common_prefix()
+    }
+
+    fn run(id: usize, old_state: &Self::State, input: &Self::Input) -> anyhow::Result<Self::State> {
+        let plan = Self::plan();
+        let num_steps = plan.steps.len();
+
+        if id < num_steps {
+            if old_state.pc != id {
+                bail!(
+                    "the step {} cannot follow the state at {}",
+                    id,
+                    old_state.pc
+                );
+            }
+
+            if id == num_steps - 1 {
+                return Ok(Self::new());
+            }
+
+            let script = script! {
+                for segment in plan.segments[plan.steps[id].clone()].iter() {
+                    { segment.script(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG) }
+                }
+            };
+
+            let mut witness = input.hints.clone();
+            witness.extend_from_slice(&input.stack);
+
+            let final_stack = get_final_stack(script, witness);
+            let stack_hash = StackHash::compute(&final_stack);
+
+            Ok(Self::State {
+                pc: id + 1,
+                stack_hash,
+                stack: final_stack,
+            })
+        } else if id == num_steps {
+            if !input.stack.is_empty() || input.hints.len() != 1 {
+                bail!("the reset takes the signature of the operator as its only input");
+            }
+            Ok(Self::new())
+        } else {
+            bail!("the program has no script {}", id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chain::test::fee_of_step;
+    use crate::proof::test::{proof_for, test_proof};
+    use crate::serialization::SerializedVerifierHints;
+    use crate::split::auto::{
+        AutoSplitInput, AutoSplitParameters, AutoSplitProgram, SplitBudget, SplitPlan,
+        VerifierSegment,
+    };
+    use crate::split::limits::{check_stack_usage, stack_usage_of_steps, MAX_STACK_SIZE};
+    use crate::split::{FibonacciSplitParameters, FibonacciSplitState};
+    use crate::{
+        fibonacci_claim, verify_with_hints, FibonacciClaimMode, VerifierConfig, FIB_CLAIM,
+        FIB_LOG_SIZE,
+    };
+    use covenants_gadgets::test::{simulation_test, SimulationInstruction};
+    use covenants_gadgets::CovenantProgram;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    struct StandardParameters;
+
+    impl FibonacciSplitParameters for StandardParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-AUTO";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+    }
+
+    impl AutoSplitParameters for StandardParameters {
+        const BUDGET: SplitBudget = SplitBudget::STANDARD;
+    }
+
+    struct PublicInputParameters;
+
+    impl FibonacciSplitParameters for PublicInputParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-AUTO-PUBLIC-INPUT";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::PublicInput;
+        const CONFIG: VerifierConfig = VerifierConfig {
+            n_queries: 4,
+            ..VerifierConfig::DEFAULT
+        };
+    }
+
+    impl AutoSplitParameters for PublicInputParameters {
+        const BUDGET: SplitBudget = SplitBudget {
+            max_size: 800000,
+            max_stack_elements: MAX_STACK_SIZE,
+        };
+    }
+
+    /// The hints of a proof under the parameters, with the plan of the program computed from them.
+    fn hints_with_plan<P: AutoSplitParameters>() -> (Option<M31>, SerializedVerifierHints) {
+        let (claim, public_claim) = match P::CLAIM_MODE {
+            FibonacciClaimMode::Constant(claim) => (claim, None),
+            FibonacciClaimMode::PublicInput => {
+                let claim = fibonacci_claim(P::LOG_SIZE);
+                (claim, Some(claim))
+            }
+        };
+
+        let fib = Fibonacci::new(P::LOG_SIZE, claim);
+        let proof = proof_for(P::LOG_SIZE, claim, P::CONFIG);
+        let hints =
+            SerializedVerifierHints::from(&verify_with_hints(proof, &fib.air, P::CONFIG).unwrap());
+
+        let plan = SplitPlan::new::<P>(public_claim, &hints, P::BUDGET).unwrap();
+        AutoSplitProgram::<P>::set_plan(plan).unwrap();
+        (public_claim, hints)
+    }
+
+    #[test]
+    fn test_plan() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let config = VerifierConfig::default();
+        let proof = test_proof();
+        let hints =
+            SerializedVerifierHints::from(&verify_with_hints(proof, &fib.air, config).unwrap());
+
+        let budget = SplitBudget::STANDARD;
+        let plan = SplitPlan::new::<StandardParameters>(None, &hints, budget).unwrap();
+
+        // the steps cover all the segments in order
+        assert_eq!(plan.segments, VerifierSegment::all(config));
+        assert_eq!(plan.steps.first().unwrap().start, 0);
+        assert_eq!(plan.steps.last().unwrap().end, plan.segments.len());
+        for (step, next) in plan.steps.iter().zip(plan.steps.iter().skip(1)) {
+            assert!(!step.is_empty());
+            assert_eq!(step.end, next.start);
+        }
+
+        for (step, size) in plan.steps.iter().zip(plan.sizes.iter()) {
+            println!(
+                "script {} ({} to {}): script size {}, witness size {} ({} elements)",
+                size.index,
+                plan.segments[step.start],
+                plan.segments[step.end - 1],
+                size.script_size,
+                size.witness_size,
+                size.num_witness_elements
+            );
+            assert!(size.script_size + size.witness_size <= budget.max_size);
+            assert!(size.num_witness_elements <= budget.max_stack_elements);
+        }
+
+        // the script sizes match the program
+        AutoSplitProgram::<StandardParameters>::set_plan(plan.clone()).unwrap();
+        let scripts = AutoSplitProgram::<StandardParameters>::get_all_scripts();
+        for size in plan.sizes.iter() {
+            assert_eq!(scripts[&size.index].len(), size.script_size);
+        }
+
+        // a larger budget needs fewer steps
+        let larger = SplitPlan::new::<StandardParameters>(
+            None,
+            &hints,
+            SplitBudget {
+                max_size: 2 * budget.max_size,
+                ..budget
+            },
+        )
+        .unwrap();
+        assert!(larger.steps.len() < plan.steps.len());
+
+        // the plan cannot change once the program uses it
+        assert!(AutoSplitProgram::<StandardParameters>::set_plan(larger).is_err());
+        AutoSplitProgram::<StandardParameters>::set_plan(plan.clone()).unwrap();
+
+        // a step only follows the state at the same step
+        let input = AutoSplitInput {
+            stack: vec![],
+            hints: vec![],
+        };
+        let state = AutoSplitProgram::<StandardParameters>::new();
+        assert!(AutoSplitProgram::<StandardParameters>::run(1, &state, &input).is_err());
+        assert!(
+            AutoSplitProgram::<StandardParameters>::run(plan.steps.len() + 1, &state, &input)
+                .is_err()
+        );
+
+        // no gadget fits into a tiny budget
+        assert!(SplitPlan::new::<StandardParameters>(
+            None,
+            &hints,
+            SplitBudget {
+                max_size: 1000,
+                ..budget
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_stack_limits() {
+        type Program = AutoSplitProgram<StandardParameters>;
+
+        let (public_claim, hints) = hints_with_plan::<StandardParameters>();
+        let usages = stack_usage_of_steps::<Program>(Program::num_steps(), |state| {
+            (
+                state.pc,
+                Program::input_for_state(state, public_claim, &hints),
+            )
+        })
+        .unwrap();
+        println!("{}", check_stack_usage(&usages).unwrap());
+    }
+
+    fn test_integration_with_parameters<P: AutoSplitParameters>() {
+        let (public_claim, hints) = hints_with_plan::<P>();
+
+        let mut test_generator = |old_state: &FibonacciSplitState| {
+            let program_input =
+                AutoSplitProgram::<P>::input_for_state(old_state, public_claim, &hints);
+            let fee =
+                fee_of_step::<AutoSplitProgram<P>>(old_state.pc, old_state, &program_input, 7);
+
+            Some(SimulationInstruction {
+                program_index: old_state.pc,
+                fee: fee as usize,
+                program_input,
+            })
+        };
+
+        simulation_test::<AutoSplitProgram<P>>(
+            2 * AutoSplitProgram::<P>::num_steps(),
+            &mut test_generator,
+        );
+    }
+
+    #[test]
+    fn test_integration() {
+        test_integration_with_parameters::<StandardParameters>();
+        test_integration_with_parameters::<PublicInputParameters>();
+    }
+}
@@ -0,0 +1,371 @@
+use crate::bitcoin_script::fiat_shamir::FibonacciFiatShamirGadget;
+use crate::bitcoin_script::fold::FibonacciPerQueryFoldGadget;
+use crate::bitcoin_script::prepare::FibonacciPrepareGadget;
+use crate::bitcoin_script::quotients::FibonacciPerQueryQuotientGadget;
+use crate::chain::CovenantWitness;
+use crate::error::DecodeError;
+use crate::serialization::WitnessHint;
+use crate::split::{
+    FibonacciSplitInput, FibonacciSplitParameters, FibonacciSplitProgram, FibonacciSplitState,
+};
+use crate::FibonacciClaimMode;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::script::read_scriptint;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{ScriptBuf, Transaction};
+use bitcoin_circle_stark::treepp::*;
+use covenants_gadgets::utils::stack_hash::StackHash;
+use covenants_gadgets::{get_script_pub_key, CovenantProgram};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use stwo_prover::core::fields::m31::{self, M31};
+
+/// A transaction of the Fibonacci split program, decoded from the witness of its program input.
+#[derive(Clone, Debug)]
+pub struct FibonacciSplitTx {
+    /// The index of the script that the transaction executes.
+    pub program_index: usize,
+    /// The old state, with the stack carried in the input.
+    ///
+    /// The stack is empty for Fiat-Shamir and the reset, since their inputs do not carry it.
+    pub old_state: FibonacciSplitState,
+    /// The new state, with the stack computed from the input.
+    pub new_state: FibonacciSplitState,
+    /// The input of the step, as `get_tx` takes it.
+    pub input: FibonacciSplitInput,
+    /// The elements of the covenant.
+    pub covenant: CovenantWitness,
+}
+
+impl<P: FibonacciSplitParameters> FibonacciSplitProgram<P> {
+    /// Decode a transaction produced by `get_tx` for this program.
+    ///
+    /// The script of the transaction is one of the leaves of the program, as the control block
+    /// proves, and it ends with the script of the step, which gives the program index. The program
+    /// elements of the witness are the old state and the new state, as hinted to the common prefix,
+    /// followed by the input of the step in the order that `FibonacciSplitInput` pushes it. The new
+    /// stack is recomputed by running the step natively, so the result is enough to continue the
+    /// program from the new state.
+    pub fn decode_tx(tx: &Transaction) -> Result<FibonacciSplitTx, DecodeError> {
+        let covenant = CovenantWitness::from_tx(tx)?;
+        let program_index = Self::program_index_of(&covenant)?;
+
+        let elements = &covenant.program_elements;
+        if elements.len() < 4 {
+            return Err(DecodeError::UnexpectedWitnessLength {
+                len: CovenantWitness::NUM_COVENANT_ELEMENTS + elements.len() + 2,
+            });
+        }
+
+        let read_state = |index: usize| {
+            let malformed = DecodeError::MalformedElement {
+                index: CovenantWitness::NUM_COVENANT_ELEMENTS + index,
+            };
+            let pc = read_scriptint(&elements[index]).map_err(|_| malformed.clone())?;
+            if pc < 0 || elements[index + 1].len() != 32 {
+                return Err(malformed);
+            }
+            Ok(FibonacciSplitState {
+                pc: pc as usize,
+                stack_hash: elements[index + 1].clone(),
+                stack: vec![],
+            })
+        };
+        let mut old_state = read_state(0)?;
+        let mut new_state = read_state(2)?;
+        if Self::get_hash(&old_state) != covenant.old_state_hash
+            || Self::get_hash(&new_state) != covenant.new_state_hash
+        {
+            return Err(DecodeError::StateHashMismatch);
+        }
+
+        let input = &elements[4..];
+
+        if program_index == Self::reset_index() {
+            // the signature of the operator is the only element of the input
+            if input.len() != 1 {
+                return Err(DecodeError::UnexpectedWitnessLength {
+                    len: CovenantWitness::NUM_COVENANT_ELEMENTS + elements.len() + 2,
+                });
+            }
+            return Ok(FibonacciSplitTx {
+                program_index,
+                old_state,
+                new_state,
+                input: FibonacciSplitInput::Reset(input[0].clone()),
+                covenant,
+            });
+        }
+
+        if old_state.pc != program_index {
+            return Err(DecodeError::ProgramCounterMismatch {
+                pc: old_state.pc,
+                program_index,
+            });
+        }
+
+        let decoded_input;
+        if program_index == 0 {
+            let claim = match P::CLAIM_MODE {
+                FibonacciClaimMode::Constant(_) => None,
+                FibonacciClaimMode::PublicInput => {
+                    let malformed = DecodeError::MalformedElement {
+                        index: CovenantWitness::NUM_COVENANT_ELEMENTS + 4,
+                    };
+                    let value = input
+                        .first()
+                        .and_then(|elem| read_scriptint(elem).ok())
+                        .ok_or_else(|| malformed.clone())?;
+                    if !(0..m31::P as i64).contains(&value) {
+                        return Err(malformed);
+                    }
+                    Some(M31::from_u32_unchecked(value as u32))
+                }
+            };
+            let hints_start = if claim.is_some() { 1 } else { 0 };
+            decoded_input =
+                FibonacciSplitInput::FiatShamir(claim, WitnessHint(input[hints_start..].to_vec()));
+
+            let script = script! {
+                { FibonacciFiatShamirGadget::run(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG) }
+            };
+            new_state.stack = get_final_stack(script, input.to_vec());
+        } else {
+            // the carried stack is the prefix of the input that matches the old stack hash
+            let stack_len = (1..=input.len())
+                .find(|&len| {
+                    let stack = input[..len].to_vec();
+                    StackHash::compute(&stack) == old_state.stack_hash
+                })
+                .ok_or(DecodeError::StackNotFound)?;
+            old_state.stack = input[..stack_len].to_vec();
+            let hints = &input[stack_len..];
+
+            if program_index == 1 {
+                let script = script! {
+                    { FibonacciPrepareGadget::run(P::LOG_SIZE, P::CONFIG) }
+                };
+
+                // the native execution takes the hints before the stack
+                let mut witness = hints.to_vec();
+                witness.extend_from_slice(&old_state.stack);
+                new_state.stack = get_final_stack(script, witness);
+
+                decoded_input = FibonacciSplitInput::Prepare(
+                    old_state.stack.clone(),
+                    WitnessHint(hints.to_vec()),
+                );
+            } else {
+                let (quotients_hints, fold_hints) = Self::split_query_hints(
+                    program_index - Self::query_step_index(0),
+                    &old_state.stack,
+                    hints,
+                )?;
+                if new_state.pc != 0 {
+                    new_state.stack = old_state.stack.clone();
+                }
+
+                decoded_input = FibonacciSplitInput::PerQuery(
+                    old_state.stack.clone(),
+                    quotients_hints,
+                    fold_hints,
+                );
+            }
+        }
+
+        if !new_state.stack.is_empty()
+            && StackHash::compute(&new_state.stack) != new_state.stack_hash
+        {
+            return Err(DecodeError::StackHashMismatch);
+        }
+
+        Ok(FibonacciSplitTx {
+            program_index,
+            old_state,
+            new_state,
+            input: decoded_input,
+            covenant,
+        })
+    }
+
+    /// The index of the script of the transaction, whose control block must commit it to the
+    /// output of the program.
+    ///
+    /// The script is the covenant and the common prefix followed by the script of the step, so it
+    /// ends with the longest of the scripts of the program that it ends with.
+    fn program_index_of(covenant: &CovenantWitness) -> Result<usize, DecodeError> {
+        let script_pub_key = get_script_pub_key::<Self>();
+        let output_key = XOnlyPublicKey::from_slice(&script_pub_key.as_bytes()[2..34]).unwrap();
+        if !covenant.control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            output_key,
+            &covenant.script,
+        ) {
+            return Err(DecodeError::UnknownScript);
+        }
+
+        // compiling the scripts is slow, so they are kept by `CACHE_NAME`
+        static SCRIPTS: OnceLock<Mutex<HashMap<&'static str, Vec<(usize, ScriptBuf)>>>> =
+            OnceLock::new();
+        let mut cache = SCRIPTS.get_or_init(Default::default).lock().unwrap();
+        let scripts = cache.entry(P::CACHE_NAME).or_insert_with(|| {
+            Self::get_all_scripts()
+                .into_iter()
+                .map(|(index, script)| (index, script.compile()))
+                .collect()
+        });
+
+        scripts
+            .iter()
+            .filter(|(_, script)| covenant.script.as_bytes().ends_with(script.as_bytes()))
+            .max_by_key(|(_, script)| script.len())
+            .map(|(index, _)| *index)
+            .ok_or(DecodeError::UnknownScript)
+    }
+
+    /// Split the hints of a per-query step into the quotient hints and the folding hints of each
+    /// query, by running the gadgets natively.
+    ///
+    /// The hints are taken from the bottom of the stack, so those that a gadget does not consume
+    /// are left at the bottom of its final stack, below its output.
+    fn split_query_hints(
+        step: usize,
+        stack: &[Vec<u8>],
+        hints: &[Vec<u8>],
+    ) -> Result<(Vec<WitnessHint>, Vec<WitnessHint>), DecodeError> {
+        let num_consumed = |script: Script, hints: &[Vec<u8>], stack: &[Vec<u8>]| {
+            let mut witness = hints.to_vec();
+            witness.extend_from_slice(stack);
+            let final_stack = get_final_stack(script, witness);
+
+            let num_consumed = (0..=hints.len())
+                .find(|&num_consumed| {
+                    let num_left = hints.len() - num_consumed;
+                    final_stack.len() >= num_left
+                        && final_stack[..num_left] == hints[num_consumed..]
+                })
+                .unwrap();
+            let output = final_stack[hints.len() - num_consumed..].to_vec();
+            (num_consumed, output)
+        };
+
+        let mut quotients_hints = vec![];
+        let mut fold_hints = vec![];
+        let mut stack = stack.to_vec();
+        let mut hints = hints;
+        for query in Self::queries_of_step(step) {
+            let quotient_script = script! {
+                { FibonacciPerQueryQuotientGadget::run(query, P::LOG_SIZE, P::CONFIG) }
+            };
+            let query_script = script! {
+                { FibonacciPerQueryQuotientGadget::run(query, P::LOG_SIZE, P::CONFIG) }
+                { FibonacciPerQueryFoldGadget::run(query, P::LOG_SIZE, P::CONFIG) }
+            };
+            let (num_quotient_hints, _) = num_consumed(quotient_script, hints, &stack);
+            let (num_query_hints, output) = num_consumed(query_script, hints, &stack);
+
+            quotients_hints.push(WitnessHint(hints[..num_quotient_hints].to_vec()));
+            fold_hints.push(WitnessHint(
+                hints[num_quotient_hints..num_query_hints].to_vec(),
+            ));
+            hints = &hints[num_query_hints..];
+            stack = output;
+        }
+
+        if !hints.is_empty() {
+            return Err(DecodeError::UnexpectedHints { len: hints.len() });
+        }
+        Ok((quotients_hints, fold_hints))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::DecodeError;
+    use crate::proof::test::test_proof;
+    use crate::serialization::SerializedVerifierHints;
+    use crate::split::{DefaultFibonacciSplitParameters, FibonacciSplitProgram};
+    use crate::{verify_with_hints, VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE};
+    use bitcoin::hashes::Hash;
+    use bitcoin::opcodes::OP_TRUE;
+    use bitcoin::{OutPoint, Txid, Witness};
+    use covenants_gadgets::{get_tx, CovenantInput, CovenantProgram, DUST_AMOUNT};
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+
+    #[test]
+    fn test_decode_tx() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+        let hints = SerializedVerifierHints::from(
+            &verify_with_hints(proof, &fib.air, verifier_config).unwrap(),
+        );
+
+        let mut info = CovenantInput {
+            old_randomizer: 12,
+            old_balance: 10000000,
+            old_txid: Txid::all_zeros(),
+            input_outpoint1: OutPoint::null(),
+            input_outpoint2: None,
+            optional_deposit_input: None,
+            new_balance: 0,
+        };
+
+        let mut old_state = Program::new();
+        for _ in 0..Program::num_steps() {
+            let input = Program::input_for_state(&old_state, None, &hints);
+            let new_state = Program::run(old_state.pc, &old_state, &input).unwrap();
+
+            info.new_balance = info.old_balance - 100000 - DUST_AMOUNT;
+            let (tx_template, randomizer) =
+                get_tx::<Program>(&info, old_state.pc, &old_state, &new_state, &input);
+
+            let decoded = Program::decode_tx(&tx_template.tx).unwrap();
+            assert_eq!(decoded.program_index, old_state.pc);
+            assert_eq!(decoded.old_state.pc, old_state.pc);
+            assert_eq!(decoded.old_state.stack_hash, old_state.stack_hash);
+            assert_eq!(decoded.old_state.stack, old_state.stack);
+            assert_eq!(decoded.new_state.pc, new_state.pc);
+            assert_eq!(decoded.new_state.stack_hash, new_state.stack_hash);
+            assert_eq!(decoded.new_state.stack, new_state.stack);
+            assert_eq!(decoded.input, input);
+            assert_eq!(decoded.covenant.old_balance, info.old_balance);
+            assert_eq!(decoded.covenant.new_balance, info.new_balance);
+            assert_eq!(decoded.covenant.old_randomizer, info.old_randomizer);
+            assert_eq!(decoded.covenant.new_randomizer, randomizer);
+            assert_eq!(decoded.covenant.old_txid, info.old_txid);
+
+            info.old_randomizer = randomizer;
+            info.old_balance = info.new_balance;
+            info.old_txid = tx_template.tx.compute_txid();
+            info.input_outpoint1 = tx_template.tx.input[0].previous_output;
+            old_state = new_state;
+        }
+
+        // the reset only has the signature in its input
+        let reset_state = Program::new();
+        info.new_balance = info.old_balance - 100000 - DUST_AMOUNT;
+        let (mut tx_template, _) = get_tx::<Program>(
+            &info,
+            Program::reset_index(),
+            &old_state,
+            &reset_state,
+            &Program::unsigned_reset_input(),
+        );
+        let decoded = Program::decode_tx(&tx_template.tx).unwrap();
+        assert_eq!(decoded.program_index, Program::reset_index());
+        assert_eq!(decoded.input, Program::unsigned_reset_input());
+
+        // a script outside the program is not committed to by the control block
+        let mut witness = tx_template.tx.input[0].witness.to_vec();
+        let script_index = witness.len() - 2;
+        witness[script_index].push(OP_TRUE.to_u8());
+        tx_template.tx.input[0].witness = Witness::from_slice(&witness);
+        assert!(matches!(
+            Program::decode_tx(&tx_template.tx),
+            Err(DecodeError::UnknownScript)
+        ));
+    }
+}
@@ -0,0 +1,366 @@
+use crate::chain::{caboose_script_pub_key, CovenantWitness};
+use crate::rpc::RpcClient;
+use crate::split::{FibonacciSplitParameters, FibonacciSplitProgram, FibonacciSplitState};
+use anyhow::{bail, Result};
+use bitcoin::{Address, OutPoint, Transaction};
+use covenants_gadgets::CovenantProgram;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/// The status of an instance of the program on the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FollowerStatus {
+    /// The initial output is not spent yet.
+    NotStarted,
+    /// Some steps are done, and the next step is expected.
+    InProgress,
+    /// The last step is done, so the proof is verified.
+    Completed,
+    /// The last transaction resets the program to the initial state.
+    Reset,
+}
+
+impl Display for FollowerStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FollowerStatus::NotStarted => write!(f, "not started"),
+            FollowerStatus::InProgress => write!(f, "in progress"),
+            FollowerStatus::Completed => write!(f, "completed"),
+            FollowerStatus::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+/// The progress of an instance of the program, as of the last poll.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FollowerProgress {
+    /// The status of the instance.
+    pub status: FollowerStatus,
+    /// The number of steps done since the program was last in the initial state.
+    pub step: usize,
+    /// The number of steps of the program.
+    pub num_steps: usize,
+    /// The remaining balance of the program output.
+    pub balance: u64,
+    /// The current program output.
+    pub tip: OutPoint,
+    /// The number of transactions followed from the initial output, including resets.
+    pub num_txs: usize,
+    /// Whether the instance is in progress but has not moved for the stall timeout.
+    pub stalled: bool,
+}
+
+impl Display for FollowerProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, step {} of {}, balance {} sat, tip {}",
+            self.status, self.step, self.num_steps, self.balance, self.tip
+        )?;
+        if self.stalled {
+            write!(f, ", stalled")?;
+        }
+        Ok(())
+    }
+}
+
+/// A follower of an instance of the Fibonacci split program, which walks the transactions that
+/// spend the program output from the initial output and reconstructs the state.
+///
+/// Each transaction is checked against the state that the follower has so far: it must spend the
+/// current program output, start from the hash of the current state, and carry the caboose of the
+/// new state.
+pub struct FibonacciSplitFollower<P: FibonacciSplitParameters> {
+    /// The address of the program.
+    address: Address,
+    /// The current program output.
+    tip: OutPoint,
+    /// The current state.
+    state: FibonacciSplitState,
+    /// The balance of the current program output, once the initial transaction is fetched.
+    balance: Option<u64>,
+    /// The status of the instance.
+    status: FollowerStatus,
+    /// The number of transactions followed.
+    num_txs: usize,
+    /// The time without a new transaction after which an instance in progress is stalled.
+    stall_timeout: Duration,
+    /// The time of the last new transaction, or of the creation of the follower.
+    last_progress: Instant,
+    /// The parameters of the program.
+    _phantom: PhantomData<P>,
+}
+
+impl<P: FibonacciSplitParameters> FibonacciSplitFollower<P> {
+    /// Create a follower of the instance funded by the initial output, which must pay the address
+    /// of the program.
+    pub fn new(address: Address, initial_utxo: OutPoint, stall_timeout: Duration) -> Self {
+        Self {
+            address,
+            tip: initial_utxo,
+            state: FibonacciSplitProgram::<P>::new(),
+            balance: None,
+            status: FollowerStatus::NotStarted,
+            num_txs: 0,
+            stall_timeout,
+            last_progress: Instant::now(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The current state, with the stack needed to continue the program.
+    pub fn state(&self) -> &FibonacciSplitState {
+        &self.state
+    }
+
+    /// Follow the new transactions, in the mempool or in blocks, and report the progress.
+    ///
+    /// The node needs `-txindex` to return the confirmed transactions, while the transactions in
+    /// the mempool are found without it.
+    pub fn poll(&mut self, rpc: &RpcClient) -> Result<FollowerProgress> {
+        if self.balance.is_none() {
+            let tx = rpc.get_raw_transaction(&self.tip.txid)?;
+            let Some(txout) = tx.output.get(self.tip.vout as usize) else {
+                bail!("the initial output {} does not exist", self.tip);
+            };
+            if txout.script_pubkey != self.address.script_pubkey() {
+                bail!("the initial output {} does not pay the program", self.tip);
+            }
+            self.balance = Some(txout.value.to_sat());
+        }
+
+        loop {
+            if let Some(txid) = rpc.get_tx_spending_prevout(&self.tip)? {
+                let tx = rpc.get_raw_transaction(&txid)?;
+                self.advance(&tx)?;
+                continue;
+            }
+            if rpc.is_unspent(&self.tip)? {
+                break;
+            }
+
+            // the spending transaction is in a block, so walk back from a confirmed program output
+            let txs = self.find_confirmed_txs(rpc)?;
+            if txs.is_empty() {
+                bail!("cannot find the transaction that spends {}", self.tip);
+            }
+            for tx in txs.iter().rev() {
+                self.advance(tx)?;
+            }
+        }
+
+        Ok(self.progress())
+    }
+
+    /// The progress as of the last transaction followed.
+    pub fn progress(&self) -> FollowerProgress {
+        let num_steps = FibonacciSplitProgram::<P>::num_steps();
+        let step = match self.status {
+            FollowerStatus::Completed => num_steps,
+            _ => self.state.pc,
+        };
+
+        FollowerProgress {
+            status: self.status,
+            step,
+            num_steps,
+            balance: self.balance.unwrap_or_default(),
+            tip: self.tip,
+            num_txs: self.num_txs,
+            stalled: self.status == FollowerStatus::InProgress
+                && self.last_progress.elapsed() >= self.stall_timeout,
+        }
+    }
+
+    /// Find the confirmed transactions from the one that spends the tip to a confirmed program
+    /// output, in the reverse order.
+    ///
+    /// All the instances of the program share the same address, so the outputs of the other
+    /// instances are skipped when their chain does not reach the tip.
+    fn find_confirmed_txs(&self, rpc: &RpcClient) -> Result<Vec<Transaction>> {
+        for candidate in rpc.scan_tx_out_set(&self.address)? {
+            if candidate.vout != 0 {
+                continue;
+            }
+
+            let mut txs = vec![];
+            let mut txid = candidate.txid;
+            loop {
+                let tx = rpc.get_raw_transaction(&txid)?;
+                if tx.input.first().map(|txin| txin.previous_output) == Some(self.tip) {
+                    txs.push(tx);
+                    return Ok(txs);
+                }
+                // the funding transaction of another instance is not a covenant spend
+                let Ok(witness) = CovenantWitness::from_tx(&tx) else {
+                    break;
+                };
+                txid = witness.old_txid;
+                txs.push(tx);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// Apply a transaction that spends the tip.
+    fn advance(&mut self, tx: &Transaction) -> Result<()> {
+        let txid = tx.compute_txid();
+        if tx.input.first().map(|txin| txin.previous_output) != Some(self.tip) {
+            bail!(
+                "the transaction {} does not spend the tip {}",
+                txid,
+                self.tip
+            );
+        }
+
+        let decoded = FibonacciSplitProgram::<P>::decode_tx(tx)?;
+        if decoded.covenant.old_state_hash != FibonacciSplitProgram::<P>::get_hash(&self.state) {
+            bail!(
+                "the transaction {} does not start from the current state",
+                txid
+            );
+        }
+        if tx.output.len() < 2 || tx.output[0].script_pubkey != self.address.script_pubkey() {
+            bail!("the transaction {} does not pay the program", txid);
+        }
+        if tx.output[1].script_pubkey
+            != caboose_script_pub_key::<FibonacciSplitProgram<P>>(
+                &decoded.new_state,
+                decoded.covenant.new_randomizer,
+            )
+        {
+            bail!(
+                "the caboose of the transaction {} does not match the new state",
+                txid
+            );
+        }
+
+        self.status = if decoded.program_index == FibonacciSplitProgram::<P>::reset_index() {
+            FollowerStatus::Reset
+        } else if decoded.new_state.pc == 0 {
+            FollowerStatus::Completed
+        } else {
+            FollowerStatus::InProgress
+        };
+        self.state = decoded.new_state;
+        self.tip = OutPoint { txid, vout: 0 };
+        self.balance = Some(tx.output[0].value.to_sat());
+        self.num_txs += 1;
+        self.last_progress = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chain::test::build_chain;
+    use crate::chain::CovenantWitness;
+    use crate::mock_rpc::{serve, MockNode};
+    use crate::rpc::RpcClient;
+    use crate::split::follow::{FibonacciSplitFollower, FollowerStatus};
+    use crate::split::{DefaultFibonacciSplitParameters, FibonacciSplitProgram};
+    use bitcoin::{Address, Network, OutPoint};
+    use covenants_gadgets::{
+        get_script_pub_key, get_tx, CovenantInput, CovenantProgram, DUST_AMOUNT,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+    type Follower = FibonacciSplitFollower<DefaultFibonacciSplitParameters>;
+
+    #[test]
+    fn test_follower() {
+        let (funding_tx, txs) = build_chain();
+        let address =
+            Address::from_script(&get_script_pub_key::<Program>(), Network::Regtest).unwrap();
+        let initial_utxo = OutPoint {
+            txid: funding_tx.compute_txid(),
+            vout: 0,
+        };
+
+        let node = Arc::new(Mutex::new(MockNode::new(Network::Regtest)));
+        node.lock().unwrap().add_to_mempool(funding_tx.clone());
+        node.lock().unwrap().mine();
+        let rpc = RpcClient::new(serve(node.clone()), None);
+
+        let mut follower = Follower::new(address.clone(), initial_utxo, Duration::from_secs(3600));
+        let progress = follower.poll(&rpc).unwrap();
+        assert_eq!(progress.status, FollowerStatus::NotStarted);
+        assert_eq!(progress.step, 0);
+        assert_eq!(progress.num_steps, Program::num_steps());
+        assert_eq!(progress.balance, funding_tx.output[0].value.to_sat());
+        assert_eq!(progress.tip, initial_utxo);
+        assert!(!progress.stalled);
+
+        // some transactions are confirmed, and the next one is in the mempool
+        for tx in txs[..3].iter() {
+            node.lock().unwrap().add_to_mempool(tx.clone());
+        }
+        node.lock().unwrap().mine();
+        node.lock().unwrap().add_to_mempool(txs[3].clone());
+
+        let progress = follower.poll(&rpc).unwrap();
+        let decoded = Program::decode_tx(&txs[3]).unwrap();
+        assert_eq!(progress.status, FollowerStatus::InProgress);
+        assert_eq!(progress.step, 4);
+        assert_eq!(progress.num_txs, 4);
+        assert_eq!(progress.balance, txs[3].output[0].value.to_sat());
+        assert_eq!(
+            progress.tip,
+            OutPoint {
+                txid: txs[3].compute_txid(),
+                vout: 0
+            }
+        );
+        assert_eq!(follower.state().pc, decoded.new_state.pc);
+        assert_eq!(follower.state().stack, decoded.new_state.stack);
+        assert!(!progress.stalled);
+
+        // without a timeout, an instance in progress is stalled until the next transaction
+        let mut impatient_follower = Follower::new(address.clone(), initial_utxo, Duration::ZERO);
+        let progress = impatient_follower.poll(&rpc).unwrap();
+        assert_eq!(progress.step, 4);
+        assert!(progress.stalled);
+
+        // the rest of the transactions complete the program
+        for tx in txs[4..].iter() {
+            node.lock().unwrap().add_to_mempool(tx.clone());
+        }
+        node.lock().unwrap().mine();
+
+        let progress = follower.poll(&rpc).unwrap();
+        assert_eq!(progress.status, FollowerStatus::Completed);
+        assert_eq!(progress.step, Program::num_steps());
+        assert_eq!(progress.num_txs, txs.len());
+        assert_eq!(follower.state().pc, 0);
+        assert!(!progress.stalled);
+
+        // a reset after the last transaction, whose signature the follower does not check
+        let last_tx = txs.last().unwrap();
+        let covenant = CovenantWitness::from_tx(last_tx).unwrap();
+        let info = CovenantInput {
+            old_randomizer: covenant.new_randomizer,
+            old_balance: covenant.new_balance,
+            old_txid: last_tx.compute_txid(),
+            input_outpoint1: last_tx.input[0].previous_output,
+            input_outpoint2: None,
+            optional_deposit_input: None,
+            new_balance: covenant.new_balance - 100000 - DUST_AMOUNT,
+        };
+        let (reset_tx, _) = get_tx::<Program>(
+            &info,
+            Program::reset_index(),
+            follower.state(),
+            &Program::new(),
+            &Program::unsigned_reset_input(),
+        );
+        node.lock().unwrap().add_to_mempool(reset_tx.tx);
+
+        let progress = follower.poll(&rpc).unwrap();
+        assert_eq!(progress.status, FollowerStatus::Reset);
+        assert_eq!(progress.step, 0);
+        assert_eq!(progress.num_txs, txs.len() + 1);
+    }
+}
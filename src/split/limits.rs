@@ -0,0 +1,188 @@
+use crate::chain::input_exec;
+use crate::error::ChainError;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::transaction::Version;
+use bitcoin::{OutPoint, TapLeafHash, Transaction, TxOut, Txid};
+use bitcoin_circle_stark::treepp::*;
+use bitcoin_scriptexec::{Exec, ExecCtx, Options, TxTemplate};
+use covenants_gadgets::{get_tx, CovenantInput, CovenantProgram, DUST_AMOUNT};
+use std::cmp::max;
+use std::fmt::{Display, Formatter};
+
+/// The maximum number of elements in the main stack and the altstack together.
+pub const MAX_STACK_SIZE: usize = 1000;
+
+/// The maximum size of a stack element, in bytes.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// The usage of the stack when executing a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The index of the script.
+    pub index: usize,
+    /// Whether the script succeeds when the limits are not enforced.
+    pub success: bool,
+    /// The peak number of elements in the main stack and the altstack together.
+    pub peak_stack_depth: usize,
+    /// The size of the largest element, in bytes.
+    pub max_element_size: usize,
+}
+
+impl StackUsage {
+    /// Execute the script with the witness, without enforcing the limits, and record the usage.
+    pub fn measure(index: usize, script: Script, witness: Vec<Vec<u8>>) -> Self {
+        let max_witness_size = witness.iter().map(|elem| elem.len()).max().unwrap_or(0);
+
+        let exec = Exec::new(
+            ExecCtx::Tapscript,
+            Self::options(),
+            TxTemplate {
+                tx: Transaction {
+                    version: Version::TWO,
+                    lock_time: LockTime::ZERO,
+                    input: vec![],
+                    output: vec![],
+                },
+                prevouts: vec![],
+                input_idx: 0,
+                taproot_annex_scriptleaf: Some((TapLeafHash::all_zeros(), None)),
+            },
+            script.compile(),
+            witness,
+        )
+        .expect("error creating exec");
+
+        Self::run(index, exec, max_witness_size)
+    }
+
+    /// Execute the first input of a transaction of a covenant program, without enforcing the
+    /// limits, and record the usage of the whole tapscript, i.e., the covenant, the common prefix
+    /// of the program, and the script of the step, on the witness of the transaction.
+    pub fn measure_tx(
+        index: usize,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+    ) -> Result<Self, ChainError> {
+        // the script and the control block are not pushed to the stack
+        let witness = tx.input[0].witness.to_vec();
+        let max_witness_size = witness[..witness.len().saturating_sub(2)]
+            .iter()
+            .map(|elem| elem.len())
+            .max()
+            .unwrap_or(0);
+
+        let exec = input_exec(tx, 0, 0, prevouts, Self::options())?;
+        Ok(Self::run(index, exec, max_witness_size))
+    }
+
+    fn options() -> Options {
+        Options {
+            enforce_stack_limit: false,
+            ..Default::default()
+        }
+    }
+
+    fn run(index: usize, mut exec: Exec, max_witness_size: usize) -> Self {
+        // all the elements other than the witness are created on the top of the main stack
+        let mut max_element_size = max_witness_size;
+        while exec.exec_next().is_ok() {
+            if let Ok(top) = exec.stack().last() {
+                max_element_size = max(max_element_size, top.len());
+            }
+        }
+
+        Self {
+            index,
+            success: exec.result().unwrap().success,
+            peak_stack_depth: exec.stats().max_nb_stack_items,
+            max_element_size,
+        }
+    }
+
+    /// Whether the script stays within the consensus limits.
+    pub fn within_limits(&self) -> bool {
+        self.peak_stack_depth <= MAX_STACK_SIZE && self.max_element_size <= MAX_SCRIPT_ELEMENT_SIZE
+    }
+}
+
+impl Display for StackUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "script {}: peak stack depth {} / {}, max element size {} / {}",
+            self.index,
+            self.peak_stack_depth,
+            MAX_STACK_SIZE,
+            self.max_element_size,
+            MAX_SCRIPT_ELEMENT_SIZE
+        )?;
+        if !self.success {
+            write!(f, " (failed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the program from its initial state for the given number of steps, where `next` gives the
+/// index of the script and the input for each state, and measure the stack usage of each step.
+///
+/// Each step is measured on the transaction that `get_tx` builds for it, so the usage includes
+/// the covenant and the common prefix. The transactions spend placeholder outputs, since the
+/// usage does not depend on the txids and the balances.
+pub fn stack_usage_of_steps<T: CovenantProgram>(
+    num_steps: usize,
+    mut next: impl FnMut(&T::State) -> (usize, T::Input),
+) -> anyhow::Result<Vec<StackUsage>> {
+    let mut info = CovenantInput {
+        old_randomizer: 12,
+        old_balance: PLACEHOLDER_BALANCE,
+        old_txid: Txid::all_zeros(),
+        input_outpoint1: OutPoint::null(),
+        input_outpoint2: None,
+        optional_deposit_input: None,
+        new_balance: PLACEHOLDER_BALANCE - DUST_AMOUNT,
+    };
+
+    let mut state = T::new();
+    let mut usages = vec![];
+    for _ in 0..num_steps {
+        let (index, input) = next(&state);
+        let new_state = T::run(index, &state, &input)?;
+
+        let (tx_template, randomizer) = get_tx::<T>(&info, index, &state, &new_state, &input);
+        usages.push(StackUsage::measure_tx(
+            index,
+            &tx_template.tx,
+            &tx_template.prevouts,
+        )?);
+
+        info.old_randomizer = randomizer;
+        info.old_balance = info.new_balance;
+        info.new_balance -= DUST_AMOUNT;
+        info.old_txid = tx_template.tx.compute_txid();
+        info.input_outpoint1 = tx_template.tx.input[0].previous_output;
+        state = new_state;
+    }
+    Ok(usages)
+}
+
+/// The initial balance of the program in the transactions measured by `stack_usage_of_steps`,
+/// which only pays the cabooses.
+const PLACEHOLDER_BALANCE: u64 = 100_000_000;
+
+/// Report the stack usage of each step, one line per step, or fail with the first step that fails
+/// or exceeds the limits.
+pub fn check_stack_usage(usages: &[StackUsage]) -> anyhow::Result<String> {
+    if let Some(usage) = usages
+        .iter()
+        .find(|usage| !usage.success || !usage.within_limits())
+    {
+        anyhow::bail!("{}", usage);
+    }
+    Ok(usages
+        .iter()
+        .map(|usage| usage.to_string())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
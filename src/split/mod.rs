@@ -2,27 +2,41 @@ use crate::bitcoin_script::fiat_shamir::FibonacciFiatShamirGadget;
 use crate::bitcoin_script::fold::FibonacciPerQueryFoldGadget;
 use crate::bitcoin_script::prepare::FibonacciPrepareGadget;
 use crate::bitcoin_script::quotients::FibonacciPerQueryQuotientGadget;
-use crate::fiat_shamir::FiatShamirHints;
-use crate::fold::PerQueryFoldHints;
-use crate::prepare::PrepareHints;
-use crate::quotients::PerQueryQuotientHint;
-use crate::FIB_LOG_SIZE;
+use crate::bitcoin_script::stack_layout::StackLayout;
+use crate::chain::CovenantWitness;
+use crate::serialization::{SerializedVerifierHints, WitnessHint};
+use crate::{FibonacciClaimMode, VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE};
+use anyhow::bail;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Keypair, Message, Secp256k1};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{TapSighashType, Transaction, Witness};
 use bitcoin_circle_stark::treepp::*;
 use bitcoin_circle_stark::utils::{clean_stack, hash};
 use bitcoin_circle_stark::OP_HINT;
 use bitcoin_scriptexec::utils::scriptint_vec;
+use bitcoin_scriptexec::TxTemplate;
 use covenants_gadgets::utils::stack_hash::StackHash;
 use covenants_gadgets::CovenantProgram;
 use sha2::digest::Update;
 use sha2::{Digest, Sha256};
+use std::cmp::min;
 use std::collections::BTreeMap;
-use stwo_prover::core::channel::Sha256Channel;
-use stwo_prover::core::fields::{
-    m31::{BaseField, M31},
-    IntoSlice,
-};
-use stwo_prover::core::prover::N_QUERIES;
-use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
+use std::marker::PhantomData;
+use std::ops::Range;
+use stwo_prover::core::fields::m31::M31;
+
+/// The automatic splitter, which partitions the verifier into steps under a size budget.
+pub mod auto;
+
+/// Decoding the transactions of the split program from their witnesses.
+pub mod decode;
+
+/// Following an instance of the split program on the chain through the RPC of a node.
+pub mod follow;
+
+/// Measuring the stack usage of the steps against the consensus limits.
+pub mod limits;
 
 /// The state of the Fibonacci split program.
 #[derive(Clone, Debug)]
@@ -45,23 +59,31 @@ impl From<FibonacciSplitState> for Script {
 }
 
 /// An enum of the input to the Fibonacci split program.
-#[derive(Clone)]
+///
+/// The hints are in their serialized form, so the input can be built from a hints file.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FibonacciSplitInput {
-    /// Hints for Fiat-Shamir
-    FiatShamir(Box<FiatShamirHints>),
+    /// Hints for Fiat-Shamir, with the claim if it is a public input
+    FiatShamir(Option<M31>, WitnessHint),
     /// Hints for prepare
-    Prepare(Vec<Vec<u8>>, PrepareHints),
-    /// Hints for per-query quotient and folding
-    PerQuery(Vec<Vec<u8>>, PerQueryQuotientHint, PerQueryFoldHints),
-    /// Dummy hints for reset
-    Reset,
+    Prepare(Vec<Vec<u8>>, WitnessHint),
+    /// Hints for per-query quotient and folding, for each query of the step
+    PerQuery(Vec<Vec<u8>>, Vec<WitnessHint>, Vec<WitnessHint>),
+    /// The signature of the operator over the reset transaction
+    Reset(Vec<u8>),
 }
 
 impl From<FibonacciSplitInput> for Script {
     fn from(v: FibonacciSplitInput) -> Self {
         match v {
-            FibonacciSplitInput::FiatShamir(h) => script! {
-                { *h }
+            FibonacciSplitInput::FiatShamir(claim, h) => match claim {
+                Some(claim) => script! {
+                    { claim }
+                    { h }
+                },
+                None => script! {
+                    { h }
+                },
             },
             FibonacciSplitInput::Prepare(v, h) => script! {
                 for elem in v {
@@ -73,21 +95,227 @@ impl From<FibonacciSplitInput> for Script {
                 for elem in v {
                     { elem }
                 }
-                { h1 }
-                { h2 }
+                for (quotients_hint, fold_hint) in h1.into_iter().zip(h2) {
+                    { quotients_hint }
+                    { fold_hint }
+                }
+            },
+            FibonacciSplitInput::Reset(signature) => script! {
+                { signature }
             },
-            FibonacciSplitInput::Reset => script! {},
         }
     }
 }
 
+/// The parameters of a Fibonacci split program.
+pub trait FibonacciSplitParameters {
+    /// The name of the cache for the scripts, which must be unique for each set of parameters.
+    const CACHE_NAME: &'static str;
+    /// The log size of the Fibonacci trace.
+    const LOG_SIZE: u32;
+    /// How the claim, i.e., the last element of the Fibonacci trace, is provided to the script.
+    ///
+    /// In the public-input mode, the claim is carried in the stack through all the steps, so one
+    /// program can verify different claims.
+    const CLAIM_MODE: FibonacciClaimMode;
+    /// The configuration of the verifier.
+    ///
+    /// The number of queries, together with `QUERIES_PER_STEP`, determines the layout of the program.
+    const CONFIG: VerifierConfig;
+    /// The number of queries verified in each per-query step.
+    ///
+    /// Batching queries results in fewer transactions with larger scripts and witnesses.
+    const QUERIES_PER_STEP: usize = 1;
+    /// The x-only public key of the operator, whose signature is required to reset the program.
+    ///
+    /// The default is the unspendable key of BIP-341, so the program cannot be reset unless the
+    /// parameters name an operator.
+    const OPERATOR_PUBLIC_KEY: [u8; 32] = UNSPENDABLE_PUBLIC_KEY;
+}
+
+/// The x-coordinate of the point H of BIP-341, whose discrete logarithm is unknown.
+pub const UNSPENDABLE_PUBLIC_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// The parameters of the Fibonacci split program used in the demo.
+pub struct DefaultFibonacciSplitParameters;
+
+impl FibonacciSplitParameters for DefaultFibonacciSplitParameters {
+    const CACHE_NAME: &'static str = "FIBONACCI";
+    const LOG_SIZE: u32 = FIB_LOG_SIZE;
+    const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+    const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+}
+
 /// The Fibonacci split program.
-pub struct FibonacciSplitProgram;
+///
+/// The scripts are laid out as follows:
+/// - 0: Fiat-Shamir
+/// - 1: prepare
+/// - 2, ..., num_query_steps + 1: the per-query steps, each verifying `QUERIES_PER_STEP`
+///   queries (the last one may verify fewer), where the last step resets the program
+/// - num_query_steps + 2: reset, signed by the operator
+pub struct FibonacciSplitProgram<P: FibonacciSplitParameters = DefaultFibonacciSplitParameters> {
+    _marker: PhantomData<P>,
+}
+
+/// The sizes of the script and the witness of a step of the split program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FibonacciSplitStepSize {
+    /// The index of the script.
+    pub index: usize,
+    /// The size of the script in bytes, excluding the common prefix.
+    pub script_size: usize,
+    /// The number of elements of the witness from the input, excluding the states.
+    pub num_witness_elements: usize,
+    /// The size of the witness from the input in bytes, excluding the states.
+    pub witness_size: usize,
+}
+
+impl<P: FibonacciSplitParameters> FibonacciSplitProgram<P> {
+    /// The number of per-query steps.
+    pub fn num_query_steps() -> usize {
+        P::CONFIG.n_queries.div_ceil(P::QUERIES_PER_STEP)
+    }
+
+    /// The number of steps to verify a proof, which is also the number of transactions.
+    pub fn num_steps() -> usize {
+        Self::num_query_steps() + 2
+    }
+
+    /// The index of the script for the given per-query step.
+    pub fn query_step_index(step: usize) -> usize {
+        step + 2
+    }
+
+    /// The queries verified in the given per-query step.
+    pub fn queries_of_step(step: usize) -> Range<usize> {
+        let start = step * P::QUERIES_PER_STEP;
+        let end = min(start + P::QUERIES_PER_STEP, P::CONFIG.n_queries);
+        start..end
+    }
+
+    /// The index of the script that resets the program.
+    pub fn reset_index() -> usize {
+        Self::num_query_steps() + 2
+    }
+
+    /// The script that resets the program, which is shared with the automatically split program.
+    pub(crate) fn reset_script() -> Script {
+        script! {
+            // input:
+            // - old pc
+            // - old stack hash
+            // - new pc
+            // - new stack hash
+
+            { [0u8; 32].to_vec() } OP_EQUALVERIFY
+            0 OP_EQUALVERIFY
+            OP_2DROP
 
-impl CovenantProgram for FibonacciSplitProgram {
+            // the covenant fixes the sequence and the locktime, which rules out a timelock,
+            // so only the operator can reset the program, with a signature that is the only
+            // element left from the input
+            { P::OPERATOR_PUBLIC_KEY.to_vec() } OP_CHECKSIG
+        }
+    }
+
+    /// The input of a reset, with a placeholder for the signature that `sign_reset` replaces once
+    /// the transaction is built.
+    pub fn unsigned_reset_input() -> FibonacciSplitInput {
+        FibonacciSplitInput::Reset(vec![0u8; 64])
+    }
+
+    /// Sign a reset transaction produced by `get_tx` with the key of the operator, and put the
+    /// signature in place of the placeholder.
+    ///
+    /// The signature does not commit to the witness, so the transaction and the sighash checked by
+    /// the covenant stay the same.
+    pub fn sign_reset(tx_template: &mut TxTemplate, keypair: &Keypair) {
+        let (leaf_hash, _) = tx_template
+            .taproot_annex_scriptleaf
+            .as_ref()
+            .expect("the program is spent through a script");
+        let sighash = SighashCache::new(&tx_template.tx)
+            .taproot_script_spend_signature_hash(
+                tx_template.input_idx,
+                &Prevouts::All(&tx_template.prevouts),
+                *leaf_hash,
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let signature = Secp256k1::new()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), keypair);
+
+        let signature_index = Self::reset_signature_index(&tx_template.tx);
+        let txin = &mut tx_template.tx.input[tx_template.input_idx];
+        let mut witness = txin.witness.to_vec();
+        witness[signature_index] = signature.serialize().to_vec();
+        txin.witness = Witness::from_slice(&witness);
+    }
+
+    /// The index of the signature in the witness of a reset transaction produced by `get_tx`.
+    ///
+    /// The signature is the only element of the input, so it is the last of the program elements.
+    pub(crate) fn reset_signature_index(tx: &Transaction) -> usize {
+        let covenant =
+            CovenantWitness::from_tx(tx).expect("the reset is spent through the covenant");
+        CovenantWitness::NUM_COVENANT_ELEMENTS + covenant.program_elements.len() - 1
+    }
+
+    /// The input for the step following the given state, taken from the hints.
+    pub fn input_for_state(
+        state: &FibonacciSplitState,
+        public_claim: Option<M31>,
+        hints: &SerializedVerifierHints,
+    ) -> FibonacciSplitInput {
+        if state.pc == 0 {
+            FibonacciSplitInput::FiatShamir(public_claim, hints.fiat_shamir_hints.clone())
+        } else if state.pc == 1 {
+            FibonacciSplitInput::Prepare(state.stack.clone(), hints.prepare_hints.clone())
+        } else {
+            let queries = Self::queries_of_step(state.pc - Self::query_step_index(0));
+            FibonacciSplitInput::PerQuery(
+                state.stack.clone(),
+                hints.per_query_quotients_hints[queries.clone()].to_vec(),
+                hints.per_query_fold_hints[queries].to_vec(),
+            )
+        }
+    }
+
+    /// Compute the sizes of the script and the witness of each step, when verifying a proof with
+    /// the given hints.
+    pub fn step_sizes(
+        public_claim: Option<M31>,
+        hints: &SerializedVerifierHints,
+    ) -> Vec<FibonacciSplitStepSize> {
+        let scripts = Self::get_all_scripts();
+
+        let mut state = Self::new();
+        let mut sizes = vec![];
+        for _ in 0..Self::num_steps() {
+            let input = Self::input_for_state(&state, public_claim, hints);
+            let witness = convert_to_witness(Script::from(input.clone())).unwrap();
+
+            sizes.push(FibonacciSplitStepSize {
+                index: state.pc,
+                script_size: scripts[&state.pc].len(),
+                num_witness_elements: witness.len(),
+                witness_size: witness.iter().map(|elem| elem.len()).sum(),
+            });
+
+            state = Self::run(state.pc, &state, &input).unwrap();
+        }
+        sizes
+    }
+}
+
+impl<P: FibonacciSplitParameters> CovenantProgram for FibonacciSplitProgram<P> {
     type State = FibonacciSplitState;
     type Input = FibonacciSplitInput;
-    const CACHE_NAME: &'static str = "FIBONACCI";
+    const CACHE_NAME: &'static str = P::CACHE_NAME;
 
     fn new() -> Self::State {
         FibonacciSplitState {
@@ -106,10 +334,20 @@ impl CovenantProgram for FibonacciSplitProgram {
     }
 
     fn get_all_scripts() -> BTreeMap<usize, Script> {
-        let mut channel = Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[M31::reduce(
-            443693538,
-        )])));
+        let log_size = P::LOG_SIZE;
+        let config = P::CONFIG;
+        assert!(
+            config.n_queries > 0 && P::QUERIES_PER_STEP > 0,
+            "the split program needs at least one query per step"
+        );
+        let num_query_steps = Self::num_query_steps();
+
+        let fiat_shamir_output_size = StackLayout::fiat_shamir_output(log_size, config)
+            .with_claim(P::CLAIM_MODE)
+            .len();
+        let prepare_output_size = StackLayout::prepare_output(log_size, config)
+            .with_claim(P::CLAIM_MODE)
+            .len();
 
         let mut map = BTreeMap::new();
         map.insert(
@@ -130,28 +368,29 @@ impl CovenantProgram for FibonacciSplitProgram {
                 OP_TOALTSTACK
 
                 // Run the Fiat-Shamir gadget
-                { FibonacciFiatShamirGadget::run(&channel) }
+                { FibonacciFiatShamirGadget::run(P::CLAIM_MODE, log_size, config) }
 
                 // expected output:
+                // - claim (only in the public-input mode)
                 // - trace oods values (3 * 4 = 12)
                 // - composition odds raw values (4 * 4 = 16)
                 // - random_coeff2 (4)
                 // - circle_poly_alpha (4)
-                // - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+                // - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
                 // - last layer (4)
-                // - queries (N_QUERIES)
-                // - trace queries (2 * N_QUERIES)
-                // - composition queries (8 * N_QUERIES)
+                // - queries (n_queries)
+                // - trace queries (2 * n_queries)
+                // - composition queries (8 * n_queries)
                 // - masked points (3 * 8 = 24)
                 // - oods point (8)
                 OP_DEPTH
-                { 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + 12 }
+                { fiat_shamir_output_size }
                 OP_EQUALVERIFY
 
-                { StackHash::hash_drop(8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + 12) }
+                { StackHash::hash_drop(fiat_shamir_output_size) }
                 OP_FROMALTSTACK OP_EQUALVERIFY
                 OP_TRUE
-            }
+            },
         );
         map.insert(
             1,
@@ -171,30 +410,32 @@ impl CovenantProgram for FibonacciSplitProgram {
                 OP_TOALTSTACK OP_TOALTSTACK
 
                 // previous stack, as the first part of the input:
+                // - claim (only in the public-input mode)
                 // - trace oods values (3 * 4 = 12)
                 // - composition odds raw values (4 * 4 = 16)
                 // - random_coeff2 (4)
                 // - circle_poly_alpha (4)
-                // - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+                // - (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
                 // - last layer (4)
-                // - queries (N_QUERIES)
-                // - trace queries (2 * N_QUERIES)
-                // - composition queries (8 * N_QUERIES)
+                // - queries (n_queries)
+                // - trace queries (2 * n_queries)
+                // - composition queries (8 * n_queries)
                 // - masked points (3 * 8 = 24)
                 // - oods point (8)
 
-                { StackHash::hash_from_hint(8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 + 4 + 16 + 12) }
+                { StackHash::hash_from_hint(fiat_shamir_output_size) }
                 OP_FROMALTSTACK OP_EQUALVERIFY
 
-                { FibonacciPrepareGadget::run() }
+                { FibonacciPrepareGadget::run(log_size, config) }
 
                 // expected output:
+                //    claim (only in the public-input mode)
                 //    circle_poly_alpha (4)
-                //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * FIB_LOG_SIZE
+                //    (commitment, alpha), ..., (commitment, alpha) (1 + 4) * log_size
                 //    last layer (4)
-                //    queries (N_QUERIES)
-                //    trace queries (2 * N_QUERIES)
-                //    composition queries (8 * N_QUERIES)
+                //    queries (n_queries)
+                //    trace queries (2 * n_queries)
+                //    composition queries (8 * n_queries)
                 //    masked points (3 * 8 = 24)
                 //    oods point (8)
                 //    (a, b), (a, b), (a, b) for trace (3 * 2 * 2 = 12)
@@ -204,17 +445,20 @@ impl CovenantProgram for FibonacciSplitProgram {
                 //    coeff^6, coeff^5, ..., coeff (24)
 
                 OP_DEPTH
-                { 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 }
+                { prepare_output_size }
                 OP_EQUALVERIFY
 
-                { StackHash::hash_drop(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
+                { StackHash::hash_drop(prepare_output_size) }
                 OP_FROMALTSTACK OP_EQUALVERIFY
                 OP_TRUE
-            }
+            },
         );
-        for i in 0..7 {
+        for step in 0..num_query_steps {
+            let index = Self::query_step_index(step);
+            let is_last = step == num_query_steps - 1;
+
             map.insert(
-                i + 2,
+                index,
                 script! {
                     // input:
                     // - old pc
@@ -222,79 +466,48 @@ impl CovenantProgram for FibonacciSplitProgram {
                     // - new pc
                     // - new stack hash
 
-                    OP_SWAP { i + 3 } OP_EQUALVERIFY
-                    OP_ROT { i + 2 } OP_EQUALVERIFY
+                    if is_last {
+                        // the last query resets the program
+                        OP_SWAP 0 OP_EQUALVERIFY
+                        OP_ROT { index } OP_EQUALVERIFY
 
-                    // require old/new stack hash to be the same
-                    OP_2DUP OP_EQUALVERIFY
+                        // stack:
+                        // - old stack hash
+                        // - new stack hash
+                        { [0u8; 32].to_vec() } OP_EQUALVERIFY
 
-                    // stack:
-                    // - old stack hash
-                    // - new stack hash
-                    OP_TOALTSTACK OP_TOALTSTACK
+                        OP_TOALTSTACK
+                    } else {
+                        OP_SWAP { index + 1 } OP_EQUALVERIFY
+                        OP_ROT { index } OP_EQUALVERIFY
+
+                        // require old/new stack hash to be the same
+                        OP_2DUP OP_EQUALVERIFY
+
+                        // stack:
+                        // - old stack hash
+                        // - new stack hash
+                        OP_TOALTSTACK OP_TOALTSTACK
+                    }
 
-                    { StackHash::hash_from_hint(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
+                    { StackHash::hash_from_hint(prepare_output_size) }
                     OP_FROMALTSTACK OP_EQUALVERIFY
 
-                    { FibonacciPerQueryQuotientGadget::run(i) }
-                    { FibonacciPerQueryFoldGadget::run(i) }
+                    for query in Self::queries_of_step(step) {
+                        { FibonacciPerQueryQuotientGadget::run(query, log_size, config) }
+                        { FibonacciPerQueryFoldGadget::run(query, log_size, config) }
+                    }
 
                     OP_DEPTH
-                    { 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 }
+                    { prepare_output_size }
                     OP_EQUALVERIFY
 
-                    { clean_stack(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
+                    { clean_stack(prepare_output_size) }
                     OP_TRUE
-                }
+                },
             );
         }
-        map.insert(
-            9,
-            script! {
-                // input:
-                // - old pc
-                // - old stack hash
-                // - new pc
-                // - new stack hash
-
-                OP_SWAP 0 OP_EQUALVERIFY
-                OP_ROT 9 OP_EQUALVERIFY
-
-                // stack:
-                // - old stack hash
-                // - new stack hash
-                { [0u8; 32].to_vec() } OP_EQUALVERIFY
-
-                OP_TOALTSTACK
-                { StackHash::hash_from_hint(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
-                OP_FROMALTSTACK OP_EQUALVERIFY
-
-                { FibonacciPerQueryQuotientGadget::run(7) }
-                { FibonacciPerQueryFoldGadget::run(7) }
-
-                OP_DEPTH
-                { 24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4 }
-                OP_EQUALVERIFY
-
-                { clean_stack(24 + 4 + 12 + 16 + 12 + 8 + 24 + (2 + 8 + 1) * N_QUERIES + 4 + (1 + 4) * FIB_LOG_SIZE as usize + 4) }
-                OP_TRUE
-            }
-        );
-        map.insert(
-            10, // reset
-            script! {
-                // input:
-                // - old pc
-                // - old stack hash
-                // - new pc
-                // - new stack hash
-
-                { [0u8; 32].to_vec() } OP_EQUALVERIFY
-                0 OP_EQUALVERIFY
-                OP_2DROP
-                OP_TRUE
-            },
-        );
+        map.insert(Self::reset_index(), Self::reset_script());
         map
     }
 
@@ -330,28 +543,29 @@ impl CovenantProgram for FibonacciSplitProgram {
     }
 
     fn run(id: usize, old_state: &Self::State, input: &Self::Input) -> anyhow::Result<Self::State> {
-        if id == 0 {
-            assert_eq!(old_state.pc, 0);
-            assert!(matches!(input, Self::Input::FiatShamir(_)));
+        if id < Self::reset_index() && old_state.pc != id {
+            bail!(
+                "the step {} cannot follow the state at {}",
+                id,
+                old_state.pc
+            );
+        }
 
-            let fiat_shamir_hints = match input {
-                FibonacciSplitInput::FiatShamir(h) => h,
-                _ => unreachable!(),
+        if id == 0 {
+            let FibonacciSplitInput::FiatShamir(claim, _) = input else {
+                bail!(
+                    "the Fiat-Shamir step takes the claim and the Fiat-Shamir hints as its input"
+                );
             };
-
-            let mut channel = Sha256Channel::default();
-            channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[M31::reduce(
-                443693538,
-            )])));
+            if claim.is_some() != (P::CLAIM_MODE == FibonacciClaimMode::PublicInput) {
+                bail!("the claim is part of the input exactly in the public-input mode");
+            }
 
             let script = script! {
-                { FibonacciFiatShamirGadget::run(&channel) }
+                { FibonacciFiatShamirGadget::run(P::CLAIM_MODE, P::LOG_SIZE, P::CONFIG) }
             };
 
-            let witness = convert_to_witness(script! {
-                { *fiat_shamir_hints.clone() }
-            })
-            .unwrap();
+            let witness = convert_to_witness(Script::from(input.clone())).unwrap();
 
             println!("fiat-shamir witness size: {}", witness.len());
 
@@ -365,16 +579,12 @@ impl CovenantProgram for FibonacciSplitProgram {
                 stack: final_stack,
             })
         } else if id == 1 {
-            assert_eq!(old_state.pc, 1);
-            assert!(matches!(input, Self::Input::Prepare(_, _)));
-
-            let (stack, prepare_hints) = match input {
-                FibonacciSplitInput::Prepare(s, h) => (s, h),
-                _ => unreachable!(),
+            let FibonacciSplitInput::Prepare(stack, prepare_hints) = input else {
+                bail!("the prepare step takes the stack and the prepare hints as its input");
             };
 
             let script = script! {
-                { FibonacciPrepareGadget::run() }
+                { FibonacciPrepareGadget::run(P::LOG_SIZE, P::CONFIG) }
             };
 
             let mut witness = convert_to_witness(script! {
@@ -394,11 +604,25 @@ impl CovenantProgram for FibonacciSplitProgram {
                 stack_hash,
                 stack: final_stack,
             })
-        } else if (2..=9).contains(&id) {
-            assert_eq!(old_state.pc, id);
-            assert!(matches!(input, Self::Input::PerQuery(_, _, _)));
+        } else if (Self::query_step_index(0)..Self::reset_index()).contains(&id) {
+            let FibonacciSplitInput::PerQuery(_, quotients_hints, fold_hints) = input else {
+                bail!(
+                    "the step {} takes the stack and the hints of its queries as its input",
+                    id
+                );
+            };
+            let num_queries = Self::queries_of_step(id - Self::query_step_index(0)).len();
+            if quotients_hints.len() != num_queries || fold_hints.len() != num_queries {
+                bail!(
+                    "the step {} takes the hints of {} queries, not {} and {}",
+                    id,
+                    num_queries,
+                    quotients_hints.len(),
+                    fold_hints.len()
+                );
+            }
 
-            if id <= 8 {
+            if id < Self::query_step_index(Self::num_query_steps() - 1) {
                 println!("step witness size: {}", old_state.stack.len());
                 Ok(Self::State {
                     pc: id + 1,
@@ -412,154 +636,741 @@ impl CovenantProgram for FibonacciSplitProgram {
                     stack: vec![],
                 })
             }
-        } else if id == 10 {
+        } else if id == Self::reset_index() {
+            if !matches!(input, FibonacciSplitInput::Reset(_)) {
+                bail!("the reset takes the signature of the operator as its input");
+            }
             Ok(Self::State {
                 pc: 0,
                 stack_hash: vec![0u8; 32],
                 stack: vec![],
             })
         } else {
-            unreachable!()
+            bail!("the program has no script {}", id)
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::chain::test::{fee_of_step, funding_tx};
+    use crate::chain::validate_chain;
+    use crate::error::ChainError;
     use crate::fiat_shamir::compute_fiat_shamir_hints;
     use crate::fold::compute_fold_hints;
     use crate::prepare::compute_prepare_hints;
+    use crate::proof::test::{proof_for, test_proof};
     use crate::quotients::compute_quotients_hints;
-    use crate::split::{FibonacciSplitInput, FibonacciSplitProgram, FibonacciSplitState};
-    use crate::FIB_LOG_SIZE;
+    use crate::serialization::{SerializedVerifierHints, VerifierHintsFile, WitnessHint};
+    use crate::split::limits::{check_stack_usage, stack_usage_of_steps};
+    use crate::split::{
+        DefaultFibonacciSplitParameters, FibonacciSplitInput, FibonacciSplitParameters,
+        FibonacciSplitProgram, FibonacciSplitState, FibonacciSplitStepSize,
+    };
+    use crate::tamper::{hints_for_tampering, tampered_hints, TamperedStep};
+    use crate::{
+        channel_for_claim, fibonacci_claim, verify_with_hints, FibonacciClaimMode, VerifierConfig,
+        VerifierHints, FIB_CLAIM, FIB_LOG_SIZE,
+    };
+    use bitcoin::secp256k1::{Keypair, Secp256k1};
+    use bitcoin::{Transaction, Witness};
+    use bitcoin_circle_stark::treepp::*;
+    use bitcoin_scriptexec::execute_script_with_witness_unlimited_stack;
     use covenants_gadgets::test::{simulation_test, SimulationInstruction};
-    use rand::{Rng, SeedableRng};
-    use rand_chacha::ChaCha20Rng;
+    use covenants_gadgets::{get_tx, CovenantInput, CovenantProgram, DUST_AMOUNT};
     use std::cell::RefCell;
+    use std::collections::HashMap;
     use std::ops::AddAssign;
     use std::rc::Rc;
-    use stwo_prover::core::channel::Sha256Channel;
-    use stwo_prover::core::fields::m31::{BaseField, M31};
-    use stwo_prover::core::fields::IntoSlice;
-    use stwo_prover::core::pcs::PcsConfig;
-    use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
-    use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleChannel;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::prover::PROOF_OF_WORK_BITS;
     use stwo_prover::examples::fibonacci::Fibonacci;
-    use stwo_prover::trace_generation::commit_and_prove;
+
+    struct LogSize6Parameters;
+
+    impl FibonacciSplitParameters for LogSize6Parameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-LOG-SIZE-6";
+        const LOG_SIZE: u32 = 6;
+        const CLAIM_MODE: FibonacciClaimMode =
+            FibonacciClaimMode::Constant(M31::from_u32_unchecked(473575083));
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+    }
+
+    struct PublicClaimParameters;
+
+    impl FibonacciSplitParameters for PublicClaimParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-PUBLIC-CLAIM";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::PublicInput;
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+    }
 
     #[test]
     fn test_integration() {
-        let mut prng = ChaCha20Rng::seed_from_u64(0);
-        let config = PcsConfig::default();
-
-        let fib = Fibonacci::new(FIB_LOG_SIZE, M31::reduce(443693538));
-
-        let trace = fib.get_trace();
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
-        let proof =
-            commit_and_prove::<_, Sha256MerkleChannel>(&fib.air, channel, vec![trace], config)
+        test_integration_with_parameters::<DefaultFibonacciSplitParameters>();
+    }
+
+    #[test]
+    fn test_integration_log_size_6() {
+        test_integration_with_parameters::<LogSize6Parameters>();
+    }
+
+    struct LogSize10Parameters;
+
+    impl FibonacciSplitParameters for LogSize10Parameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-LOG-SIZE-10";
+        const LOG_SIZE: u32 = 10;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::PublicInput;
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+    }
+
+    #[test]
+    fn test_integration_log_size_10() {
+        test_integration_with_parameters::<LogSize10Parameters>();
+    }
+
+    struct LogSize16Parameters;
+
+    impl FibonacciSplitParameters for LogSize16Parameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-LOG-SIZE-16";
+        const LOG_SIZE: u32 = 16;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::PublicInput;
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+    }
+
+    #[test]
+    #[ignore = "proving a trace of 2^16 rows and running all the steps take minutes"]
+    fn test_integration_log_size_16() {
+        test_integration_with_parameters::<LogSize16Parameters>();
+    }
+
+    struct MorePowBitsParameters;
+
+    impl FibonacciSplitParameters for MorePowBitsParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-MORE-POW-BITS";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig {
+            pow_bits: PROOF_OF_WORK_BITS + 2,
+            ..VerifierConfig::DEFAULT
+        };
+    }
+
+    #[test]
+    fn test_integration_more_pow_bits() {
+        test_integration_with_parameters::<MorePowBitsParameters>();
+    }
+
+    struct LogBlowup2Parameters;
+
+    impl FibonacciSplitParameters for LogBlowup2Parameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-LOG-BLOWUP-2";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig {
+            log_blowup_factor: 2,
+            ..VerifierConfig::DEFAULT
+        };
+    }
+
+    #[test]
+    fn test_integration_log_blowup_2() {
+        test_integration_with_parameters::<LogBlowup2Parameters>();
+    }
+
+    #[test]
+    fn test_integration_public_claim() {
+        test_integration_with_parameters::<PublicClaimParameters>();
+    }
+
+    struct FourQueriesParameters;
+
+    impl FibonacciSplitParameters for FourQueriesParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-FOUR-QUERIES";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig {
+            n_queries: 4,
+            ..VerifierConfig::DEFAULT
+        };
+    }
+
+    struct TwelveQueriesParameters;
+
+    impl FibonacciSplitParameters for TwelveQueriesParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-TWELVE-QUERIES";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig {
+            n_queries: 12,
+            ..VerifierConfig::DEFAULT
+        };
+    }
+
+    #[test]
+    fn test_integration_different_num_queries() {
+        test_integration_with_parameters::<FourQueriesParameters>();
+        test_integration_with_parameters::<TwelveQueriesParameters>();
+    }
+
+    struct BatchedParameters;
+
+    impl FibonacciSplitParameters for BatchedParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-BATCHED";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+        const QUERIES_PER_STEP: usize = 4;
+    }
+
+    struct UnevenBatchedParameters;
+
+    impl FibonacciSplitParameters for UnevenBatchedParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-UNEVEN-BATCHED";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+        const QUERIES_PER_STEP: usize = 3;
+    }
+
+    #[test]
+    fn test_integration_batched() {
+        test_integration_with_parameters::<BatchedParameters>();
+        test_integration_with_parameters::<UnevenBatchedParameters>();
+    }
+
+    #[test]
+    fn test_script_layout() {
+        type Program = FibonacciSplitProgram<FourQueriesParameters>;
+
+        let scripts = Program::get_all_scripts();
+        assert_eq!(scripts.len(), 7);
+        assert_eq!(
+            scripts.keys().copied().collect::<Vec<_>>(),
+            (0..=Program::reset_index()).collect::<Vec<_>>()
+        );
+        assert_eq!(Program::num_steps(), 6);
+        assert_eq!(Program::query_step_index(3), 5);
+        assert_eq!(Program::queries_of_step(3), 3..4);
+        assert_eq!(Program::reset_index(), 6);
+
+        type UnevenProgram = FibonacciSplitProgram<UnevenBatchedParameters>;
+        assert_eq!(UnevenProgram::num_query_steps(), 3);
+        assert_eq!(UnevenProgram::queries_of_step(0), 0..3);
+        assert_eq!(UnevenProgram::queries_of_step(2), 6..8);
+        assert_eq!(UnevenProgram::reset_index(), 5);
+
+        // the per-query steps keep the stack, and the last one resets the program
+        let input = FibonacciSplitInput::PerQuery(
+            vec![],
+            vec![WitnessHint::default()],
+            vec![WitnessHint::default()],
+        );
+
+        let old_state = FibonacciSplitState {
+            pc: Program::query_step_index(2),
+            stack_hash: vec![1u8; 32],
+            stack: vec![],
+        };
+        let new_state = Program::run(Program::query_step_index(2), &old_state, &input).unwrap();
+        assert_eq!(new_state.pc, Program::query_step_index(3));
+        assert_eq!(new_state.stack_hash, old_state.stack_hash);
+
+        let old_state = FibonacciSplitState {
+            pc: Program::query_step_index(3),
+            ..old_state
+        };
+        let new_state = Program::run(Program::query_step_index(3), &old_state, &input).unwrap();
+        assert_eq!(new_state.pc, 0);
+        assert_eq!(new_state.stack_hash, vec![0u8; 32]);
+    }
+
+    fn default_hints() -> SerializedVerifierHints {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let proof = test_proof();
+        SerializedVerifierHints::from(&verify_with_hints(proof, &fib.air, verifier_config).unwrap())
+    }
+
+    #[test]
+    fn test_run_errors() {
+        type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+
+        let hints = default_hints();
+        let state = Program::new();
+        let input = Program::input_for_state(&state, None, &hints);
+
+        // a step that does not follow the state
+        assert!(Program::run(1, &state, &input).is_err());
+        // an input of another step
+        let prepare_input = FibonacciSplitInput::Prepare(vec![], WitnessHint::default());
+        assert!(Program::run(0, &state, &prepare_input).is_err());
+        // a claim in the constant mode
+        let FibonacciSplitInput::FiatShamir(_, fs_hints) = input.clone() else {
+            unreachable!()
+        };
+        let with_claim = FibonacciSplitInput::FiatShamir(Some(FIB_CLAIM), fs_hints);
+        assert!(Program::run(0, &state, &with_claim).is_err());
+        // a script that does not exist
+        assert!(Program::run(Program::reset_index() + 1, &state, &input).is_err());
+
+        // a query step with the hints of another number of queries
+        let query_state = FibonacciSplitState {
+            pc: Program::query_step_index(0),
+            ..state
+        };
+        let no_queries = FibonacciSplitInput::PerQuery(vec![], vec![], vec![]);
+        assert!(Program::run(query_state.pc, &query_state, &no_queries).is_err());
+    }
+
+    #[test]
+    fn test_step_sizes() {
+        let hints = default_hints();
+
+        let unbatched =
+            FibonacciSplitProgram::<DefaultFibonacciSplitParameters>::step_sizes(None, &hints);
+        let batched = FibonacciSplitProgram::<BatchedParameters>::step_sizes(None, &hints);
+
+        for (name, sizes) in [("1 query", &unbatched), ("4 queries", &batched)] {
+            for size in sizes.iter() {
+                println!(
+                    "{} per step, script {}: script size {}, witness size {} ({} elements)",
+                    name,
+                    size.index,
+                    size.script_size,
+                    size.witness_size,
+                    size.num_witness_elements
+                );
+            }
+        }
+
+        assert_eq!(unbatched.len(), 10);
+        assert_eq!(batched.len(), 4);
+
+        // the Fiat-Shamir and the prepare steps are not affected by batching
+        assert_eq!(unbatched[..2], batched[..2]);
+
+        // a batch has a larger script and witness than a single query, but the stack from the
+        // previous step is repeated fewer times in total
+        assert!(batched[2].script_size > unbatched[2].script_size);
+        assert!(batched[2].witness_size > unbatched[2].witness_size);
+
+        let total_witness_size = |sizes: &[FibonacciSplitStepSize]| {
+            sizes[2..]
+                .iter()
+                .map(|size| size.witness_size)
+                .sum::<usize>()
+        };
+        assert!(total_witness_size(&batched) < total_witness_size(&unbatched));
+    }
+
+    #[test]
+    fn test_hints_file_round_trip() {
+        type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let verifier_config = VerifierConfig::default();
+        let typed_hints = verify_with_hints(test_proof(), &fib.air, verifier_config).unwrap();
+        let hints = SerializedVerifierHints::from(&typed_hints);
+        let file = VerifierHintsFile::new(FIB_LOG_SIZE, FIB_CLAIM, verifier_config, typed_hints);
+        let funding_tx = funding_tx::<Program>(10000000);
+
+        // the transactions built from the hints read back from a file are the same
+        let build_txs = |hints: &SerializedVerifierHints| {
+            let mut info = CovenantInput {
+                old_randomizer: 12,
+                old_balance: 10000000,
+                old_txid: funding_tx.compute_txid(),
+                input_outpoint1: funding_tx.input[0].previous_output,
+                input_outpoint2: None,
+                optional_deposit_input: None,
+                new_balance: 0,
+            };
+            let mut state = Program::new();
+            let mut txs = vec![];
+            for _ in 0..Program::num_steps() {
+                let input = Program::input_for_state(&state, None, hints);
+                let new_state = Program::run(state.pc, &state, &input).unwrap();
+
+                info.new_balance = info.old_balance - 100000 - DUST_AMOUNT;
+                let (tx_template, randomizer) =
+                    get_tx::<Program>(&info, state.pc, &state, &new_state, &input);
+
+                info.old_randomizer = randomizer;
+                info.old_balance = info.new_balance;
+                info.old_txid = tx_template.tx.compute_txid();
+                info.input_outpoint1 = tx_template.tx.input[0].previous_output;
+                state = new_state;
+                txs.push(tx_template.tx);
+            }
+            txs
+        };
+        let expected_txs = build_txs(&hints);
+
+        for binary in [false, true] {
+            let path = std::env::temp_dir().join(format!("fibonacci-hints-round-trip-{}", binary));
+            file.write(&path, binary).unwrap();
+            let read_file = VerifierHintsFile::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            read_file
+                .check(FIB_LOG_SIZE, FIB_CLAIM, verifier_config)
                 .unwrap();
+            let read_hints = SerializedVerifierHints::from(&read_file.hints);
+            assert_eq!(read_hints, hints);
+            assert_eq!(build_txs(&read_hints), expected_txs);
+        }
+        validate_chain::<Program>(&funding_tx, &expected_txs, &HashMap::new()).unwrap();
+    }
+
+    fn test_integration_with_parameters<P: FibonacciSplitParameters>() {
+        // the claim is part of the witness only in the public-input mode
+        let (claim, public_claim) = match P::CLAIM_MODE {
+            FibonacciClaimMode::Constant(claim) => (claim, None),
+            FibonacciClaimMode::PublicInput => {
+                let claim = fibonacci_claim(P::LOG_SIZE);
+                (claim, Some(claim))
+            }
+        };
+
+        let fib = Fibonacci::new(P::LOG_SIZE, claim);
+        let proof = proof_for(P::LOG_SIZE, claim, P::CONFIG);
 
-        let channel = &mut Sha256Channel::default();
-        channel.update_digest(Sha256Hasher::hash(BaseField::into_slice(&[fib
-            .air
-            .component
-            .claim])));
+        let channel = &mut channel_for_claim(fib.air.component.claim);
         let (fiat_shamir_output, fiat_shamir_hints) =
-            compute_fiat_shamir_hints(proof.clone(), channel, &fib.air).unwrap();
+            compute_fiat_shamir_hints(proof.clone(), channel, &fib.air, P::CONFIG).unwrap();
 
         let (prepare_output, prepare_hints) =
             compute_prepare_hints(&fiat_shamir_output, &proof).unwrap();
 
         let (quotients_output, per_query_quotients_hints) =
-            compute_quotients_hints(&fiat_shamir_output, &prepare_output);
+            compute_quotients_hints(&fiat_shamir_output, &prepare_output).unwrap();
 
         let per_query_fold_hints = compute_fold_hints(
             &proof.commitment_scheme_proof.fri_proof,
             &fiat_shamir_output,
             &prepare_output,
             &quotients_output,
-        );
+        )
+        .unwrap();
 
+        const FEE_RATE: u64 = 7;
         let total_fee = Rc::new(RefCell::new(0));
-        let mut step = 0;
-
-        let reset = Rc::new(RefCell::new(false));
-        let mut reset_times = 0;
 
         let mut test_generator = |old_state: &FibonacciSplitState| {
-            step += 1;
-
-            let should_reset = if *reset.borrow() && step % 5 == 0 {
-                prng.gen_bool(0.5)
-            } else {
-                false
-            };
-
-            if should_reset {
-                reset_times += 1;
-                total_fee.borrow_mut().add_assign(3045);
-                Some(SimulationInstruction {
-                    program_index: 10,
-                    fee: 3045,
-                    program_input: FibonacciSplitInput::Reset,
-                })
-            } else if old_state.pc == 0 {
-                total_fee.borrow_mut().add_assign(474845);
-                Some(SimulationInstruction {
-                    program_index: 0,
-                    fee: 474845,
-                    program_input: FibonacciSplitInput::FiatShamir(Box::new(
-                        fiat_shamir_hints.clone(),
-                    )),
-                })
+            let program_input = if old_state.pc == 0 {
+                FibonacciSplitInput::FiatShamir(
+                    public_claim,
+                    WitnessHint::from_hint(&fiat_shamir_hints),
+                )
             } else if old_state.pc == 1 {
-                total_fee.borrow_mut().add_assign(325136);
-                Some(SimulationInstruction {
-                    program_index: 1,
-                    fee: 325136,
-                    program_input: FibonacciSplitInput::Prepare(
-                        old_state.stack.clone(),
-                        prepare_hints.clone(),
-                    ),
-                })
-            } else if old_state.pc >= 2 && old_state.pc <= 9 {
-                total_fee.borrow_mut().add_assign(591542);
-                let i = old_state.pc - 2;
-                Some(SimulationInstruction {
-                    program_index: old_state.pc,
-                    fee: 591542,
-                    program_input: FibonacciSplitInput::PerQuery(
-                        old_state.stack.clone(),
-                        per_query_quotients_hints[i].clone(),
-                        per_query_fold_hints[i].clone(),
-                    ),
-                })
+                FibonacciSplitInput::Prepare(
+                    old_state.stack.clone(),
+                    WitnessHint::from_hint(&prepare_hints),
+                )
+            } else if old_state.pc < FibonacciSplitProgram::<P>::reset_index() {
+                let queries = FibonacciSplitProgram::<P>::queries_of_step(
+                    old_state.pc - FibonacciSplitProgram::<P>::query_step_index(0),
+                );
+                FibonacciSplitInput::PerQuery(
+                    old_state.stack.clone(),
+                    per_query_quotients_hints[queries.clone()]
+                        .iter()
+                        .map(WitnessHint::from_hint)
+                        .collect(),
+                    per_query_fold_hints[queries]
+                        .iter()
+                        .map(WitnessHint::from_hint)
+                        .collect(),
+                )
             } else {
                 unimplemented!()
-            }
+            };
+
+            let fee = fee_of_step::<FibonacciSplitProgram<P>>(
+                old_state.pc,
+                old_state,
+                &program_input,
+                FEE_RATE,
+            );
+            total_fee.borrow_mut().add_assign(fee);
+            Some(SimulationInstruction {
+                program_index: old_state.pc,
+                fee: fee as usize,
+                program_input,
+            })
         };
 
         const TIMES: usize = 10;
 
-        simulation_test::<FibonacciSplitProgram>(TIMES * 10, &mut test_generator);
+        simulation_test::<FibonacciSplitProgram<P>>(
+            TIMES * FibonacciSplitProgram::<P>::num_steps(),
+            &mut test_generator,
+        );
 
         println!(
-            "Doing {} Fibonacci STARK verification takes {} BTC (with a rate 7 sat/vBytes)",
+            "Doing {} Fibonacci STARK verification takes {} sats in fees (at {} sat/vB)",
             TIMES,
-            *total_fee.borrow() as f64 / 1000.0 / 1000.0 / 100.0
+            *total_fee.borrow(),
+            FEE_RATE
         );
+    }
 
-        *reset.borrow_mut() = true;
-        simulation_test::<FibonacciSplitProgram>(TIMES * 5, &mut test_generator);
-        println!(
-            "Testing reset of the script with a probability of 0.5 every 5 steps. {} resets have happened during {} steps.",
-            reset_times,
-            TIMES * 5,
+    struct OperatorParameters;
+
+    impl FibonacciSplitParameters for OperatorParameters {
+        const CACHE_NAME: &'static str = "FIBONACCI-OPERATOR";
+        const LOG_SIZE: u32 = FIB_LOG_SIZE;
+        const CLAIM_MODE: FibonacciClaimMode = FibonacciClaimMode::Constant(FIB_CLAIM);
+        const CONFIG: VerifierConfig = VerifierConfig::DEFAULT;
+        // the key of the secret key [1u8; 32]
+        const OPERATOR_PUBLIC_KEY: [u8; 32] = [
+            0x1b, 0x84, 0xc5, 0x56, 0x7b, 0x12, 0x64, 0x40, 0x99, 0x5d, 0x3e, 0xd5, 0xaa, 0xba,
+            0x05, 0x65, 0xd7, 0x1e, 0x18, 0x34, 0x60, 0x48, 0x19, 0xff, 0x9c, 0x17, 0xf5, 0xe9,
+            0xd5, 0xdd, 0x07, 0x8f,
+        ];
+    }
+
+    #[test]
+    fn test_reset_needs_operator() {
+        type Program = FibonacciSplitProgram<OperatorParameters>;
+
+        let hints = default_hints();
+        let secp = Secp256k1::new();
+        let operator = Keypair::from_seckey_slice(&secp, &[1u8; 32]).unwrap();
+        let griefer = Keypair::from_seckey_slice(&secp, &[2u8; 32]).unwrap();
+        assert_eq!(
+            operator.x_only_public_key().0.serialize(),
+            OperatorParameters::OPERATOR_PUBLIC_KEY
         );
+
+        let initial_balance = 10000000;
+        let funding_tx = funding_tx::<Program>(initial_balance);
+
+        // the covenant input of the transaction that follows the given one
+        let next_info = |old_balance: u64, tx: &Transaction, randomizer: u32| CovenantInput {
+            old_randomizer: randomizer,
+            old_balance,
+            old_txid: tx.compute_txid(),
+            input_outpoint1: tx.input[0].previous_output,
+            input_outpoint2: None,
+            optional_deposit_input: None,
+            new_balance: old_balance - 100000 - DUST_AMOUNT,
+        };
+
+        let mut info = next_info(initial_balance, &funding_tx, 12);
+        let mut state = Program::new();
+        let mut txs = vec![];
+        for _ in 0..3 {
+            let input = Program::input_for_state(&state, None, &hints);
+            let new_state = Program::run(state.pc, &state, &input).unwrap();
+            let (tx_template, randomizer) =
+                get_tx::<Program>(&info, state.pc, &state, &new_state, &input);
+
+            info = next_info(info.new_balance, &tx_template.tx, randomizer);
+            state = new_state;
+            txs.push(tx_template.tx);
+        }
+        validate_chain::<Program>(&funding_tx, &txs, &HashMap::new()).unwrap();
+
+        // the reset only takes the signature of the operator
+        let input = Program::input_for_state(&state, None, &hints);
+        assert!(Program::run(Program::reset_index(), &state, &input).is_err());
+
+        let reset = |info: &CovenantInput, state: &FibonacciSplitState| {
+            get_tx::<Program>(
+                info,
+                Program::reset_index(),
+                state,
+                &Program::new(),
+                &Program::unsigned_reset_input(),
+            )
+        };
+        let validate_reset = |txs: &[Transaction], reset_tx: &Transaction| {
+            let mut txs = txs.to_vec();
+            txs.push(reset_tx.clone());
+            validate_chain::<Program>(&funding_tx, &txs, &HashMap::new())
+        };
+        let rejected = |result: Result<(), ChainError>, tx: usize| match result {
+            Err(ChainError::ScriptFailure { tx: index, .. }) => index == tx,
+            _ => false,
+        };
+
+        // a reset in the middle of a verification without the signature of the operator
+        let (unsigned_reset, _) = reset(&info, &state);
+        assert!(rejected(validate_reset(&txs, &unsigned_reset.tx), 3));
+
+        let (mut griefer_reset, _) = reset(&info, &state);
+        Program::sign_reset(&mut griefer_reset, &griefer);
+        assert!(rejected(validate_reset(&txs, &griefer_reset.tx), 3));
+
+        // the operator can reset the program, which then starts over
+        let (mut operator_reset, randomizer) = reset(&info, &state);
+        let txid = operator_reset.tx.compute_txid();
+        Program::sign_reset(&mut operator_reset, &operator);
+        assert_eq!(operator_reset.tx.compute_txid(), txid);
+        validate_reset(&txs, &operator_reset.tx).unwrap();
+
+        info = next_info(info.new_balance, &operator_reset.tx, randomizer);
+        state = Program::new();
+        txs.push(operator_reset.tx.clone());
+
+        let input = Program::input_for_state(&state, None, &hints);
+        let new_state = Program::run(state.pc, &state, &input).unwrap();
+        let (tx_template, randomizer) =
+            get_tx::<Program>(&info, state.pc, &state, &new_state, &input);
+        info = next_info(info.new_balance, &tx_template.tx, randomizer);
+        state = new_state;
+        txs.push(tx_template.tx);
+        validate_chain::<Program>(&funding_tx, &txs, &HashMap::new()).unwrap();
+
+        // the signature of the operator is bound to the transaction that it resets
+        let (mut replayed_reset, _) = reset(&info, &state);
+        let signature = match Program::decode_tx(&operator_reset.tx).unwrap().input {
+            FibonacciSplitInput::Reset(signature) => signature,
+            input => panic!("the reset has the input {:?}", input),
+        };
+        let mut witness = replayed_reset.tx.input[0].witness.to_vec();
+        witness[Program::reset_signature_index(&replayed_reset.tx)] = signature;
+        replayed_reset.tx.input[0].witness = Witness::from_slice(&witness);
+        assert!(rejected(validate_reset(&txs, &replayed_reset.tx), 5));
+    }
+
+    #[test]
+    fn test_stack_limits() {
+        type Program = FibonacciSplitProgram<DefaultFibonacciSplitParameters>;
+
+        let hints = default_hints();
+        let usages = stack_usage_of_steps::<Program>(Program::num_steps(), |state| {
+            (state.pc, Program::input_for_state(state, None, &hints))
+        })
+        .unwrap();
+        println!("{}", check_stack_usage(&usages).unwrap());
+    }
+
+    #[test]
+    fn test_split_tampered_hints() {
+        type P = DefaultFibonacciSplitParameters;
+        type Program = FibonacciSplitProgram<P>;
+
+        let (hints, other) = hints_for_tampering(P::LOG_SIZE, P::CONFIG);
+        let scripts = Program::get_all_scripts();
+
+        // run the script of a step, with the old and new states as the output of the common prefix
+        let run = |id: usize,
+                   old_state: &FibonacciSplitState,
+                   new_state: &FibonacciSplitState,
+                   input: FibonacciSplitInput| {
+            let script = script! {
+                { old_state.pc }
+                { old_state.stack_hash.clone() }
+                { new_state.pc }
+                { new_state.stack_hash.clone() }
+                { scripts[&id].clone() }
+            };
+            let witness = convert_to_witness(Script::from(input)).unwrap();
+            execute_script_with_witness_unlimited_stack(script, witness).success
+        };
+
+        let fiat_shamir_input = |hints: &VerifierHints| {
+            FibonacciSplitInput::FiatShamir(None, WitnessHint::from_hint(&hints.fiat_shamir_hints))
+        };
+        let state_0 = Program::new();
+        let state_1 = Program::run(0, &state_0, &fiat_shamir_input(&hints)).unwrap();
+
+        let prepare_input = |stack: &[Vec<u8>], hints: &VerifierHints| {
+            FibonacciSplitInput::Prepare(
+                stack.to_vec(),
+                WitnessHint::from_hint(&hints.prepare_hints),
+            )
+        };
+        let state_2 = Program::run(1, &state_1, &prepare_input(&state_1.stack, &hints)).unwrap();
+
+        let per_query_input = |stack: &[Vec<u8>], hints: &VerifierHints, query: usize| {
+            FibonacciSplitInput::PerQuery(
+                stack.to_vec(),
+                vec![WitnessHint::from_hint(
+                    &hints.per_query_quotients_hints[query],
+                )],
+                vec![WitnessHint::from_hint(&hints.per_query_fold_hints[query])],
+            )
+        };
+        let query_states = |query: usize| {
+            let old_state = FibonacciSplitState {
+                pc: Program::query_step_index(query),
+                ..state_2.clone()
+            };
+            let new_state = Program::run(
+                Program::query_step_index(query),
+                &old_state,
+                &per_query_input(&state_2.stack, &hints, query),
+            )
+            .unwrap();
+            (old_state, new_state)
+        };
+
+        let run_step = |step: TamperedStep, hints: &VerifierHints| match step {
+            TamperedStep::FiatShamir => run(0, &state_0, &state_1, fiat_shamir_input(hints)),
+            TamperedStep::Prepare => {
+                run(1, &state_1, &state_2, prepare_input(&state_1.stack, hints))
+            }
+            TamperedStep::PerQuery(query) => {
+                let (old_state, new_state) = query_states(query);
+                run(
+                    Program::query_step_index(query),
+                    &old_state,
+                    &new_state,
+                    per_query_input(&state_2.stack, hints, query),
+                )
+            }
+        };
+
+        let n_queries = P::CONFIG.n_queries;
+        for step in [
+            TamperedStep::FiatShamir,
+            TamperedStep::Prepare,
+            TamperedStep::PerQuery(0),
+            TamperedStep::PerQuery(n_queries - 1),
+        ] {
+            assert!(
+                run_step(step, &hints),
+                "the honest {:?} step is rejected",
+                step
+            );
+        }
+
+        for query in [0, n_queries - 1] {
+            for tampered in tampered_hints(&hints, &other, query)
+                .into_iter()
+                .filter(|tampered| query == 0 || tampered.step == TamperedStep::PerQuery(query))
+            {
+                assert!(
+                    !run_step(tampered.step, &tampered.hints),
+                    "the {:?} step accepts a tampered {}",
+                    tampered.step,
+                    tampered.name
+                );
+            }
+        }
+
+        // the stack carried from the previous step is bound by its hash
+        let mut tampered_stack = state_1.stack.clone();
+        tampered_stack[0].push(1);
+        assert!(!run(
+            1,
+            &state_1,
+            &state_2,
+            prepare_input(&tampered_stack, &hints)
+        ));
+
+        let mut tampered_stack = state_2.stack.clone();
+        tampered_stack[0].push(1);
+        let (old_state, new_state) = query_states(0);
+        assert!(!run(
+            Program::query_step_index(0),
+            &old_state,
+            &new_state,
+            per_query_input(&tampered_stack, &hints, 0)
+        ));
     }
 }
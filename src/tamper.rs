@@ -0,0 +1,237 @@
+use crate::fiat_shamir::compute_fiat_shamir_hints;
+use crate::fold::compute_fold_hints;
+use crate::prepare::compute_prepare_hints;
+use crate::quotients::compute_quotients_hints;
+use crate::{channel_for_claim, fibonacci_claim, VerifierConfig, VerifierHints};
+use num_traits::One;
+use stwo_prover::core::channel::Sha256Channel;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::QM31;
+use stwo_prover::core::vcs::sha256_hash::Sha256Hash;
+use stwo_prover::core::vcs::sha256_merkle::Sha256MerkleChannel;
+use stwo_prover::examples::fibonacci::Fibonacci;
+use stwo_prover::trace_generation::commit_and_prove;
+
+/// The step of the verifier that consumes a tampered hint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TamperedStep {
+    FiatShamir,
+    Prepare,
+    PerQuery(usize),
+}
+
+/// Hints where exactly one field is tampered.
+pub(crate) struct TamperedHints {
+    pub(crate) name: String,
+    pub(crate) step: TamperedStep,
+    pub(crate) hints: VerifierHints,
+}
+
+fn hints_with_channel(
+    log_size: u32,
+    config: VerifierConfig,
+    new_channel: impl Fn() -> Sha256Channel,
+) -> VerifierHints {
+    let fib = Fibonacci::new(log_size, fibonacci_claim(log_size));
+
+    let trace = fib.get_trace();
+    let proof = commit_and_prove::<_, Sha256MerkleChannel>(
+        &fib.air,
+        &mut new_channel(),
+        vec![trace],
+        config.pcs_config(),
+    )
+    .unwrap();
+
+    let (fiat_shamir_output, fiat_shamir_hints) =
+        compute_fiat_shamir_hints(proof.clone(), &mut new_channel(), &fib.air, config).unwrap();
+    let (prepare_output, prepare_hints) =
+        compute_prepare_hints(&fiat_shamir_output, &proof).unwrap();
+    let (quotients_output, per_query_quotients_hints) =
+        compute_quotients_hints(&fiat_shamir_output, &prepare_output).unwrap();
+    let per_query_fold_hints = compute_fold_hints(
+        &proof.commitment_scheme_proof.fri_proof,
+        &fiat_shamir_output,
+        &prepare_output,
+        &quotients_output,
+    )
+    .unwrap();
+
+    VerifierHints {
+        fiat_shamir_hints,
+        prepare_hints,
+        per_query_quotients_hints,
+        per_query_fold_hints,
+    }
+}
+
+/// Compute the honest hints for the Fibonacci claim, together with the hints of a proof for the
+/// same statement under an unseeded channel.
+///
+/// The fields of the latter are well-formed but do not belong to the former, which makes them
+/// good replacements for tampering fields whose types cannot be modified directly.
+pub(crate) fn hints_for_tampering(
+    log_size: u32,
+    config: VerifierConfig,
+) -> (VerifierHints, VerifierHints) {
+    let claim = fibonacci_claim(log_size);
+    (
+        hints_with_channel(log_size, config, || channel_for_claim(claim)),
+        hints_with_channel(log_size, config, Sha256Channel::default),
+    )
+}
+
+fn tamper_hash(hash: &Sha256Hash) -> Sha256Hash {
+    let mut bytes = hash.as_ref().to_vec();
+    bytes[0] ^= 1;
+    Sha256Hash::from(bytes.as_slice())
+}
+
+/// Tamper each field of the hints in turn, taking the per-query hints from the given query.
+pub(crate) fn tampered_hints(
+    hints: &VerifierHints,
+    other: &VerifierHints,
+    query: usize,
+) -> Vec<TamperedHints> {
+    let mut res = vec![];
+
+    let mut tamper = |name: &str, step: TamperedStep, f: &dyn Fn(&mut VerifierHints)| {
+        let mut tampered = hints.clone();
+        f(&mut tampered);
+        res.push(TamperedHints {
+            name: name.to_string(),
+            step,
+            hints: tampered,
+        });
+    };
+
+    // the queries of both proofs are sorted, so taking the other proof from the opposite end makes
+    // sure that it opens a different position, including in the trees shared by both proofs
+    let n_queries = hints.per_query_quotients_hints.len();
+    let other_query = n_queries - 1 - query;
+
+    let fs = TamperedStep::FiatShamir;
+    let other_fs = &other.fiat_shamir_hints;
+
+    tamper("trace commitment", fs, &|h| {
+        h.fiat_shamir_hints.commitments[0] = tamper_hash(&h.fiat_shamir_hints.commitments[0])
+    });
+    tamper("composition commitment", fs, &|h| {
+        h.fiat_shamir_hints.commitments[1] = tamper_hash(&h.fiat_shamir_hints.commitments[1])
+    });
+    tamper("random_coeff hint", fs, &|h| {
+        h.fiat_shamir_hints.random_coeff_hint = other_fs.random_coeff_hint.clone()
+    });
+    tamper("OODS hint", fs, &|h| {
+        h.fiat_shamir_hints.oods_hint = other_fs.oods_hint.clone()
+    });
+    tamper("trace OODS value", fs, &|h| {
+        h.fiat_shamir_hints.trace_oods_values[0] += QM31::one()
+    });
+    tamper("composition OODS value", fs, &|h| {
+        h.fiat_shamir_hints.composition_oods_values[0] += QM31::one()
+    });
+    tamper("composition hint", fs, &|h| {
+        h.fiat_shamir_hints.composition_hint = other_fs.composition_hint.clone()
+    });
+    tamper("second random_coeff hint", fs, &|h| {
+        h.fiat_shamir_hints.random_coeff_hint2 = other_fs.random_coeff_hint2.clone()
+    });
+    tamper("circle_poly_alpha hint", fs, &|h| {
+        h.fiat_shamir_hints.circle_poly_alpha_hint = other_fs.circle_poly_alpha_hint.clone()
+    });
+    tamper("FRI commitment", fs, &|h| {
+        let commitment = &mut h.fiat_shamir_hints.fri_commitment_and_folding_hints[0].0;
+        *commitment = tamper_hash(commitment);
+    });
+    tamper("folding alpha hint", fs, &|h| {
+        h.fiat_shamir_hints.fri_commitment_and_folding_hints[0].1 =
+            other_fs.fri_commitment_and_folding_hints[0].1.clone()
+    });
+    tamper("last layer", fs, &|h| {
+        h.fiat_shamir_hints.last_layer += QM31::one()
+    });
+    tamper("PoW hint", fs, &|h| {
+        h.fiat_shamir_hints.pow_hint = other_fs.pow_hint.clone()
+    });
+    tamper("queries hints", fs, &|h| {
+        h.fiat_shamir_hints.queries_hints = other_fs.queries_hints.clone()
+    });
+    tamper("trace queried value", fs, &|h| {
+        h.fiat_shamir_hints.merkle_proofs_traces[0].left[0] += M31::one()
+    });
+    tamper("trace Merkle siblings", fs, &|h| {
+        let proof = &mut h.fiat_shamir_hints.merkle_proofs_traces[0];
+        let mut other_proof = other_fs.merkle_proofs_traces[n_queries - 1].clone();
+        other_proof.left = proof.left.clone();
+        other_proof.right = proof.right.clone();
+        *proof = other_proof;
+    });
+    tamper("composition queried value", fs, &|h| {
+        h.fiat_shamir_hints.merkle_proofs_compositions[0].right[0] += M31::one()
+    });
+    tamper("composition Merkle siblings", fs, &|h| {
+        let proof = &mut h.fiat_shamir_hints.merkle_proofs_compositions[0];
+        let mut other_proof = other_fs.merkle_proofs_compositions[n_queries - 1].clone();
+        other_proof.left = proof.left.clone();
+        other_proof.right = proof.right.clone();
+        *proof = other_proof;
+    });
+
+    let prepare = TamperedStep::Prepare;
+    let other_prepare = &other.prepare_hints;
+
+    for i in [0, 3] {
+        tamper(&format!("column line coeffs hint {}", i), prepare, &|h| {
+            h.prepare_hints.column_line_coeffs_hints[i] =
+                other_prepare.column_line_coeffs_hints[i].clone()
+        });
+        tamper(
+            &format!("prepared pair vanishing hint {}", i),
+            prepare,
+            &|h| {
+                h.prepare_hints.prepared_pair_vanishing_hints[i] =
+                    other_prepare.prepared_pair_vanishing_hints[i].clone()
+            },
+        );
+    }
+
+    let per_query = TamperedStep::PerQuery(query);
+    let other_quotients = &other.per_query_quotients_hints[other_query];
+    let other_fold = &other.per_query_fold_hints[other_query];
+
+    tamper("precomputed Merkle proof", per_query, &|h| {
+        h.per_query_quotients_hints[query].precomputed_merkle_proofs[0] =
+            other_quotients.precomputed_merkle_proofs[0].clone()
+    });
+    for i in [0, 3] {
+        tamper(
+            &format!("denominator inverse hint {}", i),
+            per_query,
+            &|h| {
+                h.per_query_quotients_hints[query].denominator_inverse_hints[i] =
+                    other_quotients.denominator_inverse_hints[i].clone()
+            },
+        );
+    }
+
+    let num_layers = hints.per_query_fold_hints[query].twin_proofs.len();
+    for layer in [0, num_layers - 1] {
+        tamper(&format!("FRI value at layer {}", layer), per_query, &|h| {
+            h.per_query_fold_hints[query].twin_proofs[layer].left[0] += M31::one()
+        });
+        tamper(
+            &format!("FRI Merkle siblings at layer {}", layer),
+            per_query,
+            &|h| {
+                let proof = &mut h.per_query_fold_hints[query].twin_proofs[layer];
+                let mut other_proof = other_fold.twin_proofs[layer].clone();
+                other_proof.left = proof.left.clone();
+                other_proof.right = proof.right.clone();
+                *proof = other_proof;
+            },
+        );
+    }
+
+    res
+}
@@ -0,0 +1,697 @@
+use crate::fiat_shamir::{check_num_queried_values, check_proof_layout};
+use crate::fold::fill_fri_siblings;
+use crate::{channel_for_claim, sampled_values_to_mask, VerifierConfig};
+use bitcoin_circle_stark::constraints::ColumnLineCoeffs;
+use bitcoin_circle_stark::merkle_tree::MerkleTreeTwinProof;
+use bitcoin_circle_stark::precomputed_merkle_tree::PrecomputedMerkleTree;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use stwo_prover::core::air::accumulation::PointEvaluationAccumulator;
+use stwo_prover::core::air::ComponentProvers;
+use stwo_prover::core::air::{AirProver, Component};
+use stwo_prover::core::backend::cpu::quotients::denominator_inverses;
+use stwo_prover::core::channel::{Channel, Sha256Channel};
+use stwo_prover::core::circle::CirclePoint;
+use stwo_prover::core::fft::ibutterfly;
+use stwo_prover::core::fields::cm31::CM31;
+use stwo_prover::core::fields::m31::M31;
+use stwo_prover::core::fields::qm31::{SecureField, QM31};
+use stwo_prover::core::fields::secure_column::SECURE_EXTENSION_DEGREE;
+use stwo_prover::core::fields::FieldExpOps;
+use stwo_prover::core::fri::get_opening_positions;
+use stwo_prover::core::pcs::quotients::{ColumnSampleBatch, PointSample};
+use stwo_prover::core::pcs::{CommitmentSchemeVerifier, TreeVec};
+use stwo_prover::core::poly::circle::CanonicCoset;
+use stwo_prover::core::prover::StarkProof;
+use stwo_prover::core::queries::Queries;
+use stwo_prover::core::vcs::sha256_hash::Sha256Hasher;
+use stwo_prover::core::vcs::sha256_merkle::{Sha256MerkleChannel, Sha256MerkleHasher};
+use stwo_prover::core::{InteractionElements, LookupValues};
+use stwo_prover::examples::fibonacci::air::FibonacciAir;
+use stwo_prover::trace_generation::AirTraceGenerator;
+
+/// A step of the verifier, in the order in which the split program runs them.
+///
+/// The prepare step only computes the column line coefficients and does not check anything, so a
+/// proof is never rejected there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifierStep {
+    /// The Fiat-Shamir step, including the Merkle proofs of the trace and the composition.
+    FiatShamir,
+    /// The quotients and the folding for the query with the given index.
+    PerQuery(usize),
+}
+
+/// A check of the script that a proof can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifierCheck {
+    /// The proof does not have the shape that the script is written for.
+    UnsupportedProof(String),
+    /// The composition polynomial does not match the constraints at the OODS point.
+    OodsValue,
+    /// The proof of work does not have enough trailing zeros.
+    ProofOfWork,
+    /// A Merkle twin proof of the trace (tree 0) or the composition (tree 1) fails to verify.
+    MerkleTwinProof {
+        /// The index of the tree.
+        tree: usize,
+    },
+    /// A FRI layer does not open exactly the siblings of the queries.
+    FriDecommitment {
+        /// The index of the FRI layer.
+        layer: usize,
+    },
+    /// A Merkle twin proof of a FRI layer fails to verify.
+    FriMerkleProof {
+        /// The index of the FRI layer.
+        layer: usize,
+    },
+    /// The result of folding does not match the last layer.
+    LastLayer,
+}
+
+/// The verdict of the native verifier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The proof passes all the checks of the script.
+    Accept,
+    /// The proof fails a check of the script.
+    Reject {
+        /// The step where the proof is rejected.
+        step: VerifierStep,
+        /// The check that fails.
+        check: VerifierCheck,
+    },
+}
+
+impl Verdict {
+    /// Whether the proof is accepted.
+    pub fn is_accept(&self) -> bool {
+        matches!(self, Verdict::Accept)
+    }
+}
+
+impl Display for VerifierStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifierStep::FiatShamir => write!(f, "Fiat-Shamir"),
+            VerifierStep::PerQuery(query) => write!(f, "query {}", query),
+        }
+    }
+}
+
+impl Display for VerifierCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifierCheck::UnsupportedProof(reason) => write!(f, "unsupported proof: {}", reason),
+            VerifierCheck::OodsValue => write!(f, "composition does not match at the OODS point"),
+            VerifierCheck::ProofOfWork => write!(f, "proof of work verification failed"),
+            VerifierCheck::MerkleTwinProof { tree } => {
+                write!(f, "invalid Merkle twin proof for tree {}", tree)
+            }
+            VerifierCheck::FriDecommitment { layer } => {
+                write!(f, "invalid FRI decommitment at layer {}", layer)
+            }
+            VerifierCheck::FriMerkleProof { layer } => {
+                write!(f, "invalid FRI Merkle proof at layer {}", layer)
+            }
+            VerifierCheck::LastLayer => write!(f, "last layer mismatch"),
+        }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Accept => write!(f, "accepted"),
+            Verdict::Reject { step, check } => write!(f, "rejected at {}: {}", step, check),
+        }
+    }
+}
+
+/// Everything that the Fiat-Shamir step leaves on the stack for the later steps.
+struct Transcript {
+    log_domain_size: u32,
+    sampled_points: TreeVec<Vec<Vec<CirclePoint<QM31>>>>,
+    random_coeff: QM31,
+    circle_poly_alpha: QM31,
+    folding_alphas: Vec<QM31>,
+    last_layer: QM31,
+    queries: Queries,
+    queries_parents: Vec<usize>,
+    queried_values_left: Vec<Vec<M31>>,
+    queried_values_right: Vec<Vec<M31>>,
+}
+
+/// Verify a proof natively, performing the same checks as the script in the same order, so that
+/// a proof can be checked before spending any fees on-chain.
+///
+/// Unlike the hint generators, this does not rely on the checks of stwo's verifier.
+pub fn verify(
+    proof: &StarkProof<Sha256MerkleHasher>,
+    air: &FibonacciAir,
+    config: VerifierConfig,
+) -> Verdict {
+    let channel = &mut channel_for_claim(air.component.claim);
+
+    let transcript = match fiat_shamir_step(proof, channel, air, config) {
+        Ok(transcript) => transcript,
+        Err(check) => {
+            return Verdict::Reject {
+                step: VerifierStep::FiatShamir,
+                check,
+            }
+        }
+    };
+
+    let (samples, column_line_coeffs) = prepare_step(proof, &transcript);
+
+    let fold_results = quotients_step(&transcript, &samples, &column_line_coeffs);
+
+    match fold_step(proof, &transcript, &fold_results) {
+        Ok(()) => Verdict::Accept,
+        Err((query, check)) => Verdict::Reject {
+            step: VerifierStep::PerQuery(query),
+            check,
+        },
+    }
+}
+
+fn fiat_shamir_step(
+    proof: &StarkProof<Sha256MerkleHasher>,
+    channel: &mut Sha256Channel,
+    air: &FibonacciAir,
+    config: VerifierConfig,
+) -> Result<Transcript, VerifierCheck> {
+    let unsupported = |reason: &str| VerifierCheck::UnsupportedProof(reason.to_string());
+
+    config
+        .check()
+        .map_err(|err| unsupported(&format!("invalid configuration: {}", err)))?;
+    check_proof_layout(proof).map_err(|err| unsupported(&err.to_string()))?;
+
+    let fri_proof = &proof.commitment_scheme_proof.fri_proof;
+    if fri_proof.inner_layers.len() != air.component.log_size as usize {
+        return Err(unsupported(
+            "expected one FRI layer per bit of the trace size",
+        ));
+    }
+    if fri_proof.last_layer_poly.len() != 1 {
+        return Err(unsupported("expected a last layer of one element"));
+    }
+
+    // commit to the trace, draw the random coefficient, and commit to the composition
+    let mut commitment_scheme: CommitmentSchemeVerifier<Sha256MerkleChannel> =
+        CommitmentSchemeVerifier::new(config.pcs_config());
+
+    let air_prover = air.to_air_prover();
+    let components = ComponentProvers(air_prover.component_provers());
+
+    let column_log_sizes = components.components().column_log_sizes();
+    commitment_scheme.commit(proof.commitments[0], &column_log_sizes[0], channel);
+
+    channel.mix_felts(
+        &proof
+            .lookup_values
+            .0
+            .values()
+            .map(|v| SecureField::from(*v))
+            .collect_vec(),
+    );
+    let random_coeff = channel.draw_felt();
+
+    commitment_scheme.commit(
+        proof.commitments[1],
+        &[air.composition_log_degree_bound(); 4],
+        channel,
+    );
+
+    // draw the OODS point and check the composition against the constraints
+    let oods_point = CirclePoint::<SecureField>::get_random_point(channel);
+
+    let mut sampled_points = components.components().mask_points(oods_point);
+    sampled_points.push(vec![vec![oods_point]; SECURE_EXTENSION_DEGREE]);
+
+    let (trace_oods_values, composition_oods_value) =
+        sampled_values_to_mask(air, proof.commitment_scheme_proof.sampled_values.clone())
+            .map_err(|_| unsupported("unexpected layout of sampled values"))?;
+
+    let mut evaluation_accumulator = PointEvaluationAccumulator::new(random_coeff);
+    air.component.evaluate_constraint_quotients_at_point(
+        oods_point,
+        &trace_oods_values[0],
+        &mut evaluation_accumulator,
+        &InteractionElements::default(),
+        &LookupValues::default(),
+    );
+    if composition_oods_value != evaluation_accumulator.finalize() {
+        return Err(VerifierCheck::OodsValue);
+    }
+
+    // draw the randomness for the quotients and the FRI folding
+    channel.mix_felts(
+        &proof
+            .commitment_scheme_proof
+            .sampled_values
+            .clone()
+            .flatten_cols(),
+    );
+    let random_coeff = channel.draw_felt();
+    let circle_poly_alpha = channel.draw_felt();
+
+    let mut folding_alphas = vec![];
+    for layer in fri_proof.inner_layers.iter() {
+        channel.update_digest(Sha256Hasher::concat_and_hash(
+            &layer.commitment,
+            &channel.digest(),
+        ));
+        folding_alphas.push(channel.draw_felt());
+    }
+
+    let last_layer = fri_proof.last_layer_poly[0];
+    channel.mix_felts(&fri_proof.last_layer_poly);
+
+    // check the proof of work
+    channel.mix_nonce(proof.commitment_scheme_proof.proof_of_work);
+    if channel.trailing_zeros() < config.pow_bits {
+        return Err(VerifierCheck::ProofOfWork);
+    }
+
+    // sample the queries
+    let log_domain_sizes = commitment_scheme
+        .column_log_sizes()
+        .flatten()
+        .into_iter()
+        .dedup()
+        .collect_vec();
+    if log_domain_sizes.len() != 1 {
+        return Err(unsupported(
+            "expected all the columns to have the same size",
+        ));
+    }
+    let log_domain_size = log_domain_sizes[0];
+
+    let queries = Queries::generate(channel, log_domain_size, config.n_queries);
+
+    let queries_parents = get_opening_positions(&queries, &[log_domain_size])
+        .remove(&log_domain_size)
+        .unwrap()
+        .iter()
+        .map(|subdomain| subdomain.coset_index)
+        .collect_vec();
+    if queries_parents.len() != config.n_queries {
+        return Err(unsupported("expected the queries to open distinct twins"));
+    }
+
+    let queried_values = &proof.commitment_scheme_proof.queried_values.0;
+    check_num_queried_values(queried_values, queries_parents.len())
+        .map_err(|err| unsupported(&err.to_string()))?;
+
+    // check the Merkle twin proofs of the trace and the composition
+    let mut twin_proofs = vec![];
+    for (tree, (values, decommitment)) in queried_values
+        .iter()
+        .zip(proof.commitment_scheme_proof.decommitments.iter())
+        .enumerate()
+    {
+        let proofs = MerkleTreeTwinProof::from_stwo_proof(
+            log_domain_size as usize,
+            &queries_parents,
+            values,
+            decommitment,
+        );
+        for (&query, twin_proof) in queries_parents.iter().zip(proofs.iter()) {
+            if !twin_proof.verify(
+                &proof.commitments[tree],
+                log_domain_size as usize,
+                query << 1,
+            ) {
+                return Err(VerifierCheck::MerkleTwinProof { tree });
+            }
+        }
+        twin_proofs.push(proofs);
+    }
+
+    let mut queried_values_left = vec![];
+    let mut queried_values_right = vec![];
+    for (trace, composition) in twin_proofs[0].iter().zip(twin_proofs[1].iter()) {
+        queried_values_left.push(
+            trace
+                .left
+                .iter()
+                .chain(composition.left.iter())
+                .copied()
+                .collect_vec(),
+        );
+        queried_values_right.push(
+            trace
+                .right
+                .iter()
+                .chain(composition.right.iter())
+                .copied()
+                .collect_vec(),
+        );
+    }
+
+    Ok(Transcript {
+        log_domain_size,
+        sampled_points,
+        random_coeff,
+        circle_poly_alpha,
+        folding_alphas,
+        last_layer,
+        queries,
+        queries_parents,
+        queried_values_left,
+        queried_values_right,
+    })
+}
+
+fn prepare_step(
+    proof: &StarkProof<Sha256MerkleHasher>,
+    transcript: &Transcript,
+) -> (Vec<Vec<PointSample>>, Vec<ColumnLineCoeffs>) {
+    let samples = transcript
+        .sampled_points
+        .clone()
+        .zip_cols(&proof.commitment_scheme_proof.sampled_values)
+        .map_cols(|(sampled_points, sampled_values)| {
+            sampled_points
+                .into_iter()
+                .zip(sampled_values.iter())
+                .map(|(point, &value)| PointSample { point, value })
+                .collect_vec()
+        })
+        .flatten();
+
+    // the trace is sampled at three points, and the four composition columns at the OODS point
+    let column_line_coeffs = vec![
+        ColumnLineCoeffs::from_values_and_point(&[samples[0][0].value], samples[0][0].point),
+        ColumnLineCoeffs::from_values_and_point(&[samples[0][1].value], samples[0][1].point),
+        ColumnLineCoeffs::from_values_and_point(&[samples[0][2].value], samples[0][2].point),
+        ColumnLineCoeffs::from_values_and_point(
+            &[
+                samples[1][0].value,
+                samples[2][0].value,
+                samples[3][0].value,
+                samples[4][0].value,
+            ],
+            samples[1][0].point,
+        ),
+    ];
+
+    (samples, column_line_coeffs)
+}
+
+fn quotients_step(
+    transcript: &Transcript,
+    samples: &[Vec<PointSample>],
+    column_line_coeffs: &[ColumnLineCoeffs],
+) -> Vec<QM31> {
+    let precomputed_merkle_tree =
+        PrecomputedMerkleTree::new((transcript.log_domain_size - 1) as usize);
+
+    let column_sample_batches =
+        ColumnSampleBatch::new_vec(&samples.iter().collect::<Vec<&Vec<PointSample>>>());
+    let commitment_domain = CanonicCoset::new(transcript.log_domain_size).circle_domain();
+    let query_subcircle_domain =
+        get_opening_positions(&transcript.queries, &[transcript.log_domain_size])
+            .remove(&transcript.log_domain_size)
+            .unwrap();
+
+    let random_coeff = transcript.random_coeff;
+
+    let mut fold_results = vec![];
+    for (i, subdomain) in query_subcircle_domain.iter().enumerate() {
+        let inverses: Vec<Vec<CM31>> = denominator_inverses(
+            &column_sample_batches,
+            subdomain.to_circle_domain(&commitment_domain),
+        );
+
+        let point = precomputed_merkle_tree
+            .query(transcript.queries_parents[i] << 1)
+            .circle_point;
+
+        let left = &transcript.queried_values_left[i];
+        let right = &transcript.queried_values_right[i];
+
+        let mut nominators = vec![];
+        for column_line_coeff in column_line_coeffs.iter().take(3) {
+            nominators.push(column_line_coeff.apply_twin(point, &left[..1], &right[..1]));
+        }
+        nominators.push(column_line_coeffs[3].apply_twin(point, &left[1..], &right[1..]));
+
+        // the trace columns are multiplied by coeff^6, coeff^5, coeff^4, and the composition
+        // columns by coeff^3, coeff^2, coeff, 1
+        let eval = |side: usize| {
+            let nominator = |k: usize, j: usize| {
+                if side == 0 {
+                    nominators[k].0[j]
+                } else {
+                    nominators[k].1[j]
+                }
+            };
+
+            random_coeff.pow(6) * QM31::from(nominator(0, 0) * inverses[0][side])
+                + random_coeff.pow(5) * QM31::from(nominator(1, 0) * inverses[1][side])
+                + random_coeff.pow(4) * QM31::from(nominator(2, 0) * inverses[2][side])
+                + (random_coeff.pow(3) * QM31::from(nominator(3, 0))
+                    + random_coeff.pow(2) * QM31::from(nominator(3, 1))
+                    + random_coeff * QM31::from(nominator(3, 2))
+                    + QM31::from(nominator(3, 3)))
+                    * QM31::from(inverses[3][side])
+        };
+
+        let (mut f0_px, mut f1_px) = (eval(0), eval(1));
+        ibutterfly(&mut f0_px, &mut f1_px, point.y.inverse());
+
+        fold_results.push(transcript.circle_poly_alpha * f1_px + f0_px);
+    }
+
+    fold_results
+}
+
+fn fold_step(
+    proof: &StarkProof<Sha256MerkleHasher>,
+    transcript: &Transcript,
+    fold_results: &[QM31],
+) -> Result<(), (usize, VerifierCheck)> {
+    let precomputed_merkle_tree =
+        PrecomputedMerkleTree::new((transcript.log_domain_size - 1) as usize);
+    let fri_proof = &proof.commitment_scheme_proof.fri_proof;
+    let num_fri_steps = fri_proof.inner_layers.len();
+
+    // the twiddle factors for each layer, indexed by the twin
+    let mut twiddles = vec![BTreeMap::<usize, M31>::new(); num_fri_steps];
+    for &queries_parent in transcript.queries_parents.iter() {
+        let query_result = precomputed_merkle_tree.query(queries_parent << 1);
+
+        let mut idx = queries_parent;
+        for (twiddles_mut, &elem) in twiddles
+            .iter_mut()
+            .zip(query_result.twiddles_elements.iter().rev().skip(1))
+        {
+            twiddles_mut.insert(idx >> 1, elem);
+            idx >>= 1;
+        }
+    }
+
+    let mut values = transcript
+        .queries_parents
+        .iter()
+        .copied()
+        .zip(fold_results.iter().copied())
+        .collect::<BTreeMap<usize, QM31>>();
+
+    // the index of the first query that reaches each position of the current layer
+    let mut queries_at = BTreeMap::<usize, usize>::new();
+    for (query, &queries_parent) in transcript.queries_parents.iter().enumerate() {
+        queries_at.entry(queries_parent).or_insert(query);
+    }
+
+    let mut depth = precomputed_merkle_tree.layers.len() - 1;
+
+    for (layer, ((fri_layer_proof, &folding_alpha), layer_twiddles)) in fri_proof
+        .inner_layers
+        .iter()
+        .zip_eq(transcript.folding_alphas.iter())
+        .zip_eq(twiddles.iter())
+        .enumerate()
+    {
+        // fill in the siblings from the decommitment, as the hints do, where extra values are
+        // blamed on the first query
+        fill_fri_siblings(&mut values, &fri_layer_proof.evals_subset).map_err(|position| {
+            let query = match position {
+                Some(position) => queries_at[&position],
+                None => *queries_at.values().min().unwrap(),
+            };
+            (query, VerifierCheck::FriDecommitment { layer })
+        })?;
+
+        // check the Merkle twin proofs of this layer
+        let mut twins_at = BTreeMap::<usize, usize>::new();
+        for (&position, &query) in queries_at.iter() {
+            let first_query = twins_at.entry(position >> 1).or_insert(query);
+            *first_query = (*first_query).min(query);
+        }
+        let twins = twins_at.keys().copied().collect_vec();
+
+        let mut columns = vec![vec![]; 4];
+        for &twin in twins.iter() {
+            for position in [twin << 1, (twin << 1) + 1] {
+                let value = values[&position];
+                columns[0].push(value.0 .0);
+                columns[1].push(value.0 .1);
+                columns[2].push(value.1 .0);
+                columns[3].push(value.1 .1);
+            }
+        }
+
+        let twin_proofs = MerkleTreeTwinProof::from_stwo_proof(
+            depth,
+            &twins,
+            &columns,
+            &fri_layer_proof.decommitment,
+        );
+        for ((&twin, &query), twin_proof) in twins_at.iter().zip(twin_proofs.iter()) {
+            if !twin_proof.verify(&fri_layer_proof.commitment, depth, twin << 1) {
+                return Err((query, VerifierCheck::FriMerkleProof { layer }));
+            }
+        }
+
+        // fold the twins
+        let mut new_values = BTreeMap::new();
+        for &twin in twins.iter() {
+            let (mut f0_px, mut f1_px) = (values[&(twin << 1)], values[&((twin << 1) + 1)]);
+            ibutterfly(&mut f0_px, &mut f1_px, layer_twiddles[&twin]);
+            new_values.insert(twin, folding_alpha * f1_px + f0_px);
+        }
+
+        values = new_values;
+        queries_at = twins_at;
+        depth -= 1;
+    }
+
+    for (i, &queries_parent) in transcript.queries_parents.iter().enumerate() {
+        if values[&(queries_parent >> num_fri_steps)] != transcript.last_layer {
+            return Err((i, VerifierCheck::LastLayer));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::HintError;
+    use crate::proof::test::test_proof;
+    use crate::verifier::{verify, Verdict, VerifierCheck, VerifierStep};
+    use crate::{verify_with_hints, VerifierConfig, FIB_CLAIM, FIB_LOG_SIZE};
+    use num_traits::One;
+    use stwo_prover::core::fields::m31::M31;
+    use stwo_prover::core::fields::qm31::QM31;
+    use stwo_prover::examples::fibonacci::Fibonacci;
+
+    #[test]
+    fn test_native_verifier() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let config = VerifierConfig::default();
+        let proof = test_proof();
+
+        assert_eq!(verify(&proof, &fib.air, config), Verdict::Accept);
+
+        let mut wrong_sample = proof.clone();
+        wrong_sample.commitment_scheme_proof.sampled_values.0[0][0][0] += QM31::one();
+        assert_eq!(
+            verify(&wrong_sample, &fib.air, config),
+            Verdict::Reject {
+                step: VerifierStep::FiatShamir,
+                check: VerifierCheck::OodsValue,
+            }
+        );
+
+        let mut wrong_trace_value = proof.clone();
+        wrong_trace_value.commitment_scheme_proof.queried_values.0[0][0][0] += M31::one();
+        assert_eq!(
+            verify(&wrong_trace_value, &fib.air, config),
+            Verdict::Reject {
+                step: VerifierStep::FiatShamir,
+                check: VerifierCheck::MerkleTwinProof { tree: 0 },
+            }
+        );
+
+        let mut missing_decommitment = proof.clone();
+        missing_decommitment
+            .commitment_scheme_proof
+            .decommitments
+            .0
+            .pop();
+        assert_eq!(
+            verify(&missing_decommitment, &fib.air, config),
+            Verdict::Reject {
+                step: VerifierStep::FiatShamir,
+                check: VerifierCheck::UnsupportedProof(
+                    HintError::UnexpectedDecommitments.to_string()
+                ),
+            }
+        );
+
+        let mut short_queried_values = proof.clone();
+        short_queried_values
+            .commitment_scheme_proof
+            .queried_values
+            .0[1][0]
+            .truncate(1);
+        assert!(matches!(
+            verify(&short_queried_values, &fib.air, config),
+            Verdict::Reject {
+                step: VerifierStep::FiatShamir,
+                check: VerifierCheck::UnsupportedProof(_),
+            }
+        ));
+
+        let mut wrong_fri_value = proof;
+        wrong_fri_value
+            .commitment_scheme_proof
+            .fri_proof
+            .inner_layers[0]
+            .evals_subset[0] += QM31::one();
+        assert!(matches!(
+            verify(&wrong_fri_value, &fib.air, config),
+            Verdict::Reject {
+                step: VerifierStep::PerQuery(_),
+                check: VerifierCheck::FriMerkleProof { layer: 0 },
+            }
+        ));
+    }
+
+    #[test]
+    fn test_native_verifier_agrees_with_hints_on_fri_decommitment() {
+        let fib = Fibonacci::new(FIB_LOG_SIZE, FIB_CLAIM);
+        let config = VerifierConfig::default();
+        let proof = test_proof();
+
+        let mut extra_value = proof.clone();
+        extra_value.commitment_scheme_proof.fri_proof.inner_layers[0]
+            .evals_subset
+            .push(QM31::one());
+
+        let mut missing_value = proof;
+        missing_value.commitment_scheme_proof.fri_proof.inner_layers[0]
+            .evals_subset
+            .pop();
+
+        // the demo only generates the hints of a proof that the native verifier accepts
+        for malformed in [extra_value, missing_value] {
+            assert!(matches!(
+                verify(&malformed, &fib.air, config),
+                Verdict::Reject {
+                    step: VerifierStep::PerQuery(_),
+                    check: VerifierCheck::FriDecommitment { layer: 0 },
+                }
+            ));
+            assert!(verify_with_hints(malformed, &fib.air, config).is_err());
+        }
+    }
+}